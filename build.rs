@@ -21,205 +21,723 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 use std::{
     env, fs,
-    io::{ErrorKind, Write},
+    io::Write,
     path::{Path, PathBuf},
     process::Command,
 };
 
-#[allow(clippy::too_many_lines)]
+#[allow(dead_code)]
+#[path = "src/config/params.rs"]
+mod params;
+
+use params::RandomXConfig;
+
 fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
     let project_dir = Path::new(&out_dir);
     let cargo_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
 
     let repo_dir = PathBuf::from(env::var("RANDOMX_DIR").unwrap_or_else(|_| format!("{}/RandomX", &cargo_dir)));
-    let build_dir = &project_dir.join("randomx_build");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/config/params.rs");
+    println!("cargo:rerun-if-changed={}", repo_dir.display());
+    println!("cargo:rerun-if-env-changed=RANDOMX_DIR");
+
+    let config = select_config();
+    write_active_config(project_dir, &config);
+    for cfg in [
+        "randomx_variant_monero",
+        "randomx_variant_wownero",
+        "randomx_variant_arqma",
+    ] {
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+    }
+    println!("cargo:rustc-check-cfg=cfg(randomx_ffi)");
 
-    env::set_current_dir(Path::new(&repo_dir)).unwrap(); // change current path to repo for dependency build
-    match fs::create_dir_all(build_dir) {
-        Ok(_) => (),
-        Err(e) => match e.kind() {
-            ErrorKind::AlreadyExists => (),
-            _ => panic!("{}", e),
-        },
+    if env::var_os("CARGO_FEATURE_PURE_RUST").is_some() && env::var_os("CARGO_FEATURE_DIFFERENTIAL").is_none() {
+        // The safe Rust implementation in `src/pure` is the only backend.
+        assert!(
+            env::var_os("CARGO_FEATURE_MULTI_VARIANT").is_none() &&
+                env::var_os("CARGO_FEATURE_SYSTEM").is_none() &&
+                env::var_os("RANDOMX_LIB_DIR").is_none(),
+            "the `pure-rust` feature does not link a RandomX library, it cannot be combined with `multi-variant`, \
+             `system` or RANDOMX_LIB_DIR"
+        );
+        return;
     }
-    env::set_current_dir(build_dir).unwrap();
+    println!("cargo:rustc-cfg=randomx_ffi");
 
-    let host = env::var("HOST").unwrap();
-    // println!("host: {}", host);
     let target = env::var("TARGET").unwrap();
-    // println!("target: {}", target);
-    if host.contains("windows") && target.contains("windows-msvc") {
-        let c = Command::new("cmake")
-            .arg("-G")
-            .arg("Visual Studio 16 2019")
-            .arg(repo_dir.to_str().unwrap())
-            .output()
-            .expect("failed to execute CMake");
-        println!("status: {}", c.status);
-        std::io::stdout().write_all(&c.stdout).unwrap();
-        std::io::stderr().write_all(&c.stderr).unwrap();
-        assert!(c.status.success());
-
-        let m = Command::new("cmake")
-            .arg("--build")
-            .arg(".")
-            .arg("--config")
-            .arg("Release")
-            .output()
-            .expect("failed to execute Make");
-        println!("status: {}", m.status);
-        std::io::stdout().write_all(&m.stdout).unwrap();
-        std::io::stderr().write_all(&m.stderr).unwrap();
-        assert!(m.status.success());
-    } else if target.contains("aarch64-unknown-linux-gnu") {
-        let c = Command::new("cmake")
-            .arg("-D")
-            .arg("ARCH=arm64")
-            .arg("-D")
-            .arg("ARCH_ID=aarch64")
-            .arg("-D")
-            .arg("CMAKE_CROSSCOMPILING=true")
-            .arg("-D")
-            .arg("CMAKE_SYSTEM_PROCESSOR=aarch64")
-            .arg("-D")
-            .arg("CMAKE_C_COMPILER=/usr/bin/aarch64-linux-gnu-gcc")
-            .arg("-D")
-            .arg("CMAKE_CXX_COMPILER=/usr/bin/aarch64-linux-gnu-g++")
-            .arg(repo_dir.to_str().unwrap())
-            .output()
-            .expect("failed to execute CMake");
-        println!("status: {}", c.status);
-        std::io::stdout().write_all(&c.stdout).unwrap();
-        std::io::stderr().write_all(&c.stderr).unwrap();
-        assert!(c.status.success());
-
-        let m = Command::new("cmake")
-            .arg("--build")
-            .arg(".")
-            .arg("--config")
-            .arg("Release")
-            .output()
-            .expect("failed to execute Make");
-        println!("status: {}", m.status);
-        std::io::stdout().write_all(&m.stdout).unwrap();
-        std::io::stderr().write_all(&m.stderr).unwrap();
-        assert!(m.status.success());
-    } else if target.contains("linux-android") {
-        let android_abi = {
-            if target.contains("aarch64") {
-                "arm64-v8a"
-            } else if target.contains("x86_64") {
-                "x86_64"
-            } else if target.contains("armv7") {
-                "armeabi-v7a"
-            } else if target.contains("i686") {
-                "x86"
-            } else {
-                panic!("unknown Android ABI")
-            }
-        };
-
-        let android_sdk = env::var("ANDROID_SDK_ROOT").expect("ANDROID_SDK_ROOT variable not set");
-
-        let android_platform = env::var("ANDROID_PLATFORM").unwrap_or_else(|_| "android-26".to_owned());
-        let android_cmake = env::var("ANDROID_CMAKE").unwrap_or(android_sdk.clone() + "/cmake/3.22.1/bin/cmake");
-        let android_toolchain = env::var("ANDROID_CMAKE_TOOLCHAIN")
-            .unwrap_or(android_sdk + "/ndk/22.1.7171670/build/cmake/android.toolchain.cmake");
-
-        let c = Command::new(android_cmake)
-            .arg("-D")
-            .arg("CMAKE_TOOLCHAIN_FILE=".to_owned() + &android_toolchain)
-            .arg("-D")
-            .arg("ANDROID_ABI=".to_owned() + android_abi)
-            .arg("-D")
-            .arg("ANDROID_PLATFORM=".to_owned() + &android_platform)
-            .arg(repo_dir.to_str().unwrap())
-            .output()
-            .expect("failed to execute CMake");
-
-        println!("status: {}", c.status);
-        std::io::stdout().write_all(&c.stdout).unwrap();
-        std::io::stderr().write_all(&c.stderr).unwrap();
-        assert!(c.status.success());
-
-        let m = Command::new("make").output().expect("failed to execute Make");
-        println!("status: {}", m.status);
-        std::io::stdout().write_all(&m.stdout).unwrap();
-        std::io::stderr().write_all(&m.stderr).unwrap();
-        assert!(m.status.success());
-    } else if target.contains("aarch64-apple-darwin") {
-        let mut c = Command::new("cmake");
-        c.arg("-D")
-            .arg("ARCH=arm64")
-            .arg("-D")
-            .arg("ARCH_ID=aarch64")
-            .arg("-D")
-            .arg("CMAKE_CROSSCOMPILING=true")
-            .arg("-D")
-            .arg("CMAKE_SYSTEM_PROCESSOR=aarch64")
-            .arg("-D")
-            .arg("CMAKE_C_FLAGS='-arch arm64'")
-            .arg("-D")
-            .arg("CMAKE_CXX_FLAGS='-arch arm64'");
-        if let Ok(env) = env::var("RANDOMX_RS_CMAKE_OSX_SYSROOT") {
-            c.arg("-D").arg("CMAKE_OSX_SYSROOT=".to_owned() + env.as_str());
-        }
-        let output = c
-            .arg(repo_dir.to_str().unwrap())
-            .output()
-            .expect("failed to execute CMake");
-        println!("status: {}", output.status);
-        std::io::stdout().write_all(&output.stdout).unwrap();
-        std::io::stderr().write_all(&output.stderr).unwrap();
-        assert!(output.status.success());
-
-        let m = Command::new("cmake")
-            .arg("--build")
-            .arg(".")
-            .arg("--config")
-            .arg("Release")
-            .output()
-            .expect("failed to execute Make");
-        println!("status: {}", m.status);
-        std::io::stdout().write_all(&m.stdout).unwrap();
-        std::io::stderr().write_all(&m.stderr).unwrap();
-        assert!(m.status.success());
+    assert!(
+        env::var("CARGO_CFG_TARGET_ARCH").unwrap() != "wasm32",
+        "RandomX cannot be built for {}, enable the `pure-rust` (or `wasm`) feature, without `differential`",
+        target
+    );
+
+    println!("cargo:rerun-if-env-changed=RANDOMX_LIB_DIR");
+    if env::var_os("CARGO_FEATURE_SYSTEM").is_some() || env::var_os("RANDOMX_LIB_DIR").is_some() {
+        link_prebuilt(&config, &target);
+        return;
+    }
+
+    let (source_dir, out_dir) = if config == RandomXConfig::MONERO && !feature_patches() {
+        (repo_dir.clone(), project_dir.join("randomx_build"))
     } else {
-        let c = Command::new("cmake")
-            .arg(repo_dir.to_str().unwrap())
-            .output()
-            .expect("failed to execute CMake");
-        println!("status: {}", c.status);
-        std::io::stdout().write_all(&c.stdout).unwrap();
-        std::io::stderr().write_all(&c.stderr).unwrap();
-        assert!(c.status.success());
-
-        let m = Command::new("make").output().expect("failed to execute Make");
-        println!("status: {}", m.status);
-        std::io::stdout().write_all(&m.stdout).unwrap();
-        std::io::stderr().write_all(&m.stderr).unwrap();
-        assert!(m.status.success());
-    }
-
-    env::set_current_dir(Path::new(&project_dir)).unwrap(); // change path back to main project
-
-    if target.contains("windows") {
-        let include = &build_dir.join("Release");
-        println!("cargo:rustc-link-search=native={}", &include.to_str().unwrap());
-        println!("cargo:rustc-link-lib=static=randomx");
+        (
+            patch_source(&repo_dir, project_dir, &config),
+            project_dir.join(format!("randomx_build_{}", config.name)),
+        )
+    };
+    let library_dir = build_randomx(&source_dir, &out_dir);
+    println!("cargo:rustc-link-search=native={}", library_dir.display());
+    println!("cargo:rustc-link-lib=static=randomx"); // link to RandomX
+
+    if env::var_os("CARGO_FEATURE_MULTI_VARIANT").is_some() {
+        build_variants(&repo_dir, project_dir, &config, &target);
+    }
+
+    link_cpp_runtime();
+}
+
+/// Links the C++ standard library that a static librandomx depends on: the one named by `CXXSTDLIB` if it is set (an
+/// empty value links none), as with the `cc` crate, otherwise the target's default.
+fn link_cpp_runtime() {
+    println!("cargo:rerun-if-env-changed=CXXSTDLIB");
+    let os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap();
+    let stdlib = env::var("CXXSTDLIB").unwrap_or_else(|_| {
+        match (os.as_str(), target_env.as_str()) {
+            // MSVC links its runtime by itself.
+            ("windows", "msvc") => "",
+            ("macos" | "ios" | "tvos" | "watchos" | "visionos" | "android" | "freebsd" | "openbsd", _) => "c++",
+            ("linux" | "windows" | "netbsd" | "dragonfly" | "solaris" | "illumos", _) => "stdc++",
+            _ => panic!(
+                "no default C++ standard library for {} ({}), set CXXSTDLIB to the one librandomx needs",
+                os, target_env
+            ),
+        }
+        .to_string()
+    });
+    if !stdlib.is_empty() {
+        println!("cargo:rustc-link-lib=dylib={}", stdlib);
+    }
+}
+
+/// The oldest RandomX release the bindings support.
+const MIN_RANDOMX_VERSION: &str = "1.2.0";
+
+/// The functions the bindings call, and `randomx_calculate_commitment`, which appeared in RandomX 1.2 and so tells a
+/// supported library apart from an older one that has no version information.
+const REQUIRED_SYMBOLS: [&str; 18] = [
+    "randomx_get_flags",
+    "randomx_alloc_cache",
+    "randomx_init_cache",
+    "randomx_release_cache",
+    "randomx_alloc_dataset",
+    "randomx_dataset_item_count",
+    "randomx_init_dataset",
+    "randomx_get_dataset_memory",
+    "randomx_release_dataset",
+    "randomx_create_vm",
+    "randomx_vm_set_cache",
+    "randomx_vm_set_dataset",
+    "randomx_destroy_vm",
+    "randomx_calculate_hash",
+    "randomx_calculate_hash_first",
+    "randomx_calculate_hash_next",
+    "randomx_calculate_hash_last",
+    "randomx_calculate_commitment",
+];
+
+/// Links an installed librandomx instead of building the submodule: the one in `RANDOMX_LIB_DIR` if it is set,
+/// otherwise the one pkg-config finds. `RANDOMX_STATIC=1` links it statically. Fails the build if the library cannot be
+/// found, is too old or does not export the functions the bindings need.
+///
+/// pkg-config checks the version against `MIN_RANDOMX_VERSION`. A library in `RANDOMX_LIB_DIR` has no version
+/// information, so there the symbol check stands in for it: `randomx_calculate_commitment` is only exported since
+/// RandomX 1.2.
+fn link_prebuilt(config: &RandomXConfig, target: &str) {
+    assert!(
+        *config == RandomXConfig::MONERO,
+        "a system librandomx has the upstream parameters; building with the `{}` parameters needs the RandomX sources",
+        config.name
+    );
+    assert!(
+        env::var_os("CARGO_FEATURE_MULTI_VARIANT").is_none(),
+        "the `multi-variant` feature builds RandomX from source and cannot use a system librandomx"
+    );
+    if feature_patches() {
+        println!(
+            "cargo:warning=a system librandomx keeps its JIT and hardware AES code, the `no-jit` and `soft-aes-only` \
+             features only make the bindings refuse the flags"
+        );
+    }
+    println!("cargo:rerun-if-env-changed=RANDOMX_STATIC");
+    let statik = matches!(env::var("RANDOMX_STATIC").as_deref(), Ok("1" | "true"));
+
+    let link_paths = if let Some(dir) = env::var_os("RANDOMX_LIB_DIR") {
+        let dir = PathBuf::from(dir);
+        println!("cargo:rustc-link-search=native={}", dir.display());
+        println!(
+            "cargo:rustc-link-lib={}=randomx",
+            if statik { "static" } else { "dylib" }
+        );
+        vec![dir]
     } else {
-        println!("cargo:rustc-link-search=native={}", &build_dir.to_str().unwrap());
-        println!("cargo:rustc-link-lib=static=randomx");
-    } // link to RandomX
-
-    if target.contains("apple") || target.contains("android") || target.contains("freebsd") {
-        println!("cargo:rustc-link-lib=dylib=c++");
-    } else if target.contains("linux") {
-        println!("cargo:rustc-link-lib=dylib=stdc++");
+        probe_pkg_config(statik)
+    };
+
+    match find_library(&link_paths, statik, target) {
+        Some(library) => check_symbols(&library, target),
+        None => panic!(
+            "could not find {} librandomx in {:?} or the system library directories; set RANDOMX_LIB_DIR to the \
+             directory that contains it",
+            if statik { "a static" } else { "a shared" },
+            link_paths
+        ),
+    }
+
+    if statik {
+        link_cpp_runtime();
+    }
+}
+
+/// Finds librandomx with pkg-config, emitting its link flags, and returns its library directories.
+#[cfg(feature = "system")]
+fn probe_pkg_config(statik: bool) -> Vec<PathBuf> {
+    match pkg_config::Config::new()
+        .atleast_version(MIN_RANDOMX_VERSION)
+        .statik(statik)
+        .probe("randomx")
+    {
+        Ok(library) => library.link_paths,
+        Err(e) => panic!(
+            "could not find librandomx >= {} with pkg-config, set RANDOMX_LIB_DIR to the directory that contains it \
+             instead\n{}",
+            MIN_RANDOMX_VERSION, e
+        ),
+    }
+}
+
+#[cfg(not(feature = "system"))]
+fn probe_pkg_config(_statik: bool) -> Vec<PathBuf> {
+    unreachable!("a prebuilt librandomx without RANDOMX_LIB_DIR is only looked up with the `system` feature")
+}
+
+/// Returns the path of the librandomx the linker will pick from `link_paths`, falling back to the usual system
+/// library directories, which pkg-config leaves out.
+fn find_library(link_paths: &[PathBuf], statik: bool, target: &str) -> Option<PathBuf> {
+    let name = if target.contains("msvc") {
+        "randomx.lib"
+    } else if statik {
+        "librandomx.a"
     } else if target.contains("windows") {
-        // println!("cargo:rustc-link-lib=dylib=c++");
+        "librandomx.dll.a"
+    } else if target.contains("apple") {
+        "librandomx.dylib"
+    } else {
+        "librandomx.so"
+    };
+    let system_dirs = ["/usr/local/lib", "/usr/local/lib64", "/usr/lib64", "/usr/lib"];
+    let multiarch = env::var("CARGO_CFG_TARGET_ARCH").map(|arch| PathBuf::from(format!("/usr/lib/{}-linux-gnu", arch)));
+    link_paths
+        .iter()
+        .cloned()
+        .chain(multiarch)
+        .chain(system_dirs.iter().map(PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Fails the build if `library` does not export every symbol in `REQUIRED_SYMBOLS`.
+fn check_symbols(library: &Path, target: &str) {
+    if target.contains("msvc") {
+        // There is no `nm` to inspect the library with; a mismatch shows up as a link error instead.
+        return;
+    }
+    let underscore = if target.contains("apple") { "_" } else { "" };
+    // Only ELF shared libraries keep their exports in a separate dynamic symbol table.
+    let symbols = defined_symbols(library, library.extension() == Some("so".as_ref()));
+    let missing: Vec<&str> = REQUIRED_SYMBOLS
+        .iter()
+        .copied()
+        .filter(|required| {
+            !symbols
+                .iter()
+                .any(|name| name.strip_prefix(underscore) == Some(*required))
+        })
+        .collect();
+    assert!(
+        missing.is_empty(),
+        "{} does not export {}; RandomX {} or newer is required",
+        library.display(),
+        missing.join(", "),
+        MIN_RANDOMX_VERSION
+    );
+}
+
+/// Configures, builds and installs the RandomX library in `source_dir` with CMake, in `out_dir`, and returns the
+/// directory the library is installed in.
+///
+/// The `cmake` crate picks the compilers, flags, generator and toolchain file from the target and the usual `CC`, `CXX`,
+/// `CFLAGS`, `CXXFLAGS`, `CMAKE_GENERATOR` and `CMAKE_TOOLCHAIN_FILE` variables (also in their `_<target>` forms), and
+/// the build type from the opt-level and debug settings of the profile, unless `RANDOMX_BUILD_TYPE` is set.
+fn build_randomx(source_dir: &Path, out_dir: &Path) -> PathBuf {
+    assert!(
+        source_dir.join("CMakeLists.txt").is_file(),
+        "no RandomX sources in {}: run `git submodule update --init`, set RANDOMX_DIR to a RandomX checkout, or link a \
+         prebuilt library with RANDOMX_LIB_DIR",
+        source_dir.display()
+    );
+    for var in [
+        "CC",
+        "CXX",
+        "CFLAGS",
+        "CXXFLAGS",
+        "CMAKE",
+        "CMAKE_GENERATOR",
+        "CMAKE_TOOLCHAIN_FILE",
+        "RANDOMX_BUILD_TYPE",
+        "RANDOMX_RS_CMAKE_OSX_SYSROOT",
+    ] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+    let mut cmake = cmake::Config::new(source_dir);
+    cmake
+        .out_dir(out_dir)
+        // RandomX selects its JIT compiler by ARCH_ID, which otherwise defaults to the host processor.
+        .define("ARCH_ID", arch_id())
+        .define("CMAKE_INSTALL_LIBDIR", "lib");
+    if let Ok(build_type) = env::var("RANDOMX_BUILD_TYPE") {
+        cmake.profile(&build_type);
+    }
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() == "android" {
+        configure_android(&mut cmake);
+    }
+    if let Ok(sysroot) = env::var("RANDOMX_RS_CMAKE_OSX_SYSROOT") {
+        cmake.define("CMAKE_OSX_SYSROOT", sysroot);
+    }
+    cmake.build().join("lib")
+}
+
+/// Returns RandomX's name for the target architecture.
+fn arch_id() -> String {
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let little_endian = env::var("CARGO_CFG_TARGET_ENDIAN").unwrap() == "little";
+    match arch.as_str() {
+        "x86" => "i686".to_string(),
+        "arm" => "armv7".to_string(),
+        "powerpc64" if little_endian => "ppc64le".to_string(),
+        "powerpc64" => "ppc64".to_string(),
+        _ => arch,
+    }
+}
+
+/// Uses the NDK's toolchain file, found from `ANDROID_SDK_ROOT` unless `ANDROID_CMAKE_TOOLCHAIN` is set, and the CMake
+/// in `ANDROID_CMAKE`.
+fn configure_android(cmake: &mut cmake::Config) {
+    let target = env::var("TARGET").unwrap();
+    let android_abi = if target.contains("aarch64") {
+        "arm64-v8a"
+    } else if target.contains("x86_64") {
+        "x86_64"
+    } else if target.contains("armv7") {
+        "armeabi-v7a"
+    } else if target.contains("i686") {
+        "x86"
     } else {
-        unimplemented!();
+        panic!("unknown Android ABI for {}", target)
+    };
+    for var in [
+        "ANDROID_SDK_ROOT",
+        "ANDROID_PLATFORM",
+        "ANDROID_CMAKE",
+        "ANDROID_CMAKE_TOOLCHAIN",
+    ] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+    let android_platform = env::var("ANDROID_PLATFORM").unwrap_or_else(|_| "android-26".to_owned());
+    let android_toolchain = env::var("ANDROID_CMAKE_TOOLCHAIN")
+        .or_else(|_| env::var("CMAKE_TOOLCHAIN_FILE"))
+        .unwrap_or_else(|_| {
+            let android_sdk = env::var("ANDROID_SDK_ROOT")
+                .expect("ANDROID_SDK_ROOT, ANDROID_CMAKE_TOOLCHAIN or CMAKE_TOOLCHAIN_FILE must be set for Android");
+            android_sdk + "/ndk/22.1.7171670/build/cmake/android.toolchain.cmake"
+        });
+    if let Ok(android_cmake) = env::var("ANDROID_CMAKE") {
+        // The `cmake` crate runs the CMake named by `CMAKE`.
+        env::set_var("CMAKE", android_cmake);
+    }
+    cmake
+        .define("CMAKE_TOOLCHAIN_FILE", android_toolchain)
+        .define("ANDROID_ABI", android_abi)
+        .define("ANDROID_PLATFORM", android_platform);
+}
+
+/// Builds every preset other than the active configuration as `librandomx_<name>.a`, with all its symbols prefixed by
+/// `rx<name>_` so that the libraries can be linked side by side, and enables the matching `randomx_variant_<name>` cfg.
+fn build_variants(repo_dir: &Path, project_dir: &Path, active: &RandomXConfig, target: &str) {
+    assert!(
+        !target.contains("msvc"),
+        "the `multi-variant` feature needs `nm` and `objcopy`, which are not available for MSVC targets"
+    );
+    println!("cargo:rerun-if-env-changed=OBJCOPY");
+    let variants_dir = project_dir.join("randomx_variants");
+    fs::create_dir_all(&variants_dir).unwrap();
+    for config in [RandomXConfig::MONERO, RandomXConfig::WOWNERO, RandomXConfig::ARQMA] {
+        if config == *active {
+            // Served by the unprefixed library.
+            continue;
+        }
+        let source_dir = patch_source(repo_dir, project_dir, &config);
+        let out_dir = project_dir.join(format!("randomx_build_{}", config.name));
+        let library = build_randomx(&source_dir, &out_dir).join("librandomx.a");
+        let prefixed = variants_dir.join(format!("librandomx_{}.a", config.name));
+        prefix_symbols(&library, &prefixed, &format!("rx{}_", config.name), target);
+        println!("cargo:rustc-link-lib=static=randomx_{}", config.name);
+        println!("cargo:rustc-cfg=randomx_variant_{}", config.name);
+    }
+    println!("cargo:rustc-link-search=native={}", variants_dir.display());
+}
+
+/// Copies the static library `from` to `to`, prefixing every symbol it defines with `prefix`. References between the
+/// objects of the library are renamed with them, references to other libraries (e.g. the C++ runtime) are not.
+fn prefix_symbols(from: &Path, to: &Path, prefix: &str, target: &str) {
+    let objcopy = env::var("OBJCOPY").unwrap_or_else(|_| "objcopy".to_string());
+
+    // Mach-O prepends an underscore to C symbol names; the prefix goes after it.
+    let underscore = if target.contains("apple") { "_" } else { "" };
+    let symbols = defined_symbols(from, false);
+    assert!(
+        symbols.iter().any(|name| name.ends_with("randomx_alloc_cache")),
+        "{} does not define randomx_alloc_cache",
+        from.display()
+    );
+    let renames: String = symbols
+        .iter()
+        .map(|name| {
+            let bare = name.strip_prefix(underscore).unwrap_or(name);
+            format!("{} {}{}{}\n", name, underscore, prefix, bare)
+        })
+        .collect();
+    let map = to.with_extension("syms");
+    fs::write(&map, renames).unwrap();
+
+    let status = Command::new(&objcopy)
+        .arg(format!("--redefine-syms={}", map.display()))
+        .arg(from)
+        .arg(to)
+        .status()
+        .unwrap_or_else(|e| panic!("failed to execute {}: {}", objcopy, e));
+    assert!(status.success(), "{} failed on {}", objcopy, from.display());
+}
+
+/// Lists the external symbols `library` defines, using `nm` (override with `NM`). `dynamic` selects the dynamic symbol
+/// table of a shared library.
+fn defined_symbols(library: &Path, dynamic: bool) -> Vec<String> {
+    println!("cargo:rerun-if-env-changed=NM");
+    let nm = env::var("NM").unwrap_or_else(|_| "nm".to_string());
+    let mut command = Command::new(&nm);
+    if dynamic {
+        command.arg("--dynamic");
+    }
+    let output = command
+        .args(["--defined-only", "--extern-only", "--portability"])
+        .arg(library)
+        .output()
+        .unwrap_or_else(|e| panic!("failed to execute {}: {}", nm, e));
+    std::io::stderr().write_all(&output.stderr).unwrap();
+    assert!(output.status.success(), "{} failed on {}", nm, library.display());
+
+    let mut symbols: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        // `name type [value [size]]`; archive member headers end with a colon and have no type.
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(name), Some(kind)) if kind.len() == 1 && !name.ends_with(':') => Some(name.to_string()),
+                _ => None,
+            }
+        })
+        .collect();
+    symbols.sort_unstable();
+    symbols.dedup();
+    symbols
+}
+
+/// Selects the RandomX parameters from the `randomx-*` features and the `RANDOMX_CONFIG` file.
+fn select_config() -> RandomXConfig {
+    let wow = env::var_os("CARGO_FEATURE_RANDOMX_WOW").is_some();
+    let arq = env::var_os("CARGO_FEATURE_RANDOMX_ARQ").is_some();
+    let mut config = match (wow, arq) {
+        (false, false) => RandomXConfig::MONERO,
+        (true, false) => RandomXConfig::WOWNERO,
+        (false, true) => RandomXConfig::ARQMA,
+        (true, true) => panic!("the `randomx-wow` and `randomx-arq` features are mutually exclusive"),
+    };
+
+    println!("cargo:rerun-if-env-changed=RANDOMX_CONFIG");
+    if let Some(path) = env::var_os("RANDOMX_CONFIG") {
+        let path = PathBuf::from(path);
+        println!("cargo:rerun-if-changed={}", path.display());
+        let contents = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read RANDOMX_CONFIG {}: {}", path.display(), e));
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .unwrap_or_else(|| panic!("{}:{}: expected `RANDOMX_NAME = value`", path.display(), number + 1));
+            let (name, value) = (name.trim(), value.trim());
+            if name == "RANDOMX_ARGON_SALT" {
+                config.argon_salt = Box::leak(parse_c_string(value).into_boxed_slice());
+            } else {
+                let parameter = config
+                    .parameter_mut(name)
+                    .unwrap_or_else(|| panic!("{}:{}: unknown parameter {}", path.display(), number + 1, name));
+                *parameter = value
+                    .replace('_', "")
+                    .parse()
+                    .unwrap_or_else(|e| panic!("{}:{}: invalid value for {}: {}", path.display(), number + 1, name, e));
+            }
+        }
+        config.name = "custom";
+    }
+
+    if let Err(reason) = config.validate() {
+        panic!("invalid RandomX configuration `{}`: {}", config.name, reason);
+    }
+    config
+}
+
+/// Parses a salt given as a string with C-style `\xNN` escapes, optionally in double quotes.
+fn parse_c_string(value: &str) -> Vec<u8> {
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut chars = value.bytes();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match chars.next() {
+            Some(b'x') => {
+                let hex = [chars.next(), chars.next()];
+                let hex = match hex {
+                    [Some(hi), Some(lo)] => [hi, lo],
+                    _ => panic!("RANDOMX_ARGON_SALT: `\\x` must be followed by two hex digits"),
+                };
+                let hex = std::str::from_utf8(&hex).unwrap();
+                bytes.push(
+                    u8::from_str_radix(hex, 16)
+                        .unwrap_or_else(|_| panic!("RANDOMX_ARGON_SALT: invalid escape `\\x{}`", hex)),
+                );
+            },
+            Some(escaped @ (b'\\' | b'"')) => bytes.push(escaped),
+            _ => panic!("RANDOMX_ARGON_SALT: only `\\xNN`, `\\\\` and `\\\"` escapes are supported"),
+        }
+    }
+    bytes
+}
+
+/// Writes the `RandomXConfig` expression returned by `RandomXConfig::active()`.
+fn write_active_config(out_dir: &Path, config: &RandomXConfig) {
+    let mut fields = String::new();
+    let mut frequencies = String::new();
+    for (define, value) in config.parameters() {
+        let name = define.trim_start_matches("RANDOMX_").to_lowercase();
+        match name.strip_prefix("freq_") {
+            Some(instruction) => frequencies.push_str(&format!("        {}: {},\n", instruction, value)),
+            None => fields.push_str(&format!("    {}: {},\n", name, value)),
+        }
+    }
+    let salt: String = config
+        .argon_salt
+        .iter()
+        .flat_map(|b| std::ascii::escape_default(*b))
+        .map(char::from)
+        .collect();
+    let source = format!(
+        "// Generated by build.rs from the `{name}` RandomX configuration.\nRandomXConfig {{\n    name: {name:?},\n    \
+         argon_salt: b\"{salt}\",\n{fields}    frequencies: InstructionFrequencies {{\n{frequencies}    }},\n}}\n",
+        name = config.name,
+        salt = salt,
+        fields = fields,
+        frequencies = frequencies,
+    );
+    fs::write(out_dir.join("randomx_config.rs"), source).unwrap();
+}
+
+/// Copies the RandomX sources to `out_dir`, rewrites the defines in `src/configuration.h` to match `config` and
+/// applies the patches of the `no-jit` and `soft-aes-only` features. Returns the patched source directory.
+fn patch_source(repo_dir: &Path, out_dir: &Path, config: &RandomXConfig) -> PathBuf {
+    let source_dir = out_dir.join(format!("randomx_src_{}", config.name));
+    copy_dir(repo_dir, &source_dir);
+
+    let header_path = source_dir.join("src").join("configuration.h");
+    let header = fs::read_to_string(&header_path).unwrap();
+    let salt: String = config
+        .argon_salt
+        .iter()
+        .map(|&b| match b {
+            b'"' | b'\\' => format!("\\{}", char::from(b)),
+            b' '..=b'~' => char::from(b).to_string(),
+            // Octal escapes end after three digits, unlike `\x` ones which would swallow a following hex digit.
+            _ => format!("\\{:03o}", b),
+        })
+        .collect();
+    let mut defines: Vec<(&str, String)> = config
+        .parameters()
+        .iter()
+        .map(|(name, value)| (*name, value.to_string()))
+        .collect();
+    defines.push(("RANDOMX_ARGON_SALT", format!("\"{}\"", salt)));
+
+    let mut found = vec![false; defines.len()];
+    let patched: Vec<String> = header
+        .lines()
+        .map(|line| {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("#define") {
+                return line.to_string();
+            }
+            let name = words.next().unwrap_or_default();
+            match defines.iter().position(|(define, _)| *define == name) {
+                Some(index) => {
+                    found[index] = true;
+                    format!("#define {} {}", name, defines[index].1)
+                },
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    if let Some(index) = found.iter().position(|found| !found) {
+        panic!("{} does not define {}", header_path.display(), defines[index].0);
+    }
+    write_if_changed(&header_path, (patched.join("\n") + "\n").as_bytes());
+
+    if env::var_os("CARGO_FEATURE_NO_JIT").is_some() {
+        remove_jit(&source_dir);
+    }
+    if env::var_os("CARGO_FEATURE_SOFT_AES_ONLY").is_some() {
+        disable_hard_aes(&source_dir);
+    }
+    source_dir
+}
+
+/// Whether a feature needs the sources patched even for the upstream parameters.
+fn feature_patches() -> bool {
+    env::var_os("CARGO_FEATURE_NO_JIT").is_some() || env::var_os("CARGO_FEATURE_SOFT_AES_ONLY").is_some()
+}
+
+/// Makes RandomX use its fallback JIT compiler, which has no code generator and reports that no compiler is
+/// available, on every architecture, and drops the JIT sources from the CMake build.
+fn remove_jit(source_dir: &Path) {
+    let src = source_dir.join("src");
+    prepend_branch(
+        &src.join("common.hpp"),
+        "#define RANDOMX_HAVE_COMPILER",
+        "#define RANDOMX_HAVE_COMPILER 0\nclass JitCompilerFallback;\nusing JitCompiler = JitCompilerFallback;",
+    );
+    prepend_branch(
+        &src.join("jit_compiler.hpp"),
+        "#include \"jit_compiler_",
+        "#include \"jit_compiler_fallback.hpp\"",
+    );
+
+    let cmake_path = source_dir.join("CMakeLists.txt");
+    let cmake_lists = fs::read_to_string(&cmake_path).unwrap();
+    let patched: Vec<String> = cmake_lists
+        .lines()
+        .map(|line| {
+            line.split_inclusive(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .map(|word| {
+                    if word.starts_with("src/jit_compiler_") && !word.contains("fallback") {
+                        // Keep the parenthesis that closes the command.
+                        if word.ends_with(')') {
+                            ")"
+                        } else {
+                            ""
+                        }
+                    } else {
+                        word
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    write_if_changed(&cmake_path, (patched.join("\n") + "\n").as_bytes());
+}
+
+/// Inserts an always taken `#if` branch with `body` in front of the preprocessor conditional that contains the first
+/// line of `path` starting with `anchor`.
+fn prepend_branch(path: &Path, anchor: &str, body: &str) {
+    let contents = fs::read_to_string(path).unwrap();
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let conditional = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with(anchor))
+        .and_then(|anchor| {
+            lines[..anchor]
+                .iter()
+                .rposition(|line| line.trim_start().starts_with("#if"))
+        })
+        .unwrap_or_else(|| panic!("the `no-jit` patch does not apply to {}", path.display()));
+    let condition = lines[conditional].trim_start();
+    let condition = if let Some(name) = condition.strip_prefix("#ifdef") {
+        format!("defined({})", name.trim())
+    } else if let Some(name) = condition.strip_prefix("#ifndef") {
+        format!("!defined({})", name.trim())
+    } else {
+        condition.trim_start_matches("#if").trim().to_string()
+    };
+    lines[conditional] = format!(
+        "#if 1 // randomx-rs: built with the `no-jit` feature\n{}\n#elif {}",
+        body, condition
+    );
+    write_if_changed(path, (lines.join("\n") + "\n").as_bytes());
+}
+
+/// Makes RandomX report that the CPU has no hardware AES, so that it never selects it by itself.
+fn disable_hard_aes(source_dir: &Path) {
+    let path = source_dir.join("src").join("intrin_portable.h");
+    let header = fs::read_to_string(&path).unwrap();
+    assert!(
+        header.contains("#define HAVE_AES 1"),
+        "the `soft-aes-only` patch does not apply to {}",
+        path.display()
+    );
+    write_if_changed(
+        &path,
+        header.replace("#define HAVE_AES 1", "#define HAVE_AES 0").as_bytes(),
+    );
+}
+
+/// Recursively copies `from` to `to`, skipping git metadata and leaving unchanged files untouched so that CMake does
+/// not rebuild them.
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap_or_else(|e| panic!("failed to read {}: {}", from.display(), e)) {
+        let entry = entry.unwrap();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            write_if_changed(&target, &fs::read(entry.path()).unwrap());
+        }
+    }
+}
+
+fn write_if_changed(path: &Path, contents: &[u8]) {
+    if fs::read(path).map_or(true, |existing| existing != contents) {
+        fs::write(path, contents).unwrap();
     }
 }
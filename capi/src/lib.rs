@@ -0,0 +1,518 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # RandomX C API
+//!
+//! A stable C interface over the safe `randomx-rs` wrappers, built as a shared and a static library. The header,
+//! `randomx_rs.h`, is generated by the build script in its `OUT_DIR`, which is published to the build scripts of
+//! dependents as `DEP_RANDOMX_RS_CAPI_INCLUDE`; outside cargo, generate it with the `cbindgen` CLI.
+//!
+//! Objects are passed around as opaque handles created by a `*_new` function and released with the matching `*_free`
+//! function. Fallible functions return `RANDOMX_RS_OK` or one of the `RANDOMX_RS_ERR_*` codes, and write their result
+//! through an out pointer only on success.
+//!
+//! Cache, dataset and verifier handles may be shared between threads. A VM handle must only be used by one thread at a
+//! time.
+
+use std::{
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    slice,
+};
+
+use randomx_rs::{verifier, RandomXCache, RandomXDataset, RandomXError, RandomXFlag, RandomXVM};
+
+/// Size in bytes of a RandomX hash.
+pub const RANDOMX_RS_HASH_SIZE: usize = 32;
+
+/// The call succeeded.
+pub const RANDOMX_RS_OK: i32 = 0;
+/// The key was empty.
+pub const RANDOMX_RS_ERR_EMPTY_KEY: i32 = 1;
+/// The input was empty.
+pub const RANDOMX_RS_ERR_EMPTY_INPUT: i32 = 2;
+/// A parameter was invalid, e.g. a required pointer was null or an output buffer was too small.
+pub const RANDOMX_RS_ERR_INVALID_PARAMETER: i32 = 3;
+/// RandomX could not allocate the object.
+pub const RANDOMX_RS_ERR_ALLOCATION_FAILED: i32 = 4;
+/// The flags are not valid for the requested operation.
+pub const RANDOMX_RS_ERR_INVALID_FLAG_COMBINATION: i32 = 5;
+/// The dataset item range is out of bounds.
+pub const RANDOMX_RS_ERR_DATASET_RANGE_OUT_OF_BOUNDS: i32 = 6;
+/// RandomX reported an empty dataset.
+pub const RANDOMX_RS_ERR_EMPTY_DATASET: i32 = 7;
+/// The memory of an object could not be accessed.
+pub const RANDOMX_RS_ERR_MEMORY_UNAVAILABLE: i32 = 8;
+/// RandomX produced an empty hash.
+pub const RANDOMX_RS_ERR_EMPTY_HASH: i32 = 9;
+/// An integer conversion failed.
+pub const RANDOMX_RS_ERR_INT_CONVERSION: i32 = 10;
+/// The operation was cancelled.
+pub const RANDOMX_RS_ERR_CANCELLED: i32 = 11;
+/// A request queue was full.
+pub const RANDOMX_RS_ERR_QUEUE_FULL: i32 = 12;
+/// A worker thread exited unexpectedly.
+pub const RANDOMX_RS_ERR_WORKER_FAILED: i32 = 13;
+/// An I/O error occurred.
+pub const RANDOMX_RS_ERR_IO: i32 = 14;
+/// The requested RandomX variant is not built into the library.
+pub const RANDOMX_RS_ERR_UNAVAILABLE_VARIANT: i32 = 15;
+/// Objects of different RandomX variants were combined.
+pub const RANDOMX_RS_ERR_VARIANT_MISMATCH: i32 = 16;
+/// A flag was passed that the library was built without (`no-jit`, `soft-aes-only` or `pure-rust`).
+pub const RANDOMX_RS_ERR_DISABLED_FLAG: i32 = 17;
+/// A dataset (or VM) is used after its cache was re-initialized with another key.
+pub const RANDOMX_RS_ERR_KEY_MISMATCH: i32 = 18;
+/// Persisted data does not match the RandomX build, flags or key it is loaded for.
+pub const RANDOMX_RS_ERR_PERSISTENCE_MISMATCH: i32 = 19;
+/// The library panicked; the state of the handles involved is unspecified.
+pub const RANDOMX_RS_ERR_PANIC: i32 = -1;
+
+/// Opaque RandomX cache handle.
+pub struct Cache(RandomXCache);
+
+/// Opaque RandomX dataset handle.
+pub struct Dataset(RandomXDataset);
+
+/// Opaque RandomX VM handle.
+pub struct Vm(RandomXVM);
+
+/// Opaque handle to a light-mode verifier with an LRU of caches.
+pub struct Verifier(verifier::Verifier);
+
+fn status(result: Result<(), RandomXError>) -> i32 {
+    match result {
+        Ok(()) => RANDOMX_RS_OK,
+        Err(e) => e.code() as i32,
+    }
+}
+
+/// Runs `f`, converting its result to a status code. Panics must not unwind into C.
+fn guard<F>(f: F) -> i32
+where
+    F: FnOnce() -> Result<(), RandomXError>,
+{
+    catch_unwind(AssertUnwindSafe(f)).map_or(RANDOMX_RS_ERR_PANIC, status)
+}
+
+fn null_pointer() -> RandomXError {
+    RandomXError::InvalidParameter {
+        name: "pointer",
+        reason: "is null",
+    }
+}
+
+/// Borrows `len` bytes at `data`, treating a null pointer as an empty slice.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+unsafe fn handle<'a, T>(ptr: *const T) -> Result<&'a T, RandomXError> {
+    ptr.as_ref().ok_or_else(null_pointer)
+}
+
+/// Checks an out pointer up front, so that no work is done for a call that cannot return its result.
+unsafe fn out_slot<'a, T>(out: *mut T) -> Result<&'a mut T, RandomXError> {
+    out.as_mut().ok_or_else(null_pointer)
+}
+
+/// Returns the first `RANDOMX_RS_HASH_SIZE` bytes of the `out_len` byte buffer at `out`.
+unsafe fn hash_slot<'a>(out: *mut u8, out_len: usize) -> Result<&'a mut [u8], RandomXError> {
+    if out.is_null() {
+        return Err(null_pointer());
+    }
+    if out_len < RANDOMX_RS_HASH_SIZE {
+        return Err(RandomXError::InvalidParameter {
+            name: "out_len",
+            reason: "is smaller than RANDOMX_RS_HASH_SIZE",
+        });
+    }
+    Ok(slice::from_raw_parts_mut(out, RANDOMX_RS_HASH_SIZE))
+}
+
+unsafe fn free<T>(ptr: *mut T) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr));
+    }
+}
+
+/// Returns a static, NUL-terminated description of a status code.
+#[no_mangle]
+pub extern "C" fn randomx_rs_error_message(code: i32) -> *const c_char {
+    let message: &'static [u8] = match code {
+        RANDOMX_RS_OK => b"ok\0",
+        RANDOMX_RS_ERR_EMPTY_KEY => b"key is empty\0",
+        RANDOMX_RS_ERR_EMPTY_INPUT => b"input was empty\0",
+        RANDOMX_RS_ERR_INVALID_PARAMETER => b"invalid parameter\0",
+        RANDOMX_RS_ERR_ALLOCATION_FAILED => b"could not allocate RandomX object\0",
+        RANDOMX_RS_ERR_INVALID_FLAG_COMBINATION => b"invalid flag combination\0",
+        RANDOMX_RS_ERR_DATASET_RANGE_OUT_OF_BOUNDS => b"dataset range out of bounds\0",
+        RANDOMX_RS_ERR_EMPTY_DATASET => b"dataset item count was 0\0",
+        RANDOMX_RS_ERR_MEMORY_UNAVAILABLE => b"memory unavailable\0",
+        RANDOMX_RS_ERR_EMPTY_HASH => b"calculated hash was empty\0",
+        RANDOMX_RS_ERR_INT_CONVERSION => b"integer conversion failed\0",
+        RANDOMX_RS_ERR_CANCELLED => b"operation cancelled\0",
+        RANDOMX_RS_ERR_QUEUE_FULL => b"request queue is full\0",
+        RANDOMX_RS_ERR_WORKER_FAILED => b"worker thread exited unexpectedly\0",
+        RANDOMX_RS_ERR_IO => b"I/O error\0",
+        RANDOMX_RS_ERR_UNAVAILABLE_VARIANT => b"RandomX variant not available\0",
+        RANDOMX_RS_ERR_VARIANT_MISMATCH => b"objects of different RandomX variants combined\0",
+        RANDOMX_RS_ERR_DISABLED_FLAG => b"flag disabled in this build\0",
+        RANDOMX_RS_ERR_KEY_MISMATCH => b"cache re-initialized with another key\0",
+        RANDOMX_RS_ERR_PERSISTENCE_MISMATCH => b"persisted data does not match\0",
+        RANDOMX_RS_ERR_PANIC => b"internal panic\0",
+        _ => b"unknown error\0",
+    };
+    message.as_ptr().cast()
+}
+
+/// Returns the recommended RandomX flags for the current CPU.
+#[no_mangle]
+pub extern "C" fn randomx_rs_get_recommended_flags() -> u32 {
+    RandomXFlag::get_recommended_flags().bits()
+}
+
+/// Returns the number of items in a dataset through `out_count`.
+///
+/// # Safety
+/// `out_count` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_dataset_item_count(out_count: *mut u32) -> i32 {
+    guard(|| {
+        *out_slot(out_count)? = RandomXDataset::count()?;
+        Ok(())
+    })
+}
+
+/// Creates a cache initialized with `key` and writes its handle to `out`.
+///
+/// # Safety
+/// `key` must be valid for reads of `key_len` bytes and `out` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_cache_new(flags: u32, key: *const u8, key_len: usize, out: *mut *mut Cache) -> i32 {
+    guard(|| {
+        let out = out_slot(out)?;
+        let cache = RandomXCache::new(RandomXFlag::from_bits_truncate(flags), bytes(key, key_len))?;
+        *out = Box::into_raw(Box::new(Cache(cache)));
+        Ok(())
+    })
+}
+
+/// Re-initializes a cache with a new key. Does nothing if the cache already holds `key`.
+///
+/// # Safety
+/// `cache` must be null or a live cache handle and `key` must be valid for reads of `key_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_cache_init(cache: *const Cache, key: *const u8, key_len: usize) -> i32 {
+    guard(|| handle(cache)?.0.init(bytes(key, key_len)))
+}
+
+/// Releases a cache handle. Datasets and VMs created from it keep the underlying cache alive.
+///
+/// # Safety
+/// `cache` must be null or a cache handle that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_cache_free(cache: *mut Cache) {
+    free(cache);
+}
+
+/// Creates a dataset from `cache`, initialized from item `start`, and writes its handle to `out`.
+///
+/// # Safety
+/// `cache` must be null or a live cache handle and `out` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_dataset_new(
+    flags: u32,
+    cache: *const Cache,
+    start: u32,
+    out: *mut *mut Dataset,
+) -> i32 {
+    guard(|| {
+        let out = out_slot(out)?;
+        let cache = handle(cache)?.0.clone();
+        let dataset = RandomXDataset::new(RandomXFlag::from_bits_truncate(flags), cache, start)?;
+        *out = Box::into_raw(Box::new(Dataset(dataset)));
+        Ok(())
+    })
+}
+
+/// Releases a dataset handle. VMs created from it keep the underlying dataset alive.
+///
+/// # Safety
+/// `dataset` must be null or a dataset handle that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_dataset_free(dataset: *mut Dataset) {
+    free(dataset);
+}
+
+/// Creates a VM and writes its handle to `out`. `cache` is required unless `FLAG_FULL_MEM` is set, in which case
+/// `dataset` is required instead; either may be null otherwise.
+///
+/// # Safety
+/// `cache` and `dataset` must each be null or a live handle and `out` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_vm_new(
+    flags: u32,
+    cache: *const Cache,
+    dataset: *const Dataset,
+    out: *mut *mut Vm,
+) -> i32 {
+    guard(|| {
+        let out = out_slot(out)?;
+        let cache = cache.as_ref().map(|cache| cache.0.clone());
+        let dataset = dataset.as_ref().map(|dataset| dataset.0.clone());
+        let vm = RandomXVM::new(RandomXFlag::from_bits_truncate(flags), cache, dataset)?;
+        *out = Box::into_raw(Box::new(Vm(vm)));
+        Ok(())
+    })
+}
+
+/// Hashes `input` and writes the `RANDOMX_RS_HASH_SIZE` byte result to `out`.
+///
+/// # Safety
+/// `vm` must be null or a live VM handle not in use by another thread, `input` must be valid for reads of `input_len`
+/// bytes and `out` must be null or valid for writes of `out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_vm_calculate_hash(
+    vm: *const Vm,
+    input: *const u8,
+    input_len: usize,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    guard(|| {
+        let out = hash_slot(out, out_len)?;
+        let hash = handle(vm)?.0.calculate_hash(bytes(input, input_len))?;
+        out.copy_from_slice(&hash);
+        Ok(())
+    })
+}
+
+/// Releases a VM handle.
+///
+/// # Safety
+/// `vm` must be null or a VM handle that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_vm_free(vm: *mut Vm) {
+    free(vm);
+}
+
+/// Creates a light-mode verifier that keeps caches for the `capacity` most recently used keys, and writes its handle
+/// to `out`.
+///
+/// # Safety
+/// `out` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_verifier_new(flags: u32, capacity: usize, out: *mut *mut Verifier) -> i32 {
+    guard(|| {
+        let out = out_slot(out)?;
+        let verifier = verifier::Verifier::new(RandomXFlag::from_bits_truncate(flags), capacity)?;
+        *out = Box::into_raw(Box::new(Verifier(verifier)));
+        Ok(())
+    })
+}
+
+/// Hashes `input` under `key` and writes the `RANDOMX_RS_HASH_SIZE` byte result to `out`.
+///
+/// # Safety
+/// `verifier` must be null or a live verifier handle, `key` and `input` must be valid for reads of `key_len` and
+/// `input_len` bytes, and `out` must be null or valid for writes of `out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_verifier_calculate_hash(
+    verifier: *const Verifier,
+    key: *const u8,
+    key_len: usize,
+    input: *const u8,
+    input_len: usize,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    guard(|| {
+        let out = hash_slot(out, out_len)?;
+        let hash = handle(verifier)?
+            .0
+            .calculate_hash(bytes(key, key_len), bytes(input, input_len))?;
+        out.copy_from_slice(&hash);
+        Ok(())
+    })
+}
+
+/// Checks that `hash` is the hash of `input` under `key` and writes the outcome to `out_valid`.
+///
+/// # Safety
+/// `verifier` must be null or a live verifier handle, `key`, `input` and `hash` must be valid for reads of their
+/// lengths, and `out_valid` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_verifier_verify(
+    verifier: *const Verifier,
+    key: *const u8,
+    key_len: usize,
+    input: *const u8,
+    input_len: usize,
+    hash: *const u8,
+    hash_len: usize,
+    out_valid: *mut bool,
+) -> i32 {
+    guard(|| {
+        let out_valid = out_slot(out_valid)?;
+        *out_valid = handle(verifier)?
+            .0
+            .verify(bytes(key, key_len), bytes(input, input_len), bytes(hash, hash_len))?;
+        Ok(())
+    })
+}
+
+/// Returns the number of caches the verifier currently keeps, or 0 if `verifier` is null.
+///
+/// # Safety
+/// `verifier` must be null or a live verifier handle.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_verifier_cache_len(verifier: *const Verifier) -> usize {
+    verifier.as_ref().map_or(0, |verifier| verifier.0.len())
+}
+
+/// Drops all caches kept by the verifier.
+///
+/// # Safety
+/// `verifier` must be null or a live verifier handle.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_verifier_clear(verifier: *const Verifier) {
+    if let Some(verifier) = verifier.as_ref() {
+        verifier.0.clear();
+    }
+}
+
+/// Releases a verifier handle and the caches it keeps.
+///
+/// # Safety
+/// `verifier` must be null or a verifier handle that is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn randomx_rs_verifier_free(verifier: *mut Verifier) {
+    free(verifier);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CStr, ptr};
+
+    use randomx_rs::RandomXErrorCode;
+
+    use super::*;
+
+    #[test]
+    fn status_codes_match_error_codes() {
+        // The constants are literals so that cbindgen can emit them; keep them in step with `RandomXErrorCode`.
+        let codes = [
+            (RANDOMX_RS_ERR_EMPTY_KEY, RandomXErrorCode::EmptyKey),
+            (RANDOMX_RS_ERR_EMPTY_INPUT, RandomXErrorCode::EmptyInput),
+            (RANDOMX_RS_ERR_INVALID_PARAMETER, RandomXErrorCode::InvalidParameter),
+            (RANDOMX_RS_ERR_ALLOCATION_FAILED, RandomXErrorCode::AllocationFailed),
+            (
+                RANDOMX_RS_ERR_INVALID_FLAG_COMBINATION,
+                RandomXErrorCode::InvalidFlagCombination,
+            ),
+            (
+                RANDOMX_RS_ERR_DATASET_RANGE_OUT_OF_BOUNDS,
+                RandomXErrorCode::DatasetRangeOutOfBounds,
+            ),
+            (RANDOMX_RS_ERR_EMPTY_DATASET, RandomXErrorCode::EmptyDataset),
+            (RANDOMX_RS_ERR_MEMORY_UNAVAILABLE, RandomXErrorCode::MemoryUnavailable),
+            (RANDOMX_RS_ERR_EMPTY_HASH, RandomXErrorCode::EmptyHash),
+            (RANDOMX_RS_ERR_INT_CONVERSION, RandomXErrorCode::IntConversion),
+            (RANDOMX_RS_ERR_CANCELLED, RandomXErrorCode::Cancelled),
+            (RANDOMX_RS_ERR_QUEUE_FULL, RandomXErrorCode::QueueFull),
+            (RANDOMX_RS_ERR_WORKER_FAILED, RandomXErrorCode::WorkerFailed),
+            (RANDOMX_RS_ERR_IO, RandomXErrorCode::Io),
+            (RANDOMX_RS_ERR_UNAVAILABLE_VARIANT, RandomXErrorCode::UnavailableVariant),
+            (RANDOMX_RS_ERR_VARIANT_MISMATCH, RandomXErrorCode::VariantMismatch),
+            (RANDOMX_RS_ERR_DISABLED_FLAG, RandomXErrorCode::DisabledFlag),
+            (RANDOMX_RS_ERR_KEY_MISMATCH, RandomXErrorCode::KeyMismatch),
+            (RANDOMX_RS_ERR_PERSISTENCE_MISMATCH, RandomXErrorCode::PersistenceMismatch),
+        ];
+        for (status, code) in codes {
+            assert_eq!(status, code as i32, "{code:?}");
+        }
+    }
+
+    #[test]
+    fn error_messages_cover_all_codes() {
+        for code in RANDOMX_RS_ERR_PANIC..=RANDOMX_RS_ERR_PERSISTENCE_MISMATCH {
+            let message = unsafe { CStr::from_ptr(randomx_rs_error_message(code)) };
+            assert_ne!(message.to_str().unwrap(), "unknown error", "code {code}");
+        }
+    }
+
+    #[test]
+    fn null_pointers_are_rejected() {
+        unsafe {
+            assert_eq!(
+                randomx_rs_cache_new(0, b"Key".as_ptr(), 3, ptr::null_mut()),
+                RANDOMX_RS_ERR_INVALID_PARAMETER
+            );
+            assert_eq!(
+                randomx_rs_vm_calculate_hash(ptr::null(), b"Input".as_ptr(), 5, ptr::null_mut(), 0),
+                RANDOMX_RS_ERR_INVALID_PARAMETER
+            );
+            let mut cache = ptr::null_mut();
+            assert_eq!(
+                randomx_rs_cache_new(0, ptr::null(), 0, &mut cache),
+                RANDOMX_RS_ERR_EMPTY_KEY
+            );
+            assert!(cache.is_null());
+            randomx_rs_cache_free(cache);
+        }
+    }
+
+    #[test]
+    fn vm_hash_matches_test_vector() {
+        let key = b"test key 000";
+        let input = b"This is a test";
+        let mut hash = [0u8; RANDOMX_RS_HASH_SIZE];
+        unsafe {
+            let mut cache = ptr::null_mut();
+            assert_eq!(
+                randomx_rs_cache_new(0, key.as_ptr(), key.len(), &mut cache),
+                RANDOMX_RS_OK
+            );
+            let mut vm = ptr::null_mut();
+            assert_eq!(randomx_rs_vm_new(0, cache, ptr::null(), &mut vm), RANDOMX_RS_OK);
+            randomx_rs_cache_free(cache);
+            assert_eq!(
+                randomx_rs_vm_calculate_hash(vm, input.as_ptr(), input.len(), hash.as_mut_ptr(), hash.len() - 1),
+                RANDOMX_RS_ERR_INVALID_PARAMETER
+            );
+            assert_eq!(
+                randomx_rs_vm_calculate_hash(vm, input.as_ptr(), input.len(), hash.as_mut_ptr(), hash.len()),
+                RANDOMX_RS_OK
+            );
+            randomx_rs_vm_free(vm);
+        }
+        assert_eq!(
+            hash.to_vec(),
+            hex::decode("639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f").unwrap()
+        );
+    }
+}
@@ -0,0 +1,56 @@
+/* Exercises the C API through the generated header. Exits non-zero on the first failed check. */
+#include <stdio.h>
+#include <string.h>
+
+#include "randomx_rs.h"
+
+#define CHECK(cond)                                                        \
+    do {                                                                   \
+        if (!(cond)) {                                                     \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            return 1;                                                      \
+        }                                                                  \
+    } while (0)
+
+static const uint8_t EXPECTED[RANDOMX_RS_HASH_SIZE] = {
+    0x63, 0x91, 0x83, 0xaa, 0xe1, 0xbf, 0x4c, 0x9a, 0x35, 0x88, 0x4c, 0xb4, 0x6b, 0x09, 0xca, 0xd9,
+    0x17, 0x5f, 0x04, 0xef, 0xd7, 0x68, 0x4e, 0x72, 0x62, 0xa0, 0xac, 0x1c, 0x2f, 0x0b, 0x4e, 0x3f,
+};
+
+int main(void) {
+    const char *key = "test key 000";
+    const char *input = "This is a test";
+    uint32_t flags = randomx_rs_get_recommended_flags();
+    uint8_t hash[RANDOMX_RS_HASH_SIZE];
+
+    randomx_rs_cache *cache = NULL;
+    CHECK(randomx_rs_cache_new(flags, (const uint8_t *)key, strlen(key), &cache) == RANDOMX_RS_OK);
+    randomx_rs_vm *vm = NULL;
+    CHECK(randomx_rs_vm_new(flags, cache, NULL, &vm) == RANDOMX_RS_OK);
+    randomx_rs_cache_free(cache);
+    CHECK(randomx_rs_vm_calculate_hash(vm, (const uint8_t *)input, strlen(input), hash, sizeof(hash)) ==
+          RANDOMX_RS_OK);
+    CHECK(memcmp(hash, EXPECTED, sizeof(hash)) == 0);
+    randomx_rs_vm_free(vm);
+
+    randomx_rs_verifier *verifier = NULL;
+    CHECK(randomx_rs_verifier_new(flags, 2, &verifier) == RANDOMX_RS_OK);
+    bool valid = false;
+    CHECK(randomx_rs_verifier_verify(verifier, (const uint8_t *)key, strlen(key), (const uint8_t *)input,
+                                     strlen(input), EXPECTED, sizeof(EXPECTED), &valid) == RANDOMX_RS_OK);
+    CHECK(valid);
+    CHECK(randomx_rs_verifier_verify(verifier, (const uint8_t *)"test key 001", 12, (const uint8_t *)input,
+                                     strlen(input), EXPECTED, sizeof(EXPECTED), &valid) == RANDOMX_RS_OK);
+    CHECK(!valid);
+    CHECK(randomx_rs_verifier_cache_len(verifier) == 2);
+    randomx_rs_verifier_free(verifier);
+
+    cache = NULL;
+    int code = randomx_rs_cache_new(flags, NULL, 0, &cache);
+    CHECK(code == RANDOMX_RS_ERR_EMPTY_KEY);
+    CHECK(cache == NULL);
+    CHECK(strcmp(randomx_rs_error_message(code), "key is empty") == 0);
+
+    printf("C API checks passed\n");
+    return 0;
+}
@@ -0,0 +1,60 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Compiles `tests/c/capi_test.c` against the generated header and the shared library, then runs it.
+
+#![cfg(unix)]
+
+use std::{env, path::PathBuf, process::Command};
+
+#[test]
+fn c_program_links_and_runs() {
+    // The test binary lives in `target/<profile>/deps`, next to the shared library.
+    let deps_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let profile_dir = deps_dir.parent().unwrap().to_path_buf();
+    let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/c/capi_test.c");
+    let binary = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("capi_test");
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg(&source)
+        .arg("-I")
+        .arg(env!("RANDOMX_RS_CAPI_INCLUDE"))
+        .arg("-L")
+        .arg(&deps_dir)
+        .arg("-L")
+        .arg(&profile_dir)
+        .arg("-lrandomx_rs_capi")
+        .arg(format!("-Wl,-rpath,{}", deps_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", profile_dir.display()))
+        .arg("-o")
+        .arg(&binary)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "compiling {} failed", source.display());
+
+    let output = Command::new(&binary).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use randomx_rs::test_utils::fuzz_pure_rust_matches_ffi;
+
+fuzz_target!(|data: &[u8]| {
+    assert!(fuzz_pure_rust_matches_ffi(data.to_vec()));
+});
@@ -0,0 +1,100 @@
+// Tests for the Node.js bindings. Build the addon with `npm run build` and run `npm test`.
+
+const assert = require('node:assert/strict')
+const { test } = require('node:test')
+
+const {
+  FLAG_FULL_MEM,
+  FLAG_HARD_AES,
+  FLAG_JIT,
+  FLAG_SECURE,
+  Verifier,
+  createCache,
+  createDataset,
+  disabledFlags,
+  formatFlags,
+  parseFlags,
+  recommendedFlags,
+} = require('..')
+
+// Test vectors from https://github.com/tevador/RandomX/blob/040f4500a6e79d54d84a668013a94507045e786f/src/tests/tests.cpp#L963-L985
+const VECTORS = [
+  ['test key 000', 'This is a test', '639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f'],
+  ['test key 000', 'Lorem ipsum dolor sit amet', '300a0adb47603dedb42228ccb2b211104f4da45af709cd7547cd049e9489c969'],
+  [
+    'test key 000',
+    'sed do eiusmod tempor incididunt ut labore et dolore magna aliqua',
+    'c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8',
+  ],
+  [
+    'test key 001',
+    'sed do eiusmod tempor incididunt ut labore et dolore magna aliqua',
+    'e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc',
+  ],
+]
+
+const FLAGS = recommendedFlags()
+
+test('light mode vectors', async () => {
+  for (const [key, data, expected] of VECTORS) {
+    const cache = await createCache(FLAGS, Buffer.from(key))
+    const hash = await cache.calculateHash(Buffer.from(data))
+    assert.equal(hash.toString('hex'), expected)
+  }
+})
+
+test('hashes in parallel with a shared cache', async () => {
+  const cache = await createCache(FLAGS, Buffer.from('test key 000'))
+  const hashes = await Promise.all(VECTORS.slice(0, 3).map(([, data]) => cache.calculateHash(Buffer.from(data))))
+  assert.deepEqual(
+    hashes.map((hash) => hash.toString('hex')),
+    VECTORS.slice(0, 3).map(([, , expected]) => expected),
+  )
+})
+
+test('switches between caches', async () => {
+  const caches = await Promise.all(['test key 000', 'test key 001'].map((key) => createCache(FLAGS, Buffer.from(key))))
+  const [, data] = VECTORS[2]
+  for (const index of [0, 1, 1, 0]) {
+    const hash = await caches[index].calculateHash(Buffer.from(data))
+    assert.equal(hash.toString('hex'), VECTORS[2 + index][2])
+  }
+})
+
+test('fast mode vectors', { skip: (disabledFlags() & FLAG_FULL_MEM) !== 0 && 'fast mode is disabled' }, async () => {
+  const flags = FLAGS | FLAG_FULL_MEM
+  const dataset = await createDataset(flags, await createCache(flags, Buffer.from('test key 000')))
+  for (const [, data, expected] of VECTORS.slice(0, 3)) {
+    const hash = await dataset.calculateHash(Buffer.from(data))
+    assert.equal(hash.toString('hex'), expected)
+  }
+})
+
+test('verifier', async () => {
+  const verifier = new Verifier(FLAGS, 2)
+  for (const [key, data, expected] of VECTORS) {
+    const hash = Buffer.from(expected, 'hex')
+    assert.equal(await verifier.verify(Buffer.from(key), Buffer.from(data), hash), true)
+    assert.equal(await verifier.verify(Buffer.from(key), Buffer.from(data), Buffer.alloc(32)), false)
+  }
+  const [key, data, expected] = VECTORS[3]
+  assert.equal((await verifier.calculateHash(Buffer.from(key), Buffer.from(data))).toString('hex'), expected)
+  assert.equal(verifier.cacheCount, 2)
+})
+
+test('flags', () => {
+  const flags = parseFlags('jit,hard_aes') | FLAG_SECURE
+  assert.equal(flags, FLAG_JIT | FLAG_HARD_AES | FLAG_SECURE)
+  assert.equal(formatFlags(flags), 'hard_aes,jit,secure')
+  assert.equal(parseFlags(formatFlags(flags)), flags)
+  assert.throws(() => parseFlags('turbo'), /turbo/)
+  assert.throws(() => formatFlags(1 << 30), /unknown flag bits 0x40000000/)
+})
+
+test('errors', async () => {
+  await assert.rejects(createCache(FLAGS, Buffer.alloc(0)), /key is empty/)
+  assert.throws(() => createCache(FLAGS | (1 << 30), Buffer.from('test key 000')), /unknown flag bits/)
+  const cache = await createCache(FLAGS, Buffer.from('test key 000'))
+  await assert.rejects(cache.calculateHash(Buffer.alloc(0)), /input was empty/)
+  assert.throws(() => cache.calculateHash('not a buffer'))
+})
@@ -0,0 +1,354 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # RandomX for Node.js
+//!
+//! An N-API addon over the safe `randomx-rs` wrappers, built with `napi build` from `@napi-rs/cli`.
+//!
+//! Caches and datasets are created on the libuv thread pool (`createCache` and `createDataset` return promises), and
+//! so is all hashing. Each cache and dataset object keeps the VMs that hashed with it for its next hashes, one per hash
+//! that ran at the same time; they are freed with the object, once it is garbage collected and its pending hashes are
+//! done. Light-mode verification goes through `Verifier`, which keeps the caches of recently used keys. Flags are
+//! numbers, see the `FLAG_*` constants and `parseFlags`, and hashes are `Buffer`s.
+
+use std::{
+    convert::TryFrom,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use napi::{bindgen_prelude::*, Task};
+use napi_derive::napi;
+use randomx_rs::{self as rx, verifier, RandomXError, RandomXFlag, RandomXVM};
+
+#[napi]
+pub const FLAG_DEFAULT: u32 = RandomXFlag::FLAG_DEFAULT.bits();
+#[napi]
+pub const FLAG_LARGE_PAGES: u32 = RandomXFlag::FLAG_LARGE_PAGES.bits();
+#[napi]
+pub const FLAG_HARD_AES: u32 = RandomXFlag::FLAG_HARD_AES.bits();
+#[napi]
+pub const FLAG_FULL_MEM: u32 = RandomXFlag::FLAG_FULL_MEM.bits();
+#[napi]
+pub const FLAG_JIT: u32 = RandomXFlag::FLAG_JIT.bits();
+#[napi]
+pub const FLAG_SECURE: u32 = RandomXFlag::FLAG_SECURE.bits();
+#[napi]
+pub const FLAG_ARGON2_SSSE3: u32 = RandomXFlag::FLAG_ARGON2_SSSE3.bits();
+#[napi]
+pub const FLAG_ARGON2_AVX2: u32 = RandomXFlag::FLAG_ARGON2_AVX2.bits();
+#[napi]
+pub const FLAG_ARGON2: u32 = RandomXFlag::FLAG_ARGON2.bits();
+
+/// Turns a [`RandomXError`] into a JavaScript `Error` with its message.
+trait OrThrow<T> {
+    fn or_throw(self) -> Result<T>;
+}
+
+impl<T> OrThrow<T> for std::result::Result<T, RandomXError> {
+    fn or_throw(self) -> Result<T> {
+        self.map_err(|error| Error::from_reason(error.to_string()))
+    }
+}
+
+/// Converts flags from JavaScript, rejecting bits that are not a RandomX flag rather than dropping them.
+fn flags(bits: u32) -> Result<RandomXFlag> {
+    RandomXFlag::from_bits(bits).ok_or_else(|| {
+        let unknown = bits & !RandomXFlag::all().bits();
+        Error::new(Status::InvalidArg, format!("unknown flag bits {unknown:#x}"))
+    })
+}
+
+/// Returns the recommended flags for this CPU.
+#[napi]
+pub fn recommended_flags() -> u32 {
+    RandomXFlag::get_recommended_flags().bits()
+}
+
+/// Returns the flags this build cannot use, see `RandomXFlag::disabled`.
+#[napi]
+pub fn disabled_flags() -> u32 {
+    RandomXFlag::disabled().bits()
+}
+
+/// Parses flag names such as `"jit,hard_aes"` or `"recommended+large_pages"`.
+#[napi]
+#[allow(clippy::needless_pass_by_value)] // `napi` only hands over owned strings
+pub fn parse_flags(names: String) -> Result<u32> {
+    let flags = names
+        .parse::<RandomXFlag>()
+        .map_err(|error| Error::new(Status::InvalidArg, error.to_string()))?;
+    Ok(flags.bits())
+}
+
+/// Returns the names of `flags`, e.g. `"hard_aes,jit"`.
+#[napi]
+pub fn format_flags(flags: u32) -> Result<String> {
+    Ok(self::flags(flags)?.to_string())
+}
+
+/// A RandomX cache, created with `createCache`.
+// Named after the JavaScript class: `napi` only applies a `js_name` to the methods of classes with a constructor.
+#[napi]
+pub struct RandomXCache {
+    cache: rx::RandomXCache,
+    vms: Arc<VmPool>,
+}
+
+#[napi]
+impl RandomXCache {
+    /// Returns the Blake2b-256 digest of the key of the cache.
+    #[napi]
+    pub fn key_digest(&self) -> Buffer {
+        self.cache.key_digest().to_vec().into()
+    }
+
+    /// Calculates the light-mode hash of `input` on the thread pool.
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn calculate_hash(&self, input: Buffer) -> AsyncTask<Hash> {
+        AsyncTask::new(Hash {
+            vms: Arc::clone(&self.vms),
+            input: input.into(),
+        })
+    }
+}
+
+/// A RandomX dataset, created with `createDataset`.
+#[napi]
+pub struct RandomXDataset {
+    vms: Arc<VmPool>,
+}
+
+#[napi]
+impl RandomXDataset {
+    /// Calculates the fast-mode hash of `input` on the thread pool.
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn calculate_hash(&self, input: Buffer) -> AsyncTask<Hash> {
+        AsyncTask::new(Hash {
+            vms: Arc::clone(&self.vms),
+            input: input.into(),
+        })
+    }
+}
+
+pub struct CreateCache {
+    flags: RandomXFlag,
+    key: Vec<u8>,
+}
+
+impl Task for CreateCache {
+    type JsValue = RandomXCache;
+    type Output = rx::RandomXCache;
+
+    fn compute(&mut self) -> Result<rx::RandomXCache> {
+        rx::RandomXCache::new(self.flags, &self.key).or_throw()
+    }
+
+    fn resolve(&mut self, _env: Env, cache: rx::RandomXCache) -> Result<RandomXCache> {
+        Ok(RandomXCache {
+            vms: VmPool::new(self.flags - RandomXFlag::FLAG_FULL_MEM, Source::Cache(cache.clone())),
+            cache,
+        })
+    }
+}
+
+/// Creates a cache for `key` on the thread pool.
+#[napi(ts_return_type = "Promise<RandomXCache>")]
+pub fn create_cache(flags: u32, key: Buffer) -> Result<AsyncTask<CreateCache>> {
+    Ok(AsyncTask::new(CreateCache {
+        flags: self::flags(flags)?,
+        key: key.into(),
+    }))
+}
+
+pub struct CreateDataset {
+    flags: RandomXFlag,
+    cache: rx::RandomXCache,
+    start: u32,
+}
+
+impl Task for CreateDataset {
+    type JsValue = RandomXDataset;
+    type Output = rx::RandomXDataset;
+
+    fn compute(&mut self) -> Result<rx::RandomXDataset> {
+        rx::RandomXDataset::new(self.flags, self.cache.clone(), self.start).or_throw()
+    }
+
+    fn resolve(&mut self, _env: Env, dataset: rx::RandomXDataset) -> Result<RandomXDataset> {
+        Ok(RandomXDataset {
+            vms: VmPool::new(self.flags | RandomXFlag::FLAG_FULL_MEM, Source::Dataset(dataset)),
+        })
+    }
+}
+
+/// Creates and initializes a dataset from `cache` on the thread pool, starting at item `start` (0 by default).
+#[napi(ts_return_type = "Promise<RandomXDataset>")]
+pub fn create_dataset(flags: u32, cache: &RandomXCache, start: Option<u32>) -> Result<AsyncTask<CreateDataset>> {
+    Ok(AsyncTask::new(CreateDataset {
+        flags: self::flags(flags)?,
+        cache: cache.cache.clone(),
+        start: start.unwrap_or(0),
+    }))
+}
+
+/// The cache or dataset of a JavaScript object.
+enum Source {
+    Cache(rx::RandomXCache),
+    Dataset(rx::RandomXDataset),
+}
+
+/// A VM that moves between the threads of the pool.
+struct PooledVm(RandomXVM);
+
+// SAFETY: `RandomXVM` is only `!Send` because it holds the raw pointer to the C++ VM. That VM has no affinity to the
+// thread that created it; RandomX only requires that no two threads use it at once. Its linked cache and dataset are
+// `Send + Sync`, and its key generation `Cell` is only touched through the `RandomXVM` itself. A `PooledVm` is owned exclusively at all times:
+// either it sits idle in a `VmPool`, reachable only through the pool's mutex, or it has been taken out by the single
+// `Hash` task that hashes with it on a libuv worker and puts it back when done. No `&PooledVm` or `&RandomXVM` is ever
+// handed out while it is in the pool or shared between threads, so moving it to another thread cannot race.
+unsafe impl Send for PooledVm {}
+
+/// The VMs of a JavaScript cache or dataset that are idle between hashes, shared by the object and its pending hashes.
+/// The last of them to go frees the VMs, and with them the cache or dataset.
+struct VmPool {
+    flags: RandomXFlag,
+    source: Source,
+    idle: Mutex<Vec<PooledVm>>,
+}
+
+impl VmPool {
+    fn new(flags: RandomXFlag, source: Source) -> Arc<VmPool> {
+        Arc::new(VmPool {
+            flags,
+            source,
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Takes an idle VM, or creates one if all of them are hashing.
+    fn take(&self) -> std::result::Result<PooledVm, RandomXError> {
+        if let Some(vm) = self.lock().pop() {
+            return Ok(vm);
+        }
+        let vm = match &self.source {
+            Source::Cache(cache) => RandomXVM::new(self.flags, Some(cache.clone()), None),
+            Source::Dataset(dataset) => RandomXVM::new(self.flags, None, Some(dataset.clone())),
+        };
+        vm.map(PooledVm)
+    }
+
+    /// Returns a VM taken with [`VmPool::take`] for the next hash.
+    fn put(&self, vm: PooledVm) {
+        self.lock().push(vm);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<PooledVm>> {
+        // The list is only pushed to and popped from, so a panic elsewhere cannot leave it inconsistent.
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub struct Hash {
+    vms: Arc<VmPool>,
+    input: Vec<u8>,
+}
+
+impl Task for Hash {
+    type JsValue = Buffer;
+    type Output = Vec<u8>;
+
+    fn compute(&mut self) -> Result<Vec<u8>> {
+        let vm = self.vms.take().or_throw()?;
+        let hash = vm.0.calculate_hash(&self.input).or_throw();
+        self.vms.put(vm);
+        hash
+    }
+
+    fn resolve(&mut self, _env: Env, hash: Vec<u8>) -> Result<Buffer> {
+        Ok(hash.into())
+    }
+}
+
+/// Verifies light-mode hashes, keeping caches for the `capacity` most recently used keys.
+#[napi]
+pub struct Verifier(Arc<verifier::Verifier>);
+
+#[napi]
+impl Verifier {
+    #[napi(constructor)]
+    pub fn new(flags: u32, capacity: u32) -> Result<Verifier> {
+        let verifier = verifier::Verifier::new(self::flags(flags)?, capacity as usize).or_throw()?;
+        Ok(Verifier(Arc::new(verifier)))
+    }
+
+    /// Resolves to `true` if `hash` is the hash of `input` under `key`, computed on the thread pool.
+    #[napi(ts_return_type = "Promise<boolean>")]
+    pub fn verify(&self, key: Buffer, input: Buffer, hash: Buffer) -> AsyncTask<Verify> {
+        AsyncTask::new(Verify {
+            verifier: Arc::clone(&self.0),
+            key: key.into(),
+            input: input.into(),
+            hash: Some(hash.into()),
+        })
+    }
+
+    /// Calculates the hash of `input` under `key` on the thread pool.
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn calculate_hash(&self, key: Buffer, input: Buffer) -> AsyncTask<Verify> {
+        AsyncTask::new(Verify {
+            verifier: Arc::clone(&self.0),
+            key: key.into(),
+            input: input.into(),
+            hash: None,
+        })
+    }
+
+    /// Returns the number of caches currently kept.
+    #[napi(getter)]
+    pub fn cache_count(&self) -> u32 {
+        u32::try_from(self.0.len()).unwrap_or(u32::MAX)
+    }
+}
+
+/// Calculates a hash with a [`Verifier`], and compares it to `hash` if given.
+pub struct Verify {
+    verifier: Arc<verifier::Verifier>,
+    key: Vec<u8>,
+    input: Vec<u8>,
+    hash: Option<Vec<u8>>,
+}
+
+impl Task for Verify {
+    type JsValue = Either<bool, Buffer>;
+    type Output = Vec<u8>;
+
+    fn compute(&mut self) -> Result<Vec<u8>> {
+        self.verifier.calculate_hash(&self.key, &self.input).or_throw()
+    }
+
+    fn resolve(&mut self, _env: Env, hash: Vec<u8>) -> Result<Either<bool, Buffer>> {
+        Ok(match &self.hash {
+            Some(expected) => Either::A(*expected == hash),
+            None => Either::B(hash.into()),
+        })
+    }
+}
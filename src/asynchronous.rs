@@ -0,0 +1,336 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Async (tokio) facade over the blocking RandomX objects.
+//!
+//! Cache and dataset construction run on tokio's blocking pool. Hashing is served by an [`AsyncHasher`], which owns a
+//! dedicated set of worker threads, each with its own [`RandomXVM`], fed through a bounded queue.
+//!
+//! All futures returned here are cancel-safe: dropping one never leaves a VM or queue in an inconsistent state. Work
+//! that has already started on a blocking thread runs to completion and its result is discarded.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    task,
+};
+
+use crate::{
+    worker::WorkerConfig,
+    CacheFlags,
+    DatasetFlags,
+    RandomXCache,
+    RandomXDataset,
+    RandomXError,
+    RandomXVM,
+    VmFlags,
+};
+
+impl RandomXCache {
+    /// Async version of [`RandomXCache::new`], run on tokio's blocking thread pool.
+    pub async fn new_async(flags: impl Into<CacheFlags>, key: Vec<u8>) -> Result<RandomXCache, RandomXError> {
+        let flags = flags.into();
+        run_blocking(move || RandomXCache::new(flags, &key)).await
+    }
+}
+
+impl RandomXDataset {
+    /// Async version of [`RandomXDataset::new`], run on tokio's blocking thread pool.
+    pub async fn new_async(
+        flags: impl Into<DatasetFlags>,
+        cache: RandomXCache,
+        start: u32,
+    ) -> Result<RandomXDataset, RandomXError> {
+        let flags = flags.into();
+        run_blocking(move || RandomXDataset::new(flags, cache, start)).await
+    }
+}
+
+async fn run_blocking<F, T>(f: F) -> Result<T, RandomXError>
+where
+    F: FnOnce() -> Result<T, RandomXError> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(f).await.map_err(|e| {
+        if e.is_cancelled() {
+            RandomXError::Cancelled
+        } else {
+            RandomXError::WorkerFailed
+        }
+    })?
+}
+
+struct HashRequest {
+    input: Vec<u8>,
+    reply: oneshot::Sender<Result<Vec<u8>, RandomXError>>,
+}
+
+/// Hashes inputs on a dedicated pool of worker threads, each owning a [`RandomXVM`].
+///
+/// Requests are queued in a bounded channel: [`AsyncHasher::hash`] waits for a free slot when the queue is full, while
+/// [`AsyncHasher::try_hash`] fails immediately. Workers poll the idle hook of their [`WorkerConfig`] before taking the
+/// next request. Dropping the hasher closes the queue; workers finish the requests already queued and exit.
+#[derive(Debug, Clone)]
+pub struct AsyncHasher {
+    sender: mpsc::Sender<HashRequest>,
+}
+
+impl AsyncHasher {
+    /// Starts `threads` workers, each creating a VM from `flags`, `cache` and `dataset` as in [`RandomXVM::new`].
+    ///
+    /// `queue_size` is the number of requests that can wait for a worker before callers are made to wait.
+    pub async fn new(
+        flags: impl Into<VmFlags>,
+        cache: Option<RandomXCache>,
+        dataset: Option<RandomXDataset>,
+        threads: usize,
+        queue_size: usize,
+    ) -> Result<AsyncHasher, RandomXError> {
+        Self::with_workers(flags, cache, dataset, threads, queue_size, &WorkerConfig::default()).await
+    }
+
+    /// Same as [`AsyncHasher::new`], with worker threads placed according to `workers`. Fails with the error of
+    /// [`WorkerConfig::apply`] if a worker cannot be placed.
+    pub async fn with_workers(
+        flags: impl Into<VmFlags>,
+        cache: Option<RandomXCache>,
+        dataset: Option<RandomXDataset>,
+        threads: usize,
+        queue_size: usize,
+        workers: &WorkerConfig,
+    ) -> Result<AsyncHasher, RandomXError> {
+        if threads == 0 || queue_size == 0 {
+            return Err(RandomXError::InvalidParameter {
+                name: "threads and queue_size",
+                reason: "must be greater than zero",
+            });
+        }
+        let flags = flags.into();
+        let (sender, receiver) = mpsc::channel(queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut ready = Vec::with_capacity(threads);
+        for index in 0..threads {
+            let (ready_tx, ready_rx) = oneshot::channel();
+            let receiver = receiver.clone();
+            let cache = cache.clone();
+            let dataset = dataset.clone();
+            let config = workers.clone();
+            let handle = workers.spawn(index, move || {
+                // The VM is not `Send`, so it is created on the thread that uses it.
+                match RandomXVM::new(flags, cache, dataset) {
+                    Ok(vm) => {
+                        let _ignore = ready_tx.send(Ok(()));
+                        serve(&vm, &receiver, &config);
+                    },
+                    Err(e) => {
+                        let _ignore = ready_tx.send(Err(e));
+                    },
+                }
+            })?;
+            ready.push((ready_rx, handle));
+        }
+        // The handles of the workers that started are dropped: they exit on their own once the queue is closed.
+        for (ready_rx, handle) in ready {
+            if let Ok(result) = ready_rx.await {
+                result?;
+            } else {
+                // The worker exited without reporting, because applying `workers` failed or it panicked.
+                return Err(match handle.join() {
+                    Ok(Err(e)) => e,
+                    _ => RandomXError::WorkerFailed,
+                });
+            }
+        }
+        Ok(AsyncHasher { sender })
+    }
+
+    /// Hashes `input`, waiting for queue capacity if all workers are busy.
+    ///
+    /// Fails with [`RandomXError::WorkerFailed`] if the workers exited, or the worker hashing `input` panicked.
+    pub async fn hash(&self, input: Vec<u8>) -> Result<Vec<u8>, RandomXError> {
+        let (reply, response) = oneshot::channel();
+        // The hasher keeps the queue open, so it only closes when no worker is left to receive from it.
+        self.sender
+            .send(HashRequest { input, reply })
+            .await
+            .map_err(|_| RandomXError::WorkerFailed)?;
+        response.await.map_err(|_| RandomXError::WorkerFailed)?
+    }
+
+    /// Hashes `input`, failing immediately instead of waiting if the queue is full.
+    pub async fn try_hash(&self, input: Vec<u8>) -> Result<Vec<u8>, RandomXError> {
+        let (reply, response) = oneshot::channel();
+        self.sender
+            .try_send(HashRequest { input, reply })
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => RandomXError::QueueFull,
+                mpsc::error::TrySendError::Closed(_) => RandomXError::WorkerFailed,
+            })?;
+        response.await.map_err(|_| RandomXError::WorkerFailed)?
+    }
+
+    /// Returns the number of requests that can be queued before callers are made to wait.
+    pub fn available_capacity(&self) -> usize {
+        self.sender.capacity()
+    }
+}
+
+fn serve(vm: &RandomXVM, receiver: &Mutex<mpsc::Receiver<HashRequest>>, workers: &WorkerConfig) {
+    loop {
+        workers.wait_while_idle();
+        // Only one idle worker waits on the queue at a time; the others wait for the lock.
+        let request = match receiver.lock() {
+            Ok(mut receiver) => receiver.blocking_recv(),
+            Err(_) => None,
+        };
+        let Some(request) = request else {
+            return;
+        };
+        // The caller dropped its future, so nobody is waiting for this hash.
+        if request.reply.is_closed() {
+            continue;
+        }
+        let _ignore = request.reply.send(vm.calculate_hash(&request.input));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{self, Future},
+        pin::Pin,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        task::Poll,
+    };
+
+    use crate::{
+        asynchronous::AsyncHasher,
+        worker::{CoreAffinity, WorkerConfig},
+        RandomXCache,
+        RandomXDataset,
+        RandomXError,
+        RandomXFlag,
+        RandomXVM,
+        VmFlags,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn async_light_mode_vectors() {
+        let flags = RandomXFlag::get_recommended_flags();
+        let cache = RandomXCache::new_async(flags, b"test key 000".to_vec()).await.unwrap();
+        let hasher = AsyncHasher::new(VmFlags::new(flags).unwrap(), Some(cache), None, 2, 4)
+            .await
+            .unwrap();
+        let inputs = [
+            (
+                b"This is a test".to_vec(),
+                "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+            ),
+            (
+                b"Lorem ipsum dolor sit amet".to_vec(),
+                "300a0adb47603dedb42228ccb2b211104f4da45af709cd7547cd049e9489c969",
+            ),
+        ];
+        for (input, expected) in inputs {
+            assert_eq!(hasher.hash(input).await.unwrap(), hex::decode(expected).unwrap());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn async_dataset_matches_light_mode() {
+        if crate::tests::datasets_disabled() {
+            return;
+        }
+        let flags = RandomXFlag::get_recommended_flags();
+        let cache = RandomXCache::new_async(flags, b"Key".to_vec()).await.unwrap();
+        let dataset = RandomXDataset::new_async(flags, cache.clone(), 0).await.unwrap();
+        let fast = AsyncHasher::new(flags | RandomXFlag::FLAG_FULL_MEM, None, Some(dataset), 1, 1)
+            .await
+            .unwrap();
+        let light = AsyncHasher::new(flags, Some(cache), None, 1, 1).await.unwrap();
+        assert_eq!(
+            fast.hash(b"Input".to_vec()).await.unwrap(),
+            light.hash(b"Input".to_vec()).await.unwrap()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn async_hasher_applies_backpressure() {
+        let flags = RandomXFlag::get_recommended_flags();
+        let cache = RandomXCache::new_async(flags, b"Key".to_vec()).await.unwrap();
+        // The idle hook keeps the single worker from taking requests, so the one-slot queue fills up.
+        let paused = Arc::new(AtomicBool::new(true));
+        let hook = paused.clone();
+        let workers = WorkerConfig::default().with_idle_hook(move || hook.load(Ordering::SeqCst));
+        let hasher = AsyncHasher::with_workers(flags, Some(cache.clone()), None, 1, 1, &workers)
+            .await
+            .unwrap();
+        assert_eq!(hasher.available_capacity(), 1);
+        let mut queued = Box::pin(hasher.hash(b"Input".to_vec()));
+        assert!(poll_once(queued.as_mut()).await.is_pending());
+        assert_eq!(hasher.available_capacity(), 0);
+        assert!(matches!(
+            hasher.try_hash(b"Rejected".to_vec()).await,
+            Err(RandomXError::QueueFull)
+        ));
+        let mut waiting = Box::pin(hasher.hash(b"Waiting".to_vec()));
+        assert!(poll_once(waiting.as_mut()).await.is_pending());
+        // Dropping a request that waits for a slot must not wedge the queue.
+        let mut dropped = Box::pin(hasher.hash(b"dropped".to_vec()));
+        assert!(poll_once(dropped.as_mut()).await.is_pending());
+        drop(dropped);
+        assert!(poll_once(waiting.as_mut()).await.is_pending());
+
+        paused.store(false, Ordering::SeqCst);
+        let light = RandomXVM::new(flags, Some(cache), None).unwrap();
+        assert_eq!(queued.await.unwrap(), light.calculate_hash(b"Input").unwrap());
+        assert_eq!(waiting.await.unwrap(), light.calculate_hash(b"Waiting").unwrap());
+        assert_eq!(
+            hasher.try_hash(b"Input".to_vec()).await.unwrap(),
+            light.calculate_hash(b"Input").unwrap()
+        );
+        assert_eq!(hasher.available_capacity(), 1);
+        assert!(AsyncHasher::new(flags, None, None, 0, 1).await.is_err());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn async_hasher_reports_worker_placement_errors() {
+        let flags = RandomXFlag::get_recommended_flags();
+        let cache = RandomXCache::new_async(flags, b"Key".to_vec()).await.unwrap();
+        let workers = WorkerConfig::default().with_affinity(CoreAffinity::Cores(vec![usize::MAX]));
+        assert!(matches!(
+            AsyncHasher::with_workers(flags, Some(cache), None, 1, 1, &workers).await,
+            Err(RandomXError::InvalidParameter { name: "core", .. })
+        ));
+    }
+
+    // Polls `future` once and returns the result.
+    async fn poll_once<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        let mut future = Some(future);
+        future::poll_fn(|cx| Poll::Ready(future.take().unwrap().poll(cx))).await
+    }
+}
@@ -20,7 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use libc::{c_uint, c_ulong, c_void};
+use core::ffi::{c_uint, c_ulong, c_void};
 pub const RANDOMX_HASH_SIZE: u32 = 32;
 
 #[repr(C)]
@@ -38,6 +38,7 @@ pub struct randomx_vm {
     _unused: [u8; 0],
 }
 
+#[cfg(randomx_ffi)]
 extern "C" {
     pub fn randomx_alloc_cache(flags: c_uint) -> *mut randomx_cache;
     pub fn randomx_init_cache(cache: *mut randomx_cache, key: *const c_void, keySize: usize);
@@ -77,12 +78,167 @@ extern "C" {
     pub fn randomx_get_flags() -> c_uint;
 }
 
-#[cfg(test)]
+/// The entry points of one build of the RandomX library.
+///
+/// The unprefixed symbols above belong to the library built with the active `RandomXConfig`. With the `multi-variant`
+/// feature, `build.rs` also builds the other presets with their symbols prefixed, see `prefixed_backend!`.
+#[derive(Debug)]
+pub struct Backend {
+    pub alloc_cache: unsafe extern "C" fn(flags: c_uint) -> *mut randomx_cache,
+    pub init_cache: unsafe extern "C" fn(cache: *mut randomx_cache, key: *const c_void, key_size: usize),
+    pub release_cache: unsafe extern "C" fn(cache: *mut randomx_cache),
+    pub alloc_dataset: unsafe extern "C" fn(flags: c_uint) -> *mut randomx_dataset,
+    pub dataset_item_count: unsafe extern "C" fn() -> c_ulong,
+    pub init_dataset: unsafe extern "C" fn(
+        dataset: *mut randomx_dataset,
+        cache: *mut randomx_cache,
+        start_item: c_ulong,
+        item_count: c_ulong,
+    ),
+    pub get_dataset_memory: unsafe extern "C" fn(dataset: *mut randomx_dataset) -> *mut c_void,
+    pub release_dataset: unsafe extern "C" fn(dataset: *mut randomx_dataset),
+    pub create_vm: unsafe extern "C" fn(
+        flags: c_uint,
+        cache: *mut randomx_cache,
+        dataset: *mut randomx_dataset,
+    ) -> *mut randomx_vm,
+    pub vm_set_cache: unsafe extern "C" fn(machine: *mut randomx_vm, cache: *mut randomx_cache),
+    pub vm_set_dataset: unsafe extern "C" fn(machine: *mut randomx_vm, dataset: *mut randomx_dataset),
+    pub destroy_vm: unsafe extern "C" fn(machine: *mut randomx_vm),
+    pub calculate_hash:
+        unsafe extern "C" fn(machine: *mut randomx_vm, input: *const c_void, input_size: usize, output: *mut c_void),
+    pub calculate_hash_first: unsafe extern "C" fn(machine: *mut randomx_vm, input: *const c_void, input_size: usize),
+    pub calculate_hash_next: unsafe extern "C" fn(
+        machine: *mut randomx_vm,
+        input_next: *const c_void,
+        input_size_next: usize,
+        output: *mut c_void,
+    ),
+    pub calculate_hash_last: unsafe extern "C" fn(machine: *mut randomx_vm, output: *mut c_void),
+}
+
+/// The library built with the active `RandomXConfig`.
+#[cfg(randomx_ffi)]
+pub static DEFAULT_BACKEND: Backend = Backend {
+    alloc_cache: randomx_alloc_cache,
+    init_cache: randomx_init_cache,
+    release_cache: randomx_release_cache,
+    alloc_dataset: randomx_alloc_dataset,
+    dataset_item_count: randomx_dataset_item_count,
+    init_dataset: randomx_init_dataset,
+    get_dataset_memory: randomx_get_dataset_memory,
+    release_dataset: randomx_release_dataset,
+    create_vm: randomx_create_vm,
+    vm_set_cache: randomx_vm_set_cache,
+    vm_set_dataset: randomx_vm_set_dataset,
+    destroy_vm: randomx_destroy_vm,
+    calculate_hash: randomx_calculate_hash,
+    calculate_hash_first: randomx_calculate_hash_first,
+    calculate_hash_next: randomx_calculate_hash_next,
+    calculate_hash_last: randomx_calculate_hash_last,
+};
+
+/// The pure-Rust implementation, when `build.rs` did not build or link the C++ library.
+#[cfg(not(randomx_ffi))]
+pub static DEFAULT_BACKEND: Backend = crate::pure::backend::BACKEND;
+
+/// Declares a module with the entry points of a RandomX build whose symbols `build.rs` prefixed with `$prefix`.
+#[allow(unused_macros)]
+macro_rules! prefixed_backend {
+    ($module:ident, $prefix:literal) => {
+        pub mod $module {
+            use core::ffi::{c_uint, c_ulong, c_void};
+
+            use crate::bindings::{randomx_cache, randomx_dataset, randomx_vm, Backend};
+
+            extern "C" {
+                #[link_name = concat!($prefix, "randomx_alloc_cache")]
+                fn alloc_cache(flags: c_uint) -> *mut randomx_cache;
+                #[link_name = concat!($prefix, "randomx_init_cache")]
+                fn init_cache(cache: *mut randomx_cache, key: *const c_void, key_size: usize);
+                #[link_name = concat!($prefix, "randomx_release_cache")]
+                fn release_cache(cache: *mut randomx_cache);
+                #[link_name = concat!($prefix, "randomx_alloc_dataset")]
+                fn alloc_dataset(flags: c_uint) -> *mut randomx_dataset;
+                #[link_name = concat!($prefix, "randomx_dataset_item_count")]
+                fn dataset_item_count() -> c_ulong;
+                #[link_name = concat!($prefix, "randomx_init_dataset")]
+                fn init_dataset(
+                    dataset: *mut randomx_dataset,
+                    cache: *mut randomx_cache,
+                    start_item: c_ulong,
+                    item_count: c_ulong,
+                );
+                #[link_name = concat!($prefix, "randomx_get_dataset_memory")]
+                fn get_dataset_memory(dataset: *mut randomx_dataset) -> *mut c_void;
+                #[link_name = concat!($prefix, "randomx_release_dataset")]
+                fn release_dataset(dataset: *mut randomx_dataset);
+                #[link_name = concat!($prefix, "randomx_create_vm")]
+                fn create_vm(
+                    flags: c_uint,
+                    cache: *mut randomx_cache,
+                    dataset: *mut randomx_dataset,
+                ) -> *mut randomx_vm;
+                #[link_name = concat!($prefix, "randomx_vm_set_cache")]
+                fn vm_set_cache(machine: *mut randomx_vm, cache: *mut randomx_cache);
+                #[link_name = concat!($prefix, "randomx_vm_set_dataset")]
+                fn vm_set_dataset(machine: *mut randomx_vm, dataset: *mut randomx_dataset);
+                #[link_name = concat!($prefix, "randomx_destroy_vm")]
+                fn destroy_vm(machine: *mut randomx_vm);
+                #[link_name = concat!($prefix, "randomx_calculate_hash")]
+                fn calculate_hash(
+                    machine: *mut randomx_vm,
+                    input: *const c_void,
+                    input_size: usize,
+                    output: *mut c_void,
+                );
+                #[link_name = concat!($prefix, "randomx_calculate_hash_first")]
+                fn calculate_hash_first(machine: *mut randomx_vm, input: *const c_void, input_size: usize);
+                #[link_name = concat!($prefix, "randomx_calculate_hash_next")]
+                fn calculate_hash_next(
+                    machine: *mut randomx_vm,
+                    input_next: *const c_void,
+                    input_size_next: usize,
+                    output: *mut c_void,
+                );
+                #[link_name = concat!($prefix, "randomx_calculate_hash_last")]
+                fn calculate_hash_last(machine: *mut randomx_vm, output: *mut c_void);
+            }
+
+            pub static BACKEND: Backend = Backend {
+                alloc_cache,
+                init_cache,
+                release_cache,
+                alloc_dataset,
+                dataset_item_count,
+                init_dataset,
+                get_dataset_memory,
+                release_dataset,
+                create_vm,
+                vm_set_cache,
+                vm_set_dataset,
+                destroy_vm,
+                calculate_hash,
+                calculate_hash_first,
+                calculate_hash_next,
+                calculate_hash_last,
+            };
+        }
+    };
+}
+
+#[cfg(randomx_variant_monero)]
+prefixed_backend!(monero, "rxmonero_");
+#[cfg(randomx_variant_wownero)]
+prefixed_backend!(wownero, "rxwownero_");
+#[cfg(randomx_variant_arqma)]
+prefixed_backend!(arqma, "rxarqma_");
+
+#[cfg(all(test, randomx_ffi))]
 mod tests {
+    use core::ffi::{c_uint, c_void};
     use std::ptr;
 
-    use libc::{c_uint, c_void};
-
     use super::*;
 
     #[test]
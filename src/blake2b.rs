@@ -0,0 +1,238 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Blake2b, for key digests, and the Blake2b-based byte generator that seeds SuperscalarHash in `pure`.
+
+#![forbid(unsafe_code)]
+// RandomX is defined on the bits of fixed-width integers, which are converted between widths and signedness as-is.
+#![allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+
+use core::convert::TryInto;
+
+const IV: [u64; 8] = [
+    0x6a09_e667_f3bc_c908,
+    0xbb67_ae85_84ca_a73b,
+    0x3c6e_f372_fe94_f82b,
+    0xa54f_f53a_5f1d_36f1,
+    0x510e_527f_ade6_82d1,
+    0x9b05_688c_2b3e_6c1f,
+    0x1f83_d9ab_fb41_bd6b,
+    0x5be0_cd19_137e_2179,
+];
+
+const SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+];
+
+const BLOCK_SIZE: usize = 128;
+
+/// An unkeyed Blake2b state with an output size of up to 64 bytes.
+pub(crate) struct Blake2b {
+    h: [u64; 8],
+    counter: u128,
+    buffer: [u8; BLOCK_SIZE],
+    buffered: usize,
+    out_len: usize,
+}
+
+impl Blake2b {
+    pub fn new(out_len: usize) -> Blake2b {
+        assert!((1..=64).contains(&out_len), "invalid Blake2b output size {}", out_len);
+        let mut h = IV;
+        h[0] ^= 0x0101_0000 ^ out_len as u64;
+        Blake2b {
+            h,
+            counter: 0,
+            buffer: [0; BLOCK_SIZE],
+            buffered: 0,
+            out_len,
+        }
+    }
+
+    pub fn update(&mut self, mut input: &[u8]) -> &mut Blake2b {
+        while !input.is_empty() {
+            // The last block is compressed by `finalize`, so a full buffer is only flushed once more input arrives.
+            if self.buffered == BLOCK_SIZE {
+                self.counter += BLOCK_SIZE as u128;
+                let block = self.buffer;
+                self.compress(&block, false);
+                self.buffered = 0;
+            }
+            let n = input.len().min(BLOCK_SIZE - self.buffered);
+            self.buffer[self.buffered..self.buffered + n].copy_from_slice(&input[..n]);
+            self.buffered += n;
+            input = &input[n..];
+        }
+        self
+    }
+
+    /// Writes the hash to `out`, which must be `out_len` bytes long.
+    pub fn finalize(&mut self, out: &mut [u8]) {
+        assert_eq!(out.len(), self.out_len);
+        self.counter += self.buffered as u128;
+        for byte in &mut self.buffer[self.buffered..] {
+            *byte = 0;
+        }
+        let block = self.buffer;
+        self.compress(&block, true);
+        let mut bytes = [0u8; 64];
+        for (chunk, word) in bytes.chunks_exact_mut(8).zip(self.h.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out.copy_from_slice(&bytes[..self.out_len]);
+    }
+
+    fn compress(&mut self, block: &[u8; BLOCK_SIZE], last: bool) {
+        let mut m = [0u64; 16];
+        for (word, chunk) in m.iter_mut().zip(block.chunks_exact(8)) {
+            *word = u64::from_le_bytes(chunk.try_into().unwrap());
+        }
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.h);
+        v[8..].copy_from_slice(&IV);
+        v[12] ^= self.counter as u64;
+        v[13] ^= (self.counter >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+        for s in &SIGMA {
+            g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+        for i in 0..8 {
+            self.h[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+}
+
+#[inline]
+fn g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// Hashes `input` into `out`, whose length is the output size.
+pub(crate) fn blake2b(out: &mut [u8], input: &[u8]) {
+    Blake2b::new(out.len()).update(input).finalize(out);
+}
+
+/// The byte generator of SuperscalarHash: a 64-byte buffer that is rehashed with Blake2b when it runs out.
+#[cfg(feature = "pure-rust")]
+pub(crate) struct Blake2Generator {
+    data: [u8; 64],
+    index: usize,
+}
+
+#[cfg(feature = "pure-rust")]
+impl Blake2Generator {
+    // The last 4 bytes of the seed buffer hold the nonce.
+    const MAX_SEED_SIZE: usize = 60;
+
+    pub fn new(seed: &[u8], nonce: u32) -> Blake2Generator {
+        let mut data = [0u8; 64];
+        let len = seed.len().min(Self::MAX_SEED_SIZE);
+        data[..len].copy_from_slice(&seed[..len]);
+        data[Self::MAX_SEED_SIZE..].copy_from_slice(&nonce.to_le_bytes());
+        Blake2Generator { data, index: 64 }
+    }
+
+    pub fn get_byte(&mut self) -> u8 {
+        self.check_data(1);
+        let byte = self.data[self.index];
+        self.index += 1;
+        byte
+    }
+
+    pub fn get_u32(&mut self) -> u32 {
+        self.check_data(4);
+        let value = u32::from_le_bytes(self.data[self.index..self.index + 4].try_into().unwrap());
+        self.index += 4;
+        value
+    }
+
+    fn check_data(&mut self, needed: usize) {
+        if self.index + needed > self.data.len() {
+            let data = self.data;
+            blake2b(&mut self.data, &data);
+            self.index = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blake2b_known_answers() {
+        let mut out = [0u8; 64];
+        blake2b(&mut out, b"abc");
+        assert_eq!(
+            hex::encode(out),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        let mut out = [0u8; 32];
+        blake2b(&mut out, b"");
+        assert_eq!(
+            hex::encode(out),
+            "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+        );
+    }
+
+    #[test]
+    fn blake2b_incremental_matches_one_shot() {
+        let input: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let mut expected = [0u8; 64];
+        blake2b(&mut expected, &input);
+        for split in [0, 1, 127, 128, 129, 256, 999, 1000] {
+            let mut out = [0u8; 64];
+            Blake2b::new(64)
+                .update(&input[..split])
+                .update(&input[split..])
+                .finalize(&mut out);
+            assert_eq!(out, expected, "split at {}", split);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Compile-time RandomX parameters.
+//!
+//! RandomX forks such as RandomWOW (Wownero) and RandomARQ (ArQmA) change the `RANDOMX_*` defines in RandomX's
+//! `configuration.h`. `build.rs` patches them before building the library:
+//!
+//! * the `randomx-wow` and `randomx-arq` features select a preset, and
+//! * the `RANDOMX_CONFIG` environment variable names a file of `RANDOMX_NAME = value` lines that override the preset
+//!   (or the upstream parameters). `RANDOMX_ARGON_SALT` takes a string with C-style `\xNN` escapes.
+//!
+//! [`RandomXConfig::active`] returns the parameter set the linked library was built with.
+
+mod params;
+
+pub use params::{InstructionFrequencies, RandomXConfig};
+
+static ACTIVE: RandomXConfig = include!(concat!(env!("OUT_DIR"), "/randomx_config.rs"));
+
+impl RandomXConfig {
+    /// Returns the parameter set the linked RandomX library was built with.
+    pub fn active() -> &'static RandomXConfig {
+        &ACTIVE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{InstructionFrequencies, RandomXConfig},
+        RandomXCache,
+        RandomXFlag,
+        RandomXVM,
+    };
+
+    type Vector = (&'static [u8], &'static [u8], &'static str);
+
+    /// Returns the light-mode vectors `(key, input, hash)` published with the RandomX implementation of a preset.
+    fn published_vectors(config: &RandomXConfig) -> &'static [Vector] {
+        match config.name {
+            // https://github.com/tevador/RandomX/blob/040f4500a6e79d54d84a668013a94507045e786f/src/tests/tests.cpp#L963-L985
+            "monero" => &[
+                (
+                    b"test key 000",
+                    b"This is a test",
+                    "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f",
+                ),
+                (
+                    b"test key 000",
+                    b"Lorem ipsum dolor sit amet",
+                    "300a0adb47603dedb42228ccb2b211104f4da45af709cd7547cd049e9489c969",
+                ),
+                (
+                    b"test key 000",
+                    b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua",
+                    "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8",
+                ),
+                (
+                    b"test key 001",
+                    b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua",
+                    "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc",
+                ),
+            ],
+            // The vectors in src/tests/tests.cpp of the RandomWOW and RandomARQ forks are still to be added.
+            _ => &[],
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        any(feature = "randomx-wow", feature = "randomx-arq"),
+        ignore = "the published RandomWOW and RandomARQ vectors are not bundled yet"
+    )]
+    fn active_preset_matches_published_vectors() {
+        let config = RandomXConfig::active();
+        if config.name == "custom" {
+            // Parameters from RANDOMX_CONFIG have no published vectors to check against.
+            return;
+        }
+        let vectors = published_vectors(config);
+        assert!(
+            !vectors.is_empty(),
+            "no published vectors for the {} preset",
+            config.name
+        );
+        let flags = RandomXFlag::get_recommended_flags();
+        for (key, input, expected) in vectors {
+            let cache = RandomXCache::new(flags, key).unwrap();
+            let vm = RandomXVM::new(flags, Some(cache), None).unwrap();
+            assert_eq!(
+                hex::encode(vm.calculate_hash(input).unwrap()),
+                *expected,
+                "{}",
+                config.name
+            );
+        }
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for config in [RandomXConfig::MONERO, RandomXConfig::WOWNERO, RandomXConfig::ARQMA] {
+            assert_eq!(config.validate(), Ok(()), "{}", config.name);
+        }
+        assert_eq!(RandomXConfig::active().validate(), Ok(()));
+        #[cfg(not(any(feature = "randomx-wow", feature = "randomx-arq")))]
+        assert_eq!(RandomXConfig::active().name, RandomXConfig::MONERO.name);
+        #[cfg(feature = "randomx-wow")]
+        assert_eq!(RandomXConfig::active().name, RandomXConfig::WOWNERO.name);
+        #[cfg(feature = "randomx-arq")]
+        assert_eq!(RandomXConfig::active().name, RandomXConfig::ARQMA.name);
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let mut config = RandomXConfig::MONERO;
+        *config.parameter_mut("RANDOMX_SCRATCHPAD_L3").unwrap() = 3_000_000;
+        assert!(config.validate().is_err());
+        assert!(config.parameter_mut("RANDOMX_ARGON_SALT").is_none());
+
+        let config = RandomXConfig {
+            frequencies: InstructionFrequencies {
+                nop: 1,
+                ..InstructionFrequencies::DEFAULT
+            },
+            ..RandomXConfig::MONERO
+        };
+        assert_eq!(config.validate(), Err("RANDOMX_FREQ_* must add up to 256"));
+    }
+}
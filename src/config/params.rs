@@ -0,0 +1,322 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! RandomX parameter sets.
+//!
+//! This file is also compiled into `build.rs`, so it must only depend on `core`.
+
+/// Frequencies of the RandomX VM instructions, out of 256 per program slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstructionFrequencies {
+    pub iadd_rs: u32,
+    pub iadd_m: u32,
+    pub isub_r: u32,
+    pub isub_m: u32,
+    pub imul_r: u32,
+    pub imul_m: u32,
+    pub imulh_r: u32,
+    pub imulh_m: u32,
+    pub ismulh_r: u32,
+    pub ismulh_m: u32,
+    pub imul_rcp: u32,
+    pub ineg_r: u32,
+    pub ixor_r: u32,
+    pub ixor_m: u32,
+    pub iror_r: u32,
+    pub irol_r: u32,
+    pub iswap_r: u32,
+    pub fswap_r: u32,
+    pub fadd_r: u32,
+    pub fadd_m: u32,
+    pub fsub_r: u32,
+    pub fsub_m: u32,
+    pub fscal_r: u32,
+    pub fmul_r: u32,
+    pub fdiv_m: u32,
+    pub fsqrt_r: u32,
+    pub cbranch: u32,
+    pub cfround: u32,
+    pub istore: u32,
+    pub nop: u32,
+}
+
+/// The compile-time parameters of a RandomX variant, i.e. the values of the `RANDOMX_*` defines in RandomX's
+/// `configuration.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RandomXConfig {
+    /// Name of the parameter set, e.g. `"monero"`, or `"custom"` for a `RANDOMX_CONFIG` file.
+    pub name: &'static str,
+    /// Cache size in KiB.
+    pub argon_memory: u32,
+    pub argon_iterations: u32,
+    pub argon_lanes: u32,
+    pub argon_salt: &'static [u8],
+    pub cache_accesses: u32,
+    pub superscalar_latency: u32,
+    /// Dataset base size in bytes.
+    pub dataset_base_size: u32,
+    /// Dataset extra size in bytes.
+    pub dataset_extra_size: u32,
+    pub program_size: u32,
+    pub program_iterations: u32,
+    pub program_count: u32,
+    /// Scratchpad L3 size in bytes.
+    pub scratchpad_l3: u32,
+    /// Scratchpad L2 size in bytes.
+    pub scratchpad_l2: u32,
+    /// Scratchpad L1 size in bytes.
+    pub scratchpad_l1: u32,
+    pub jump_bits: u32,
+    pub jump_offset: u32,
+    pub frequencies: InstructionFrequencies,
+}
+
+/// Number of numeric `RANDOMX_*` defines, i.e. all of them except `RANDOMX_ARGON_SALT`.
+pub const PARAMETER_COUNT: usize = 45;
+
+macro_rules! parameters {
+    ($($($field:ident).+ => $define:literal,)*) => {
+        impl RandomXConfig {
+            /// Returns the numeric parameters with the names of the `configuration.h` defines they set.
+            pub fn parameters(&self) -> [(&'static str, u32); PARAMETER_COUNT] {
+                [$(($define, self.$($field).+),)*]
+            }
+
+            /// Returns a mutable reference to the parameter set by the `configuration.h` define `name`.
+            pub fn parameter_mut(&mut self, name: &str) -> Option<&mut u32> {
+                match name {
+                    $($define => Some(&mut self.$($field).+),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+parameters! {
+    argon_memory => "RANDOMX_ARGON_MEMORY",
+    argon_iterations => "RANDOMX_ARGON_ITERATIONS",
+    argon_lanes => "RANDOMX_ARGON_LANES",
+    cache_accesses => "RANDOMX_CACHE_ACCESSES",
+    superscalar_latency => "RANDOMX_SUPERSCALAR_LATENCY",
+    dataset_base_size => "RANDOMX_DATASET_BASE_SIZE",
+    dataset_extra_size => "RANDOMX_DATASET_EXTRA_SIZE",
+    program_size => "RANDOMX_PROGRAM_SIZE",
+    program_iterations => "RANDOMX_PROGRAM_ITERATIONS",
+    program_count => "RANDOMX_PROGRAM_COUNT",
+    scratchpad_l3 => "RANDOMX_SCRATCHPAD_L3",
+    scratchpad_l2 => "RANDOMX_SCRATCHPAD_L2",
+    scratchpad_l1 => "RANDOMX_SCRATCHPAD_L1",
+    jump_bits => "RANDOMX_JUMP_BITS",
+    jump_offset => "RANDOMX_JUMP_OFFSET",
+    frequencies.iadd_rs => "RANDOMX_FREQ_IADD_RS",
+    frequencies.iadd_m => "RANDOMX_FREQ_IADD_M",
+    frequencies.isub_r => "RANDOMX_FREQ_ISUB_R",
+    frequencies.isub_m => "RANDOMX_FREQ_ISUB_M",
+    frequencies.imul_r => "RANDOMX_FREQ_IMUL_R",
+    frequencies.imul_m => "RANDOMX_FREQ_IMUL_M",
+    frequencies.imulh_r => "RANDOMX_FREQ_IMULH_R",
+    frequencies.imulh_m => "RANDOMX_FREQ_IMULH_M",
+    frequencies.ismulh_r => "RANDOMX_FREQ_ISMULH_R",
+    frequencies.ismulh_m => "RANDOMX_FREQ_ISMULH_M",
+    frequencies.imul_rcp => "RANDOMX_FREQ_IMUL_RCP",
+    frequencies.ineg_r => "RANDOMX_FREQ_INEG_R",
+    frequencies.ixor_r => "RANDOMX_FREQ_IXOR_R",
+    frequencies.ixor_m => "RANDOMX_FREQ_IXOR_M",
+    frequencies.iror_r => "RANDOMX_FREQ_IROR_R",
+    frequencies.irol_r => "RANDOMX_FREQ_IROL_R",
+    frequencies.iswap_r => "RANDOMX_FREQ_ISWAP_R",
+    frequencies.fswap_r => "RANDOMX_FREQ_FSWAP_R",
+    frequencies.fadd_r => "RANDOMX_FREQ_FADD_R",
+    frequencies.fadd_m => "RANDOMX_FREQ_FADD_M",
+    frequencies.fsub_r => "RANDOMX_FREQ_FSUB_R",
+    frequencies.fsub_m => "RANDOMX_FREQ_FSUB_M",
+    frequencies.fscal_r => "RANDOMX_FREQ_FSCAL_R",
+    frequencies.fmul_r => "RANDOMX_FREQ_FMUL_R",
+    frequencies.fdiv_m => "RANDOMX_FREQ_FDIV_M",
+    frequencies.fsqrt_r => "RANDOMX_FREQ_FSQRT_R",
+    frequencies.cbranch => "RANDOMX_FREQ_CBRANCH",
+    frequencies.cfround => "RANDOMX_FREQ_CFROUND",
+    frequencies.istore => "RANDOMX_FREQ_ISTORE",
+    frequencies.nop => "RANDOMX_FREQ_NOP",
+}
+
+impl InstructionFrequencies {
+    /// The instruction frequencies of upstream RandomX.
+    pub const DEFAULT: InstructionFrequencies = InstructionFrequencies {
+        iadd_rs: 16,
+        iadd_m: 7,
+        isub_r: 16,
+        isub_m: 7,
+        imul_r: 16,
+        imul_m: 4,
+        imulh_r: 4,
+        imulh_m: 1,
+        ismulh_r: 4,
+        ismulh_m: 1,
+        imul_rcp: 8,
+        ineg_r: 2,
+        ixor_r: 15,
+        ixor_m: 5,
+        iror_r: 8,
+        irol_r: 2,
+        iswap_r: 4,
+        fswap_r: 4,
+        fadd_r: 16,
+        fadd_m: 5,
+        fsub_r: 16,
+        fsub_m: 5,
+        fscal_r: 6,
+        fmul_r: 32,
+        fdiv_m: 4,
+        fsqrt_r: 6,
+        cbranch: 25,
+        cfround: 1,
+        istore: 16,
+        nop: 0,
+    };
+}
+
+impl RandomXConfig {
+    /// Upstream RandomX, as used by Monero and Tari.
+    pub const MONERO: RandomXConfig = RandomXConfig {
+        name: "monero",
+        argon_memory: 262_144,
+        argon_iterations: 3,
+        argon_lanes: 1,
+        argon_salt: b"RandomX\x03",
+        cache_accesses: 8,
+        superscalar_latency: 170,
+        dataset_base_size: 2_147_483_648,
+        dataset_extra_size: 33_554_368,
+        program_size: 256,
+        program_iterations: 2048,
+        program_count: 8,
+        scratchpad_l3: 2_097_152,
+        scratchpad_l2: 262_144,
+        scratchpad_l1: 16_384,
+        jump_bits: 8,
+        jump_offset: 8,
+        frequencies: InstructionFrequencies::DEFAULT,
+    };
+    /// RandomWOW, as used by Wownero.
+    pub const WOWNERO: RandomXConfig = RandomXConfig {
+        name: "wownero",
+        argon_salt: b"RandomWOW\x01",
+        program_iterations: 1024,
+        program_count: 16,
+        scratchpad_l3: 1_048_576,
+        scratchpad_l2: 131_072,
+        frequencies: InstructionFrequencies {
+            iadd_rs: 25,
+            iror_r: 10,
+            irol_r: 0,
+            fswap_r: 8,
+            fadd_r: 20,
+            fsub_r: 20,
+            fmul_r: 20,
+            cbranch: 16,
+            ..InstructionFrequencies::DEFAULT
+        },
+        ..RandomXConfig::MONERO
+    };
+    /// RandomARQ, as used by ArQmA.
+    pub const ARQMA: RandomXConfig = RandomXConfig {
+        name: "arqma",
+        argon_iterations: 1,
+        argon_salt: b"RandomARQ\x01",
+        program_iterations: 1024,
+        program_count: 4,
+        scratchpad_l3: 262_144,
+        scratchpad_l2: 131_072,
+        ..RandomXConfig::MONERO
+    };
+
+    /// Checks the constraints RandomX places on its parameters, see the `static_assert`s in RandomX's `common.hpp`.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let checks = [
+            (
+                self.argon_memory.is_power_of_two() && self.argon_memory >= 8,
+                "RANDOMX_ARGON_MEMORY must be a power of 2 and at least 8",
+            ),
+            (
+                self.argon_iterations > 0,
+                "RANDOMX_ARGON_ITERATIONS must be greater than 0",
+            ),
+            (self.argon_lanes > 0, "RANDOMX_ARGON_LANES must be greater than 0"),
+            (
+                self.argon_salt.len() >= 8,
+                "RANDOMX_ARGON_SALT must be at least 8 bytes long",
+            ),
+            (self.cache_accesses > 1, "RANDOMX_CACHE_ACCESSES must be greater than 1"),
+            (
+                self.superscalar_latency > 0 && self.superscalar_latency <= 10_000,
+                "RANDOMX_SUPERSCALAR_LATENCY must be between 1 and 10000",
+            ),
+            (
+                self.dataset_base_size.is_power_of_two() && self.dataset_base_size >= 64,
+                "RANDOMX_DATASET_BASE_SIZE must be a power of 2 and at least 64",
+            ),
+            (
+                self.dataset_extra_size & 63 == 0,
+                "RANDOMX_DATASET_EXTRA_SIZE must be divisible by 64",
+            ),
+            (self.program_size > 0, "RANDOMX_PROGRAM_SIZE must be greater than 0"),
+            (
+                self.program_iterations > 0,
+                "RANDOMX_PROGRAM_ITERATIONS must be greater than 0",
+            ),
+            (self.program_count > 0, "RANDOMX_PROGRAM_COUNT must be greater than 0"),
+            (
+                self.scratchpad_l1.is_power_of_two() && self.scratchpad_l1 >= 64,
+                "RANDOMX_SCRATCHPAD_L1 must be a power of 2 and at least 64",
+            ),
+            (
+                self.scratchpad_l2.is_power_of_two() && self.scratchpad_l2 >= self.scratchpad_l1,
+                "RANDOMX_SCRATCHPAD_L2 must be a power of 2 and at least RANDOMX_SCRATCHPAD_L1",
+            ),
+            (
+                self.scratchpad_l3.is_power_of_two() && self.scratchpad_l3 >= self.scratchpad_l2,
+                "RANDOMX_SCRATCHPAD_L3 must be a power of 2 and at least RANDOMX_SCRATCHPAD_L2",
+            ),
+            (self.jump_bits > 0, "RANDOMX_JUMP_BITS must be greater than 0"),
+            (
+                self.jump_bits + self.jump_offset <= 16,
+                "RANDOMX_JUMP_BITS + RANDOMX_JUMP_OFFSET must not exceed 16",
+            ),
+        ];
+        if let Some((_, reason)) = checks.iter().find(|(ok, _)| !ok) {
+            return Err(reason);
+        }
+        let total: u32 = self
+            .parameters()
+            .iter()
+            .filter(|(name, _)| name.starts_with("RANDOMX_FREQ_"))
+            .map(|(_, frequency)| frequency)
+            .sum();
+        if total == 256 {
+            Ok(())
+        } else {
+            Err("RANDOMX_FREQ_* must add up to 256")
+        }
+    }
+}
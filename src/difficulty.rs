@@ -0,0 +1,148 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Checking hashes against a proof-of-work difficulty or a mining pool's share target.
+//!
+//! Monero and Tari read a RandomX hash as a 256-bit little-endian number and accept it for difficulty `d` when
+//! `hash * d` does not overflow 256 bits. Stratum pools instead send a share target, which stands for the difficulty
+//! `u64::MAX / target`; see [`target_to_difficulty`].
+
+use core::convert::TryInto;
+
+use crate::RandomXError;
+
+/// Returns whether `hash` meets `difficulty`, that is whether `hash * difficulty` (with `hash` read as a 256-bit
+/// little-endian number) fits in 256 bits. Every hash meets a difficulty of 0 or 1.
+// Truncating the 128-bit partial products to their low 64 bits is the point of the limb arithmetic.
+#[allow(clippy::cast_possible_truncation)]
+pub fn meets_difficulty(hash: &[u8; 32], difficulty: u128) -> bool {
+    let difficulty = [difficulty as u64, (difficulty >> 64) as u64];
+    // The 384-bit product, least significant limb first; the hash meets the difficulty if the top two limbs are 0.
+    let mut product = [0u64; 6];
+    for (i, chunk) in hash.chunks_exact(8).enumerate() {
+        let limb = u128::from(u64::from_le_bytes(chunk.try_into().unwrap_or_default()));
+        let mut carry = 0u128;
+        for (j, &d) in difficulty.iter().enumerate() {
+            let t = limb * u128::from(d) + u128::from(product[i + j]) + carry;
+            product[i + j] = t as u64;
+            carry = t >> 64;
+        }
+        product[i + difficulty.len()] = carry as u64;
+    }
+    product[4] == 0 && product[5] == 0
+}
+
+/// Returns the highest difficulty `hash` meets, `floor((2^256 - 1) / hash)`, saturating at `u128::MAX`.
+pub fn achieved_difficulty(hash: &[u8; 32]) -> u128 {
+    if meets_difficulty(hash, u128::MAX) {
+        return u128::MAX;
+    }
+    // `low` is met and `high` is not.
+    let (mut low, mut high) = (1u128, u128::MAX);
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if meets_difficulty(hash, middle) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+/// Converts a stratum share target to the difficulty it stands for. The target is 4 or 8 bytes, little-endian; a
+/// 4-byte target `t` stands for the difficulty `u32::MAX / t`.
+pub fn target_to_difficulty(target: &[u8]) -> Result<u64, RandomXError> {
+    let difficulty = match *target {
+        [a, b, c, d] => u32::MAX.checked_div(u32::from_le_bytes([a, b, c, d])).map(u64::from),
+        [a, b, c, d, e, f, g, h] => u64::MAX.checked_div(u64::from_le_bytes([a, b, c, d, e, f, g, h])),
+        _ => {
+            return Err(RandomXError::InvalidParameter {
+                name: "target",
+                reason: "must be 4 or 8 bytes",
+            })
+        },
+    };
+    difficulty.ok_or(RandomXError::InvalidParameter {
+        name: "target",
+        reason: "must not be zero",
+    })
+}
+
+/// Returns the 8-byte share target for `difficulty`, as sent by pools for difficulties above `u32::MAX`. Difficulty 0
+/// gets the same target as difficulty 1.
+pub fn difficulty_to_target(difficulty: u64) -> u64 {
+    u64::MAX / difficulty.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::difficulty::{achieved_difficulty, difficulty_to_target, meets_difficulty, target_to_difficulty};
+
+    #[test]
+    fn difficulty_check_multiplies_the_hash() {
+        let mut hash = [0xffu8; 32];
+        assert!(meets_difficulty(&hash, 0));
+        assert!(meets_difficulty(&hash, 1));
+        assert!(!meets_difficulty(&hash, 2));
+
+        // 2^255 - 1 meets difficulty 2, but not 3.
+        hash[31] = 0x7f;
+        assert!(meets_difficulty(&hash, 2));
+        assert!(!meets_difficulty(&hash, 3));
+
+        // 2^129 - 1 meets difficulty 2^127, but not 2^127 + 1.
+        let mut hash = [0u8; 32];
+        hash[..16].copy_from_slice(&[0xff; 16]);
+        hash[16] = 1;
+        assert!(meets_difficulty(&hash, 1 << 127));
+        assert!(!meets_difficulty(&hash, (1 << 127) + 1));
+        assert!(meets_difficulty(&[0u8; 32], u128::MAX));
+    }
+
+    #[test]
+    fn achieved_difficulty_is_the_highest_met() {
+        assert_eq!(achieved_difficulty(&[0xff; 32]), 1);
+        assert_eq!(achieved_difficulty(&[0; 32]), u128::MAX);
+        let mut hash = [0xff; 32];
+        hash[31] = 0x7f;
+        assert_eq!(achieved_difficulty(&hash), 2);
+        // 2^194 - 1 times 2^62 still fits in 256 bits, but not times 2^62 + 1.
+        let mut hash = [0xff; 32];
+        hash[24..].copy_from_slice(&[3, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(achieved_difficulty(&hash), 1 << 62);
+    }
+
+    #[test]
+    fn targets_convert_to_difficulty() {
+        assert_eq!(target_to_difficulty(&hex::decode("b88d0600").unwrap()).unwrap(), 10000);
+        assert_eq!(target_to_difficulty(&[0xff; 4]).unwrap(), 1);
+        assert_eq!(
+            target_to_difficulty(&(1u64 << 32).to_le_bytes()).unwrap(),
+            u64::from(u32::MAX)
+        );
+        assert_eq!(difficulty_to_target(u64::from(u32::MAX)), (1 << 32) + 1);
+        assert_eq!(difficulty_to_target(0), u64::MAX);
+        assert!(target_to_difficulty(&[0; 4]).is_err());
+        assert!(target_to_difficulty(&[1; 5]).is_err());
+    }
+}
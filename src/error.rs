@@ -0,0 +1,225 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+use core::{fmt, num::TryFromIntError};
+
+use thiserror::Error;
+
+use crate::{RandomXFlag, Variant};
+
+/// The kind of RandomX object an error refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RandomXObject {
+    /// A [`RandomXCache`](crate::RandomXCache).
+    Cache,
+    /// A [`RandomXDataset`](crate::RandomXDataset).
+    Dataset,
+    /// A [`RandomXVM`](crate::RandomXVM).
+    Vm,
+}
+
+impl fmt::Display for RandomXObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RandomXObject::Cache => write!(f, "cache"),
+            RandomXObject::Dataset => write!(f, "dataset"),
+            RandomXObject::Vm => write!(f, "VM"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+#[non_exhaustive]
+/// This enum specifies the possible errors that may occur.
+pub enum RandomXError {
+    #[error("Problem with parameters supplied: key is empty")]
+    EmptyKey,
+    #[error("Problem with parameters supplied: input was empty")]
+    EmptyInput,
+    #[error("Problem with parameters supplied: {name} {reason}")]
+    InvalidParameter { name: &'static str, reason: &'static str },
+    #[error("Problem creating the RandomX object: Could not allocate {object} with flags {flags:?}")]
+    AllocationFailed { object: RandomXObject, flags: RandomXFlag },
+    #[error("Problem with configuration flags: {reason} ({flags:?})")]
+    InvalidFlagCombination { flags: RandomXFlag, reason: &'static str },
+    #[error(
+        "Problem creating the RandomX object: start plus item_count must be less than dataset count: start: {start}, \
+         item_count: {count}, dataset_count: {max}"
+    )]
+    DatasetRangeOutOfBounds { start: u32, count: u32, max: u32 },
+    #[error("Unknown problem running RandomX: Dataset item count was 0")]
+    EmptyDataset,
+    #[error("Unknown problem running RandomX: Could not get {object} memory")]
+    MemoryUnavailable { object: RandomXObject },
+    #[error("Unknown problem running RandomX: RandomX calculated hash was empty")]
+    EmptyHash,
+    #[error("Failed to convert Int to usize")]
+    TryFromIntError(#[from] TryFromIntError),
+    #[error("The operation was cancelled")]
+    Cancelled,
+    #[error("The request queue is full")]
+    QueueFull,
+    #[error("A worker thread exited unexpectedly")]
+    WorkerFailed,
+    #[cfg(feature = "std")]
+    #[error("I/O error: {0}")]
+    Io(#[source] Arc<std::io::Error>),
+    #[error("The RandomX variant {variant} is not built into this binary")]
+    UnavailableVariant { variant: Variant },
+    #[error("Problem with parameters supplied: objects of different RandomX variants cannot be combined")]
+    VariantMismatch,
+    #[error("Problem with configuration flags: {flags:?} not available in this build, see `RandomXFlag::disabled`")]
+    DisabledFlag { flags: RandomXFlag },
+    #[error("Problem with parameters supplied: the {object} and its cache were initialized with different keys")]
+    KeyMismatch { object: RandomXObject },
+    #[error("Problem with persisted data: the saved {object} does not match this build or key: {reason}")]
+    PersistenceMismatch { object: RandomXObject, reason: &'static str },
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for RandomXError {
+    fn from(e: std::io::Error) -> Self {
+        RandomXError::Io(Arc::new(e))
+    }
+}
+
+/// Stable numeric codes for [`RandomXError`], as used by the C ABI.
+///
+/// Codes are never reused or renumbered; new errors get new codes.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RandomXErrorCode {
+    EmptyKey = 1,
+    EmptyInput = 2,
+    InvalidParameter = 3,
+    AllocationFailed = 4,
+    InvalidFlagCombination = 5,
+    DatasetRangeOutOfBounds = 6,
+    EmptyDataset = 7,
+    MemoryUnavailable = 8,
+    EmptyHash = 9,
+    IntConversion = 10,
+    Cancelled = 11,
+    QueueFull = 12,
+    WorkerFailed = 13,
+    Io = 14,
+    UnavailableVariant = 15,
+    VariantMismatch = 16,
+    DisabledFlag = 17,
+    KeyMismatch = 18,
+    PersistenceMismatch = 19,
+}
+
+impl RandomXError {
+    /// Returns the stable numeric code for this error.
+    pub fn code(&self) -> RandomXErrorCode {
+        match self {
+            RandomXError::EmptyKey => RandomXErrorCode::EmptyKey,
+            RandomXError::EmptyInput => RandomXErrorCode::EmptyInput,
+            RandomXError::InvalidParameter { .. } => RandomXErrorCode::InvalidParameter,
+            RandomXError::AllocationFailed { .. } => RandomXErrorCode::AllocationFailed,
+            RandomXError::InvalidFlagCombination { .. } => RandomXErrorCode::InvalidFlagCombination,
+            RandomXError::DatasetRangeOutOfBounds { .. } => RandomXErrorCode::DatasetRangeOutOfBounds,
+            RandomXError::EmptyDataset => RandomXErrorCode::EmptyDataset,
+            RandomXError::MemoryUnavailable { .. } => RandomXErrorCode::MemoryUnavailable,
+            RandomXError::EmptyHash => RandomXErrorCode::EmptyHash,
+            RandomXError::TryFromIntError(_) => RandomXErrorCode::IntConversion,
+            RandomXError::Cancelled => RandomXErrorCode::Cancelled,
+            RandomXError::QueueFull => RandomXErrorCode::QueueFull,
+            RandomXError::WorkerFailed => RandomXErrorCode::WorkerFailed,
+            #[cfg(feature = "std")]
+            RandomXError::Io(_) => RandomXErrorCode::Io,
+            RandomXError::UnavailableVariant { .. } => RandomXErrorCode::UnavailableVariant,
+            RandomXError::VariantMismatch => RandomXErrorCode::VariantMismatch,
+            RandomXError::DisabledFlag { .. } => RandomXErrorCode::DisabledFlag,
+            RandomXError::KeyMismatch { .. } => RandomXErrorCode::KeyMismatch,
+            RandomXError::PersistenceMismatch { .. } => RandomXErrorCode::PersistenceMismatch,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryFrom, error::Error};
+
+    use crate::{
+        error::{RandomXErrorCode, RandomXObject},
+        RandomXError, RandomXFlag,
+    };
+
+    #[test]
+    fn display_keeps_existing_text() {
+        assert_eq!(
+            RandomXError::EmptyKey.to_string(),
+            "Problem with parameters supplied: key is empty"
+        );
+        assert_eq!(
+            RandomXError::EmptyInput.to_string(),
+            "Problem with parameters supplied: input was empty"
+        );
+        assert_eq!(
+            RandomXError::DatasetRangeOutOfBounds {
+                start: 1,
+                count: 2,
+                max: 2
+            }
+            .to_string(),
+            "Problem creating the RandomX object: start plus item_count must be less than dataset count: start: 1, \
+             item_count: 2, dataset_count: 2"
+        );
+        assert_eq!(
+            RandomXError::AllocationFailed {
+                object: RandomXObject::Cache,
+                flags: RandomXFlag::FLAG_DEFAULT
+            }
+            .code(),
+            RandomXErrorCode::AllocationFailed
+        );
+        let error = RandomXError::PersistenceMismatch {
+            object: RandomXObject::Dataset,
+            reason: "the key digest differs",
+        };
+        assert_eq!(
+            error.to_string(),
+            "Problem with persisted data: the saved dataset does not match this build or key: the key digest differs"
+        );
+        assert_eq!(error.code() as i32, 19);
+    }
+
+    #[test]
+    fn sources_are_chained() {
+        let int_error = u8::try_from(256u32).unwrap_err();
+        let error = RandomXError::from(int_error);
+        assert_eq!(error.code() as i32, 10);
+        assert!(error.source().is_some());
+        #[cfg(feature = "std")]
+        {
+            let error = RandomXError::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+            assert_eq!(error.code(), RandomXErrorCode::Io);
+            assert!(error.source().is_some());
+        }
+        assert!(RandomXError::EmptyKey.source().is_none());
+    }
+}
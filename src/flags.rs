@@ -0,0 +1,337 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Parsing, formatting and per-object validation of [`RandomXFlag`]s, and the flag sets of each object type.
+
+use alloc::string::{String, ToString};
+use core::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{RandomXError, RandomXFlag, RandomXObject};
+
+// Names for `Display` and `FromStr`, in display order. FLAG_ARGON2 is both Argon2 flags and shown instead of them.
+const NAMES: [(&str, RandomXFlag); 8] = [
+    ("large_pages", RandomXFlag::FLAG_LARGE_PAGES),
+    ("hard_aes", RandomXFlag::FLAG_HARD_AES),
+    ("full_mem", RandomXFlag::FLAG_FULL_MEM),
+    ("jit", RandomXFlag::FLAG_JIT),
+    ("secure", RandomXFlag::FLAG_SECURE),
+    ("argon2", RandomXFlag::FLAG_ARGON2),
+    ("argon2_ssse3", RandomXFlag::FLAG_ARGON2_SSSE3),
+    ("argon2_avx2", RandomXFlag::FLAG_ARGON2_AVX2),
+];
+
+/// The error returned when parsing a [`RandomXFlag`] set fails.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unknown RandomX flag `{token}`")]
+pub struct ParseFlagError {
+    token: String,
+}
+
+impl ParseFlagError {
+    /// Returns the part of the input that is not a flag.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl fmt::Display for RandomXFlag {
+    /// Formats the flags as they are parsed: lowercase names without the `FLAG_` prefix separated by commas,
+    /// `argon2` for both Argon2 flags, `default` for no flags, and any bits without a name in hex.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = *self;
+        let mut separator = "";
+        for (name, flag) in NAMES {
+            if rest.contains(flag) {
+                write!(f, "{}{}", separator, name)?;
+                rest.remove(flag);
+                separator = ",";
+            }
+        }
+        if !rest.is_empty() {
+            write!(f, "{}{:#x}", separator, rest.bits())
+        } else if separator.is_empty() {
+            f.write_str("default")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl FromStr for RandomXFlag {
+    type Err = ParseFlagError;
+
+    /// Parses flags separated by `,`, `+` or `|`, e.g. `jit,hard_aes,full_mem` or `recommended+large_pages`.
+    ///
+    /// Names are case-insensitive and may keep the `FLAG_` prefix. `auto` and `recommended` stand for
+    /// [`RandomXFlag::get_recommended_flags`], `default` and `none` for no flags, and numbers (`0x` for hex) for bits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = RandomXFlag::FLAG_DEFAULT;
+        for token in s.split([',', '+', '|']).map(str::trim) {
+            flags |= parse_token(token).ok_or_else(|| ParseFlagError {
+                token: token.to_string(),
+            })?;
+        }
+        Ok(flags)
+    }
+}
+
+fn parse_token(token: &str) -> Option<RandomXFlag> {
+    let name = match token.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("flag_") => &token[5..],
+        _ => token,
+    };
+    if ["auto", "recommended"].iter().any(|n| n.eq_ignore_ascii_case(name)) {
+        return Some(RandomXFlag::get_recommended_flags());
+    }
+    if ["", "default", "none"].iter().any(|n| n.eq_ignore_ascii_case(name)) {
+        return Some(RandomXFlag::FLAG_DEFAULT);
+    }
+    if let Some((_, flag)) = NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        return Some(*flag);
+    }
+    let bits = match token.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("0x") => u32::from_str_radix(&token[2..], 16),
+        _ => token.parse(),
+    };
+    bits.ok().map(RandomXFlag::from_bits_retain)
+}
+
+impl RandomXFlag {
+    /// Returns the flags RandomX documents for `object`; it ignores the others:
+    /// * a cache: FLAG_LARGE_PAGES, FLAG_JIT and the Argon2 flags,
+    /// * a dataset: FLAG_LARGE_PAGES, and FLAG_FULL_MEM, which every dataset implies,
+    /// * a VM: FLAG_LARGE_PAGES, FLAG_HARD_AES, FLAG_FULL_MEM, FLAG_JIT and FLAG_SECURE.
+    pub fn accepted_by(object: RandomXObject) -> RandomXFlag {
+        match object {
+            RandomXObject::Cache => RandomXFlag::FLAG_LARGE_PAGES | RandomXFlag::FLAG_JIT | RandomXFlag::FLAG_ARGON2,
+            RandomXObject::Dataset => RandomXFlag::FLAG_LARGE_PAGES | RandomXFlag::FLAG_FULL_MEM,
+            RandomXObject::Vm => {
+                RandomXFlag::FLAG_LARGE_PAGES |
+                    RandomXFlag::FLAG_HARD_AES |
+                    RandomXFlag::FLAG_FULL_MEM |
+                    RandomXFlag::FLAG_JIT |
+                    RandomXFlag::FLAG_SECURE
+            },
+        }
+    }
+
+    /// Checks that the flags make sense for `object`: they must be [accepted](RandomXFlag::accepted_by) by it, and
+    /// FLAG_SECURE needs FLAG_JIT. Fails with [`RandomXError::InvalidFlagCombination`] naming the offending flags.
+    pub fn validate_for(self, object: RandomXObject) -> Result<(), RandomXError> {
+        let unexpected = self - RandomXFlag::accepted_by(object);
+        if !unexpected.is_empty() {
+            let reason = match object {
+                RandomXObject::Cache => "not used by a cache",
+                RandomXObject::Dataset => "not used by a dataset",
+                RandomXObject::Vm => "not used by a VM",
+            };
+            return Err(RandomXError::InvalidFlagCombination {
+                flags: unexpected,
+                reason,
+            });
+        }
+        if self.contains(RandomXFlag::FLAG_SECURE) && !self.contains(RandomXFlag::FLAG_JIT) {
+            return Err(RandomXError::InvalidFlagCombination {
+                flags: self,
+                reason: "FLAG_SECURE only applies to FLAG_JIT",
+            });
+        }
+        Ok(())
+    }
+}
+
+macro_rules! object_flags {
+    ($(#[$doc:meta])* $name:ident, $object:expr, $noun:literal) => {
+        $(#[$doc])*
+        ///
+        /// Constructors also take a plain [`RandomXFlag`] through `From`, which is not checked, as before.
+        #[derive(Debug, Copy, Clone, Default)]
+        pub struct $name(RandomXFlag);
+
+        impl $name {
+            #[doc = concat!("Checks `flags` for ", $noun, ", see [`RandomXFlag::validate_for`].")]
+            pub fn new(flags: RandomXFlag) -> Result<$name, RandomXError> {
+                flags.validate_for($object)?;
+                Ok($name(flags))
+            }
+
+            /// Returns the flags.
+            pub fn flags(self) -> RandomXFlag {
+                self.0
+            }
+        }
+
+        impl From<RandomXFlag> for $name {
+            fn from(flags: RandomXFlag) -> Self {
+                $name(flags)
+            }
+        }
+
+        impl From<$name> for RandomXFlag {
+            fn from(flags: $name) -> Self {
+                flags.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+object_flags!(
+    /// Flags for [`RandomXCache`](crate::RandomXCache): FLAG_LARGE_PAGES, FLAG_JIT and the Argon2 flags.
+    CacheFlags,
+    RandomXObject::Cache,
+    "a cache"
+);
+object_flags!(
+    /// Flags for [`RandomXDataset`](crate::RandomXDataset): FLAG_LARGE_PAGES, and FLAG_FULL_MEM, which every dataset
+    /// implies.
+    DatasetFlags,
+    RandomXObject::Dataset,
+    "a dataset"
+);
+object_flags!(
+    /// Flags for [`RandomXVM`](crate::RandomXVM): FLAG_LARGE_PAGES, FLAG_HARD_AES, FLAG_FULL_MEM, FLAG_JIT and
+    /// FLAG_SECURE (with FLAG_JIT).
+    VmFlags,
+    RandomXObject::Vm,
+    "a VM"
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::{CacheFlags, DatasetFlags, RandomXError, RandomXFlag, RandomXObject, VmFlags};
+
+    #[test]
+    fn flags_parse_and_display() {
+        let flags: RandomXFlag = "jit,hard_aes,full_mem".parse().unwrap();
+        assert_eq!(
+            flags.bits(),
+            (RandomXFlag::FLAG_JIT | RandomXFlag::FLAG_HARD_AES | RandomXFlag::FLAG_FULL_MEM).bits()
+        );
+        assert_eq!(flags.to_string(), "hard_aes,full_mem,jit");
+
+        let recommended = RandomXFlag::get_recommended_flags();
+        assert_eq!("auto".parse::<RandomXFlag>().unwrap().bits(), recommended.bits());
+        let flags: RandomXFlag = "recommended+large_pages".parse().unwrap();
+        assert_eq!(flags.bits(), (recommended | RandomXFlag::FLAG_LARGE_PAGES).bits());
+        let flags: RandomXFlag = " FLAG_JIT | Flag_Secure ".parse().unwrap();
+        assert_eq!(flags.bits(), (RandomXFlag::FLAG_JIT | RandomXFlag::FLAG_SECURE).bits());
+
+        // Both Argon2 flags are FLAG_ARGON2, one of them is shown as itself.
+        assert_eq!(RandomXFlag::FLAG_ARGON2.to_string(), "argon2");
+        let flags = RandomXFlag::FLAG_ARGON2_SSSE3 | RandomXFlag::FLAG_ARGON2_AVX2;
+        assert_eq!(flags.to_string(), "argon2");
+        assert_eq!(RandomXFlag::FLAG_ARGON2_AVX2.to_string(), "argon2_avx2");
+        assert_eq!(
+            "argon2_ssse3,argon2_avx2".parse::<RandomXFlag>().unwrap().bits(),
+            flags.bits()
+        );
+
+        assert_eq!(RandomXFlag::FLAG_DEFAULT.to_string(), "default");
+        assert_eq!("default".parse::<RandomXFlag>().unwrap().bits(), 0);
+        assert_eq!("".parse::<RandomXFlag>().unwrap().bits(), 0);
+        let flags = RandomXFlag::FLAG_JIT | RandomXFlag::from_bits_retain(0x300);
+        assert_eq!(flags.to_string(), "jit,0x300");
+        assert_eq!("jit,0x300".parse::<RandomXFlag>().unwrap().bits(), flags.bits());
+        assert_eq!("12".parse::<RandomXFlag>().unwrap().bits(), 12);
+
+        let error = "jit,turbo".parse::<RandomXFlag>().unwrap_err();
+        assert_eq!(error.token(), "turbo");
+        assert_eq!(error.to_string(), "Unknown RandomX flag `turbo`");
+
+        for bits in (0..=0x1ff).chain([u32::MAX]) {
+            let flags = RandomXFlag::from_bits_retain(bits);
+            assert_eq!(
+                flags.to_string().parse::<RandomXFlag>().unwrap().bits(),
+                bits,
+                "{}",
+                flags
+            );
+        }
+    }
+
+    #[test]
+    fn flags_validate_for_object() {
+        let recommended = RandomXFlag::get_recommended_flags();
+        for object in [RandomXObject::Cache, RandomXObject::Dataset, RandomXObject::Vm] {
+            let accepted = RandomXFlag::accepted_by(object);
+            assert!((accepted - RandomXFlag::FLAG_SECURE).validate_for(object).is_ok());
+            assert!((recommended & accepted).validate_for(object).is_ok());
+        }
+        assert!(matches!(
+            RandomXFlag::FLAG_FULL_MEM.validate_for(RandomXObject::Cache),
+            Err(RandomXError::InvalidFlagCombination { flags, .. }) if flags.bits() == RandomXFlag::FLAG_FULL_MEM.bits()
+        ));
+        assert!(RandomXFlag::FLAG_JIT.validate_for(RandomXObject::Dataset).is_err());
+        assert!(RandomXFlag::FLAG_ARGON2_AVX2.validate_for(RandomXObject::Vm).is_err());
+        assert!(RandomXFlag::FLAG_SECURE.validate_for(RandomXObject::Vm).is_err());
+        assert!((RandomXFlag::FLAG_SECURE | RandomXFlag::FLAG_JIT)
+            .validate_for(RandomXObject::Vm)
+            .is_ok());
+    }
+
+    #[test]
+    fn object_flags_reject_every_invalid_pairing() {
+        // Each flag with whether a cache, a dataset and a VM accept it.
+        let table = [
+            (RandomXFlag::FLAG_LARGE_PAGES, true, true, true),
+            (RandomXFlag::FLAG_HARD_AES, false, false, true),
+            (RandomXFlag::FLAG_FULL_MEM, false, true, true),
+            (RandomXFlag::FLAG_JIT, true, false, true),
+            (RandomXFlag::FLAG_SECURE, false, false, true),
+            (RandomXFlag::FLAG_ARGON2_SSSE3, true, false, false),
+            (RandomXFlag::FLAG_ARGON2_AVX2, true, false, false),
+            (RandomXFlag::FLAG_ARGON2, true, false, false),
+        ];
+        for (flag, cache, dataset, vm) in table {
+            // FLAG_SECURE is only valid together with FLAG_JIT, which a dataset rejects on its own.
+            let with_jit = flag | RandomXFlag::FLAG_JIT;
+            assert_eq!(CacheFlags::new(with_jit).is_ok(), cache, "cache {}", flag);
+            assert_eq!(DatasetFlags::new(flag).is_ok(), dataset, "dataset {}", flag);
+            assert_eq!(VmFlags::new(with_jit).is_ok(), vm, "vm {}", flag);
+            if !cache {
+                assert!(matches!(
+                    CacheFlags::new(with_jit),
+                    Err(RandomXError::InvalidFlagCombination { flags, .. }) if flags.bits() == flag.bits()
+                ));
+            }
+        }
+        assert!(VmFlags::new(RandomXFlag::FLAG_SECURE).is_err());
+        assert!(CacheFlags::new(RandomXFlag::FLAG_DEFAULT).is_ok());
+        assert!(DatasetFlags::new(RandomXFlag::FLAG_DEFAULT).is_ok());
+        assert!(VmFlags::new(RandomXFlag::FLAG_DEFAULT).is_ok());
+
+        let flags = VmFlags::new(RandomXFlag::FLAG_JIT | RandomXFlag::FLAG_SECURE).unwrap();
+        assert_eq!(flags.to_string(), "jit,secure");
+        assert_eq!(RandomXFlag::from(flags).bits(), flags.flags().bits());
+        // Plain flags convert unchecked, so existing callers keep working.
+        let flags = CacheFlags::from(RandomXFlag::FLAG_FULL_MEM);
+        assert_eq!(flags.flags().bits(), RandomXFlag::FLAG_FULL_MEM.bits());
+    }
+}
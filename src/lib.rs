@@ -32,32 +32,87 @@
 //!
 //! Read more about how RandomX works in the [design document].
 //!
+//! ## `no_std` support
+//!
+//! With the default `std` feature disabled the crate only depends on `core` and `alloc`. The worker thread helpers
+//! and the async facade require `std`.
+//!
+//! The crate is also built as a `cdylib`, which needs `std`. Targets without dynamic libraries drop it, but on those
+//! with them, build only the rust library: `cargo rustc --lib --no-default-features --crate-type rlib`.
+//!
 //! [RandomX github repo]: <https://github.com/tevador/RandomX>
 //! [design document]: <https://github.com/tevador/RandomX/blob/master/doc/design.md>
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
 mod bindings;
+mod blake2b;
+mod config;
+pub mod difficulty;
+mod error;
+mod flags;
+#[cfg(feature = "merge-mining")]
+pub mod merge_mining;
+#[cfg(feature = "monero")]
+pub mod monero;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "pure-rust")]
+pub mod pure;
+#[cfg(feature = "stratum")]
+pub mod stratum;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "serde")]
+pub mod serialization;
+#[cfg(feature = "std")]
+pub mod swap;
 /// Test utilities for fuzzing
 pub mod test_utils;
-
-use std::{
+mod trace;
+mod variant;
+#[cfg(feature = "std")]
+pub mod verifier;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod worker;
+
+#[cfg(feature = "std")]
+use alloc::format;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{
+    cell::Cell,
     convert::TryFrom,
-    num::TryFromIntError,
+    ffi::{c_ulong, c_void},
     ptr,
-    sync::{Arc, Mutex},
-};
-
-use bindings::{
-    randomx_alloc_cache, randomx_alloc_dataset, randomx_cache, randomx_calculate_hash, randomx_create_vm,
-    randomx_dataset, randomx_dataset_item_count, randomx_destroy_vm, randomx_get_dataset_memory, randomx_init_cache,
-    randomx_init_dataset, randomx_release_cache, randomx_release_dataset, randomx_vm, randomx_vm_set_cache,
-    randomx_vm_set_dataset, RANDOMX_HASH_SIZE,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
 };
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+#[cfg(randomx_ffi)]
+use bindings::randomx_get_flags;
+use bindings::{randomx_cache, randomx_dataset, randomx_vm, Backend, RANDOMX_HASH_SIZE};
 use bitflags::bitflags;
-use libc::{c_ulong, c_void};
-use thiserror::Error;
+pub use config::{InstructionFrequencies, RandomXConfig};
+pub use error::{RandomXError, RandomXErrorCode, RandomXObject};
+pub use flags::{CacheFlags, DatasetFlags, ParseFlagError, VmFlags};
+use spin::mutex::SpinMutex;
+pub use variant::Variant;
 
-use crate::bindings::{
-    randomx_calculate_hash_first, randomx_calculate_hash_last, randomx_calculate_hash_next, randomx_get_flags,
-};
+use crate::{bindings::DEFAULT_BACKEND, trace::traced};
+#[cfg(feature = "std")]
+use crate::worker::WorkerConfig;
+
+/// Number of dataset items a worker initializes between polls of the idle hook.
+#[cfg(feature = "std")]
+const DATASET_INIT_CHUNK: u32 = 1 << 16;
 
 bitflags! {
     #[derive(Debug, Copy, Clone)]
@@ -94,8 +149,44 @@ impl RandomXFlag {
     /// * FLAG_SECURE
     ///
     /// The above flags need to be set manually, if required.
+    ///
+    /// Never includes the flags of [`RandomXFlag::disabled`].
     pub fn get_recommended_flags() -> RandomXFlag {
-        unsafe { RandomXFlag::from_bits_truncate(randomx_get_flags()) }
+        #[cfg(randomx_ffi)]
+        let flags = unsafe { RandomXFlag::from_bits_truncate(randomx_get_flags()) };
+        #[cfg(not(randomx_ffi))]
+        let flags = RandomXFlag::FLAG_DEFAULT;
+        flags - RandomXFlag::disabled()
+    }
+
+    /// Returns the flags whose code paths this build removed: FLAG_JIT with the `no-jit` feature, FLAG_HARD_AES
+    /// with the `soft-aes-only` feature, and with the `pure-rust` feature everything but light-mode hashing (FLAG_JIT,
+    /// FLAG_HARD_AES, FLAG_FULL_MEM and FLAG_LARGE_PAGES). Caches, datasets and VMs reject them with
+    /// [`RandomXError::DisabledFlag`].
+    pub fn disabled() -> RandomXFlag {
+        let mut flags = RandomXFlag::FLAG_DEFAULT;
+        if cfg!(not(randomx_ffi)) {
+            flags |= RandomXFlag::FLAG_JIT |
+                RandomXFlag::FLAG_HARD_AES |
+                RandomXFlag::FLAG_FULL_MEM |
+                RandomXFlag::FLAG_LARGE_PAGES;
+        }
+        if cfg!(feature = "no-jit") {
+            flags |= RandomXFlag::FLAG_JIT;
+        }
+        if cfg!(feature = "soft-aes-only") {
+            flags |= RandomXFlag::FLAG_HARD_AES;
+        }
+        flags
+    }
+
+    fn check_enabled(self) -> Result<(), RandomXError> {
+        let disabled = self & RandomXFlag::disabled();
+        if disabled.is_empty() {
+            Ok(())
+        } else {
+            Err(RandomXError::DisabledFlag { flags: disabled })
+        }
     }
 }
 
@@ -106,48 +197,63 @@ impl Default for RandomXFlag {
     }
 }
 
-#[derive(Debug, Clone, Error)]
-/// This enum specifies the possible errors that may occur.
-pub enum RandomXError {
-    #[error("Problem creating the RandomX object: {0}")]
-    CreationError(String),
-    #[error("Problem with configuration flags: {0}")]
-    FlagConfigError(String),
-    #[error("Problem with parameters supplied: {0}")]
-    ParameterError(String),
-    #[error("Failed to convert Int to usize")]
-    TryFromIntError(#[from] TryFromIntError),
-    #[error("Unknown problem running RandomX: {0}")]
-    Other(String),
+/// Returns the Blake2b-256 digest of `key`, see [`RandomXCache::key_digest`].
+fn key_digest(key: &[u8]) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    blake2b::blake2b(&mut digest, key);
+    digest
+}
+
+/// A key digest that can be replaced through a shared reference, all zeros until it is first stored. The lock keeps a
+/// reader from seeing half of an old digest and half of a new one.
+#[derive(Debug, Default)]
+struct SharedKeyDigest(SpinMutex<[u8; 32]>);
+
+impl SharedKeyDigest {
+    fn load(&self) -> [u8; 32] {
+        *self.0.lock()
+    }
+
+    fn store(&self, digest: &[u8; 32]) {
+        *self.0.lock() = *digest;
+    }
+
+    /// Stores `digest`, returning `true` if it differs from the digest stored before.
+    fn replace(&self, digest: &[u8; 32]) -> bool {
+        let mut current = self.0.lock();
+        let changed = *current != *digest;
+        *current = *digest;
+        changed
+    }
 }
 
 #[derive(Debug)]
 struct RandomXCacheInner {
-    cache_ptr: Mutex<*mut randomx_cache>,
+    // The pointer is set once on allocation and never changes, so an atomic load is all the synchronization reads
+    // need. `AtomicPtr` is `Send + Sync`, which makes the inner type thread-safe without a lock (or `std`).
+    cache_ptr: AtomicPtr<randomx_cache>,
+    backend: &'static Backend,
+    key_digest: SharedKeyDigest,
+    generation: AtomicUsize,
 }
 
-// SAFETY: RandomXCacheInner can be safely sent between threads because:
-// 1. The raw pointer is protected by a Mutex
-// 2. The Mutex ensures synchronized access to the pointer
-unsafe impl Send for RandomXCacheInner {}
-
-// SAFETY: RandomXCacheInner can be safely shared between threads because:
-// 1. All access to the raw pointer goes through the Mutex
-// 2. The Mutex provides the necessary synchronization
-unsafe impl Sync for RandomXCacheInner {}
+impl RandomXCacheInner {
+    fn ptr(&self) -> *mut randomx_cache {
+        self.cache_ptr.load(Ordering::Acquire)
+    }
+}
 
 impl Drop for RandomXCacheInner {
     /// De-allocates memory for the `cache` object
     fn drop(&mut self) {
-        if let Ok(ptr) = self.cache_ptr.lock() {
-            if !ptr.is_null() {
-                unsafe {
-                    randomx_release_cache(*ptr);
-                }
+        let ptr = *self.cache_ptr.get_mut();
+        if !ptr.is_null() {
+            unsafe {
+                (self.backend.release_cache)(ptr);
             }
+            #[cfg(feature = "metrics")]
+            metrics::CACHES.dec();
         }
-        // Note: If mutex is poisoned, we can't safely release the cache
-        // This is a tradeoff - we avoid potential double-free but may leak memory
     }
 }
 
@@ -157,7 +263,6 @@ pub struct RandomXCache {
     inner: Arc<RandomXCacheInner>,
 }
 
-
 impl RandomXCache {
     /// Creates and alllcates memory for a new cache object, and initializes it with
     /// the key value.
@@ -171,37 +276,110 @@ impl RandomXCache {
     /// * FLAG_ARGON2_AVX2
     ///
     /// `key` is a sequence of u8 used to initialize SuperScalarHash.
-    pub fn new(flags: RandomXFlag, key: &[u8]) -> Result<RandomXCache, RandomXError> {
-        if key.is_empty() {
-            Err(RandomXError::ParameterError("key is empty".to_string()))
-        } else {
-            let cache_ptr = unsafe { randomx_alloc_cache(flags.bits()) };
-            if cache_ptr.is_null() {
-                Err(RandomXError::CreationError("Could not allocate cache".to_string()))
+    ///
+    /// Flags disabled by a feature (see [`RandomXFlag::disabled`]) are rejected. Pass [`CacheFlags::new`] to also
+    /// reject flags a cache does not use.
+    pub fn new(flags: impl Into<CacheFlags>, key: &[u8]) -> Result<RandomXCache, RandomXError> {
+        Self::new_variant(Variant::Default, flags, key)
+    }
+
+    /// Same as [`RandomXCache::new`], for the RandomX build of `variant`. Datasets and VMs created from the cache use
+    /// the same build.
+    pub fn new_variant(
+        variant: Variant,
+        flags: impl Into<CacheFlags>,
+        key: &[u8],
+    ) -> Result<RandomXCache, RandomXError> {
+        let flags = flags.into().flags();
+        traced!("RandomXCache::new", { %flags, ?variant }, {
+            let backend = variant.backend()?;
+            flags.check_enabled()?;
+            if key.is_empty() {
+                Err(RandomXError::EmptyKey)
             } else {
-                let inner = RandomXCacheInner {
-                    cache_ptr: Mutex::new(cache_ptr),
-                };
-                let result = RandomXCache { inner: Arc::new(inner) };
-                result.init(key)?;
-                Ok(result)
+                let cache_ptr = unsafe { (backend.alloc_cache)(flags.bits()) };
+                if cache_ptr.is_null() {
+                    Err(RandomXError::AllocationFailed {
+                        object: RandomXObject::Cache,
+                        flags,
+                    })
+                } else {
+                    let inner = RandomXCacheInner {
+                        cache_ptr: AtomicPtr::new(cache_ptr),
+                        backend,
+                        key_digest: SharedKeyDigest::default(),
+                        generation: AtomicUsize::new(0),
+                    };
+                    #[cfg(feature = "metrics")]
+                    metrics::CACHES.inc();
+                    let result = RandomXCache { inner: Arc::new(inner) };
+                    result.force_init(key)?;
+                    Ok(result)
+                }
             }
-        }
+        })
     }
 
-    /// Initializes (or re-initializes) the cache object with the given key.
+    /// Initializes (or re-initializes) the cache object with the given key. Does nothing if the cache already holds
+    /// `key`, see [`RandomXCache::force_init`].
     pub fn init(&self, key: &[u8]) -> Result<(), RandomXError> {
+        self.get_or_init(key).map(|_| ())
+    }
+
+    /// Initializes the cache object with the given key unless it already holds it, as compared by
+    /// [`RandomXCache::key_digest`]. Returns `true` if the cache was (re-)initialized.
+    pub fn get_or_init(&self, key: &[u8]) -> Result<bool, RandomXError> {
+        if key.is_empty() {
+            return Err(RandomXError::EmptyKey);
+        }
+        let digest = key_digest(key);
+        if digest == self.inner.key_digest.load() {
+            Ok(false)
+        } else {
+            self.init_with_digest(key, &digest);
+            Ok(true)
+        }
+    }
+
+    /// Initializes the cache object with the given key, even if it already holds it.
+    pub fn force_init(&self, key: &[u8]) -> Result<(), RandomXError> {
         if key.is_empty() {
-            Err(RandomXError::ParameterError("key is empty".to_string()))
+            Err(RandomXError::EmptyKey)
         } else {
+            self.init_with_digest(key, &key_digest(key));
+            Ok(())
+        }
+    }
+
+    /// Returns the Blake2b-256 digest of the key the cache was last initialized with. Datasets initialized from the
+    /// cache carry the same digest, see [`RandomXDataset::key_digest`].
+    pub fn key_digest(&self) -> [u8; 32] {
+        self.inner.key_digest.load()
+    }
+
+    /// Returns the number of times the cache was initialized with a different key than it held, starting at 1 for the
+    /// key it was created with. Datasets and VMs compare it to detect that the cache was re-keyed after they were set
+    /// up from it, see [`StaleKeyPolicy`].
+    pub fn generation(&self) -> usize {
+        self.inner.generation.load(Ordering::Acquire)
+    }
+
+    fn init_with_digest(&self, key: &[u8], digest: &[u8; 32]) {
+        traced!("RandomXCache::init", { key_len = key.len() }, {
             let key_ptr = key.as_ptr() as *mut c_void;
             let key_size = key.len();
-            let cache_ptr = *self.inner.cache_ptr.lock().unwrap();
+            let cache_ptr = self.inner.ptr();
+            #[cfg(feature = "metrics")]
+            let started = Instant::now();
             unsafe {
-                randomx_init_cache(cache_ptr, key_ptr, key_size);
+                (self.inner.backend.init_cache)(cache_ptr, key_ptr, key_size);
             }
-            Ok(())
-        }
+            #[cfg(feature = "metrics")]
+            metrics::CACHE_INIT_SECONDS.observe(started.elapsed());
+            if self.inner.key_digest.replace(digest) {
+                self.inner.generation.fetch_add(1, Ordering::AcqRel);
+            }
+        })
     }
 }
 
@@ -209,8 +387,10 @@ impl RandomXCache {
 struct RandomXDatasetInner {
     dataset_ptr: *mut randomx_dataset,
     dataset_count: u32,
-    #[allow(dead_code)]
     cache: RandomXCache,
+    key_digest: SharedKeyDigest,
+    // The generation of `cache` the dataset was last initialized from, 0 if it was not initialized yet.
+    cache_generation: AtomicUsize,
 }
 
 // SAFETY: RandomXDatasetInner can be safely sent between threads because:
@@ -230,8 +410,10 @@ impl Drop for RandomXDatasetInner {
     fn drop(&mut self) {
         if !self.dataset_ptr.is_null() {
             unsafe {
-                randomx_release_dataset(self.dataset_ptr);
+                (self.cache.inner.backend.release_dataset)(self.dataset_ptr);
             }
+            #[cfg(feature = "metrics")]
+            metrics::DATASETS.dec();
         }
     }
 }
@@ -242,7 +424,6 @@ pub struct RandomXDataset {
     inner: Arc<RandomXDatasetInner>,
 }
 
-
 impl RandomXDataset {
     /// Creates a new dataset object, allocates memory to the `dataset` object and initializes it.
     ///
@@ -253,98 +434,230 @@ impl RandomXDataset {
     /// `cache` is a cache object.
     ///
     /// `start` is the item number where initialization should start, recommended to pass in 0.
+    ///
+    /// Pass [`DatasetFlags::new`] to reject flags a dataset does not use.
     // Conversions may be lossy on Windows or Linux
     #[allow(clippy::useless_conversion)]
-    pub fn new(flags: RandomXFlag, cache: RandomXCache, start: u32) -> Result<RandomXDataset, RandomXError> {
-        let result = Self::alloc(flags, cache.clone())?;
+    pub fn new(
+        flags: impl Into<DatasetFlags>,
+        cache: RandomXCache,
+        start: u32,
+    ) -> Result<RandomXDataset, RandomXError> {
+        let result = Self::alloc(flags, cache)?;
         result.init(start, result.inner.dataset_count)?;
         Ok(result)
     }
 
-    /// Allocate but don't initialize the dataset object.
-    pub fn alloc(flags: RandomXFlag, cache: RandomXCache) -> Result<RandomXDataset, RandomXError> {
-        let item_count = RandomXDataset::count()
-            .map_err(|e| RandomXError::CreationError(format!("Could not get dataset count: {e:?}")))?;
+    /// Creates a new dataset object and initializes it using `threads` worker threads configured by `workers`.
+    ///
+    /// `flags` and `cache` are the same as for [`RandomXDataset::new`].
+    #[cfg(feature = "std")]
+    pub fn new_parallel(
+        flags: impl Into<DatasetFlags>,
+        cache: RandomXCache,
+        threads: u32,
+        workers: &WorkerConfig,
+    ) -> Result<RandomXDataset, RandomXError> {
+        let result = Self::alloc(flags, cache)?;
+        result.init_parallel(threads, workers)?;
+        Ok(result)
+    }
 
-        let test = unsafe { randomx_alloc_dataset(flags.bits()) };
-        if test.is_null() {
-            Err(RandomXError::CreationError("Could not allocate dataset".to_string()))
-        } else {
-            let inner = RandomXDatasetInner {
-                dataset_ptr: test,
-                dataset_count: item_count,
-                cache,
-            };
-            let result = RandomXDataset { inner: Arc::new(inner) };
-            Ok(result)
-        }
+    /// Allocate but don't initialize the dataset object.
+    pub fn alloc(flags: impl Into<DatasetFlags>, cache: RandomXCache) -> Result<RandomXDataset, RandomXError> {
+        let flags = flags.into().flags();
+        traced!("RandomXDataset::alloc", { %flags }, {
+            (flags | RandomXFlag::FLAG_FULL_MEM).check_enabled()?;
+            let backend = cache.inner.backend;
+            let item_count = Self::item_count(backend)?;
+
+            let test = unsafe { (backend.alloc_dataset)(flags.bits()) };
+            if test.is_null() {
+                Err(RandomXError::AllocationFailed {
+                    object: RandomXObject::Dataset,
+                    flags,
+                })
+            } else {
+                let inner = RandomXDatasetInner {
+                    dataset_ptr: test,
+                    dataset_count: item_count,
+                    cache,
+                    key_digest: SharedKeyDigest::default(),
+                    cache_generation: AtomicUsize::new(0),
+                };
+                #[cfg(feature = "metrics")]
+                metrics::DATASETS.inc();
+                let result = RandomXDataset { inner: Arc::new(inner) };
+                Ok(result)
+            }
+        })
     }
 
     /// Initializes the `dataset` object with the given start and item_count.
     pub fn init(&self, start: u32, item_count: u32) -> Result<(), RandomXError> {
-        if start + item_count <= self.inner.dataset_count {
-            let cache_ptr = *self.inner.cache.inner.cache_ptr.lock().unwrap();
-            unsafe {
-                randomx_init_dataset(
-                    self.inner.dataset_ptr,
-                    cache_ptr,
-                    c_ulong::from(start),
-                    c_ulong::from(item_count),
-                );
+        traced!("RandomXDataset::init", { start, item_count }, {
+            if start + item_count <= self.inner.dataset_count {
+                let generation = self.inner.cache.generation();
+                let cache_ptr = self.inner.cache.inner.ptr();
+                #[cfg(feature = "metrics")]
+                let started = Instant::now();
+                unsafe {
+                    (self.backend().init_dataset)(
+                        self.inner.dataset_ptr,
+                        cache_ptr,
+                        c_ulong::from(start),
+                        c_ulong::from(item_count),
+                    );
+                }
+                #[cfg(feature = "metrics")]
+                {
+                    metrics::DATASET_ITEMS.add(usize::try_from(item_count)?);
+                    if item_count == self.inner.dataset_count {
+                        metrics::DATASET_BUILD_SECONDS.observe(started.elapsed());
+                    }
+                }
+                self.inner.key_digest.store(&self.inner.cache.key_digest());
+                self.inner.cache_generation.store(generation, Ordering::Release);
+                Ok(())
+            } else {
+                Err(RandomXError::DatasetRangeOutOfBounds {
+                    start,
+                    count: item_count,
+                    max: self.inner.dataset_count,
+                })
             }
-            Ok(())
-        } else {
-            Err(RandomXError::CreationError(format!(
-                "start plus item_count must be less than dataset count: start: {start}, item_count: {item_count}, \
-                 dataset_count: {}",
-                self.inner.dataset_count
-            )))
-        }
+        })
+    }
+
+    /// Initializes the whole `dataset` by splitting it into `threads` contiguous ranges, each initialized by a worker
+    /// thread configured by `workers`. Workers poll the idle hook between chunks of items.
+    #[cfg(feature = "std")]
+    pub fn init_parallel(&self, threads: u32, workers: &WorkerConfig) -> Result<(), RandomXError> {
+        traced!("RandomXDataset::init_parallel", { threads }, {
+            let dataset_count = self.inner.dataset_count;
+            let threads = threads.clamp(1, dataset_count.max(1));
+            let per_thread = dataset_count / threads;
+            let remainder = dataset_count % threads;
+            #[cfg(feature = "metrics")]
+            let started = Instant::now();
+            thread::scope(|scope| {
+                let mut handles = Vec::new();
+                for index in 0..threads {
+                    let start = index * per_thread + index.min(remainder);
+                    let end = start + per_thread + u32::from(index < remainder);
+                    let handle = thread::Builder::new()
+                        .name(format!("randomx-dataset-{index}"))
+                        .spawn_scoped(scope, move || -> Result<(), RandomXError> {
+                            workers.apply(usize::try_from(index)?)?;
+                            let mut item = start;
+                            while item < end {
+                                workers.wait_while_idle();
+                                let count = DATASET_INIT_CHUNK.min(end - item);
+                                self.init(item, count)?;
+                                item += count;
+                            }
+                            Ok(())
+                        })
+                        .map_err(RandomXError::from)?;
+                    handles.push(handle);
+                }
+                handles
+                    .into_iter()
+                    .try_for_each(|handle| handle.join().map_err(|_| RandomXError::WorkerFailed)?)?;
+                #[cfg(feature = "metrics")]
+                metrics::DATASET_BUILD_SECONDS.observe(started.elapsed());
+                Ok(())
+            })
+        })
+    }
+
+    /// Returns the key digest of the cache the `dataset` was last initialized from (see
+    /// [`RandomXCache::key_digest`]), or all zeros if it was not initialized yet.
+    pub fn key_digest(&self) -> [u8; 32] {
+        self.inner.key_digest.load()
+    }
+
+    /// Returns `true` if the cache of the `dataset` was re-initialized with another key since the `dataset` was
+    /// initialized from it. VMs do not hash with a stale dataset, see [`StaleKeyPolicy`].
+    pub fn is_stale(&self) -> bool {
+        let generation = self.inner.cache_generation.load(Ordering::Acquire);
+        generation != 0 && generation != self.inner.cache.generation()
     }
 
     /// Returns the number of items in the `dataset` or an error on failure.
     pub fn count() -> Result<u32, RandomXError> {
-        match unsafe { randomx_dataset_item_count() } {
-            0 => Err(RandomXError::Other("Dataset item count was 0".to_string())),
+        Self::item_count(&DEFAULT_BACKEND)
+    }
+
+    fn item_count(backend: &Backend) -> Result<u32, RandomXError> {
+        match unsafe { (backend.dataset_item_count)() } {
+            0 => Err(RandomXError::EmptyDataset),
             x => {
-                // This weirdness brought to you by c_ulong being different on Windows and Linux
-                #[cfg(target_os = "windows")]
+                // This weirdness brought to you by c_ulong being different on Windows (and wasm32) and Linux
+                #[cfg(any(target_os = "windows", target_arch = "wasm32"))]
                 return Ok(x);
-                #[cfg(not(target_os = "windows"))]
+                #[cfg(not(any(target_os = "windows", target_arch = "wasm32")))]
                 return Ok(u32::try_from(x)?);
             },
         }
     }
 
+    fn backend(&self) -> &'static Backend {
+        self.inner.cache.inner.backend
+    }
+
     /// Returns the values of the internal memory buffer of the `dataset` or an error on failure.
     pub fn get_data(&self) -> Result<Vec<u8>, RandomXError> {
         if self.inner.dataset_ptr.is_null() {
-            return Err(RandomXError::Other("Dataset pointer is null".into()));
+            return Err(RandomXError::MemoryUnavailable {
+                object: RandomXObject::Dataset,
+            });
         }
 
-        let memory = unsafe { randomx_get_dataset_memory(self.inner.dataset_ptr) };
+        let memory = unsafe { (self.backend().get_dataset_memory)(self.inner.dataset_ptr) };
         if memory.is_null() {
-            return Err(RandomXError::Other("Could not get dataset memory".into()));
+            return Err(RandomXError::MemoryUnavailable {
+                object: RandomXObject::Dataset,
+            });
         }
 
         let size = usize::try_from(self.inner.dataset_count)?;
         let mut result: Vec<u8> = vec![0u8; size];
         if size > 0 {
             unsafe {
-                libc::memcpy(result.as_mut_ptr() as *mut c_void, memory, size);
+                ptr::copy_nonoverlapping(memory as *const u8, result.as_mut_ptr(), size);
             }
         }
         Ok(result)
     }
 }
 
+/// What a [`RandomXVM`] in light mode does when its cache was re-initialized with another key after the VM was set up
+/// from it. See [`RandomXVM::set_stale_key_policy`].
+///
+/// A VM never hashes with a stale dataset (see [`RandomXDataset::is_stale`]), whatever its policy: rebuilding a dataset
+/// that other VMs may be reading is left to the caller, with [`RandomXDataset::init`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum StaleKeyPolicy {
+    /// Hashing fails with [`RandomXError::KeyMismatch`].
+    #[default]
+    Error,
+    /// The VM sets the re-initialized cache again before hashing.
+    Rebuild,
+}
+
 #[derive(Debug)]
 /// The RandomX Virtual Machine (VM) is a complex instruction set computer that executes generated programs.
 pub struct RandomXVM {
     flags: RandomXFlag,
     vm: *mut randomx_vm,
+    backend: &'static Backend,
     linked_cache: Option<RandomXCache>,
+    // The generation of `linked_cache` when it was set, see `RandomXVM::check_keys`.
+    cache_generation: Cell<usize>,
     linked_dataset: Option<RandomXDataset>,
+    stale_key_policy: StaleKeyPolicy,
 }
 
 impl Drop for RandomXVM {
@@ -352,8 +665,10 @@ impl Drop for RandomXVM {
     fn drop(&mut self) {
         if !self.vm.is_null() {
             unsafe {
-                randomx_destroy_vm(self.vm);
+                (self.backend.destroy_vm)(self.vm);
             }
+            #[cfg(feature = "metrics")]
+            metrics::VMS.dec();
         }
     }
 }
@@ -375,91 +690,204 @@ impl RandomXVM {
     /// `cache` is a cache object, optional if FLAG_FULL_MEM is set.
     ///
     /// `dataset` is a dataset object, optional if FLAG_FULL_MEM is not set.
+    ///
+    /// The VM uses the RandomX build (see [`Variant`]) of `cache` and `dataset`, which must match. A `dataset` that is
+    /// stale (see [`RandomXDataset::is_stale`]) or was initialized with another key than `cache` is rejected with
+    /// [`RandomXError::KeyMismatch`].
+    ///
+    /// Flags disabled by a feature (see [`RandomXFlag::disabled`]) are rejected. Pass [`VmFlags::new`] to also reject
+    /// flags a VM does not use.
     pub fn new(
-        flags: RandomXFlag,
+        flags: impl Into<VmFlags>,
         cache: Option<RandomXCache>,
         dataset: Option<RandomXDataset>,
     ) -> Result<RandomXVM, RandomXError> {
-        let is_full_mem = flags.contains(RandomXFlag::FLAG_FULL_MEM);
-        match (cache, dataset) {
-            (None, None) => Err(RandomXError::CreationError("Failed to allocate VM".to_string())),
-            (None, _) if !is_full_mem => Err(RandomXError::FlagConfigError(
-                "No cache and FLAG_FULL_MEM not set".to_string(),
-            )),
-            (_, None) if is_full_mem => Err(RandomXError::FlagConfigError(
-                "No dataset and FLAG_FULL_MEM set".to_string(),
-            )),
-            (cache, dataset) => {
-                let cache_ptr = cache
-                    .as_ref()
-                    .map(|stash| *stash.inner.cache_ptr.lock().unwrap())
-                    .unwrap_or_else(ptr::null_mut);
-                let dataset_ptr = dataset
-                    .as_ref()
-                    .map(|data| data.inner.dataset_ptr)
-                    .unwrap_or_else(ptr::null_mut);
-                let vm = unsafe { randomx_create_vm(flags.bits(), cache_ptr, dataset_ptr) };
-                Ok(RandomXVM {
-                    vm,
+        let flags = flags.into().flags();
+        traced!("RandomXVM::new", { %flags, cache = cache.is_some(), dataset = dataset.is_some() }, {
+            flags.check_enabled()?;
+            let is_full_mem = flags.contains(RandomXFlag::FLAG_FULL_MEM);
+            match (cache, dataset) {
+                (None, None) => Err(RandomXError::InvalidParameter {
+                    name: "cache and dataset",
+                    reason: "are both missing",
+                }),
+                (None, _) if !is_full_mem => Err(RandomXError::InvalidFlagCombination {
                     flags,
-                    linked_cache: cache,
-                    linked_dataset: dataset,
-                })
-            },
-        }
+                    reason: "No cache and FLAG_FULL_MEM not set",
+                }),
+                (_, None) if is_full_mem => Err(RandomXError::InvalidFlagCombination {
+                    flags,
+                    reason: "No dataset and FLAG_FULL_MEM set",
+                }),
+                (cache, dataset) => {
+                    let backend = match (&cache, &dataset) {
+                        (Some(cache), Some(dataset)) if !ptr::eq(cache.inner.backend, dataset.backend()) => {
+                            return Err(RandomXError::VariantMismatch);
+                        },
+                        (Some(cache), _) => cache.inner.backend,
+                        (None, Some(dataset)) => dataset.backend(),
+                        (None, None) => unreachable!("rejected above"),
+                    };
+                    if let Some(dataset) = &dataset {
+                        let digest = dataset.key_digest();
+                        let other_key = matches!(&cache, Some(cache) if digest != [0; 32] && cache.key_digest() != digest);
+                        if other_key || dataset.is_stale() {
+                            return Err(RandomXError::KeyMismatch {
+                                object: RandomXObject::Dataset,
+                            });
+                        }
+                    }
+                    let cache_ptr = cache
+                        .as_ref()
+                        .map(|stash| stash.inner.ptr())
+                        .unwrap_or_else(ptr::null_mut);
+                    let dataset_ptr = dataset
+                        .as_ref()
+                        .map(|data| data.inner.dataset_ptr)
+                        .unwrap_or_else(ptr::null_mut);
+                    let vm = unsafe { (backend.create_vm)(flags.bits(), cache_ptr, dataset_ptr) };
+                    if vm.is_null() {
+                        return Err(RandomXError::AllocationFailed {
+                            object: RandomXObject::Vm,
+                            flags,
+                        });
+                    }
+                    #[cfg(feature = "metrics")]
+                    metrics::VMS.inc();
+                    Ok(RandomXVM {
+                        vm,
+                        flags,
+                        backend,
+                        cache_generation: Cell::new(cache.as_ref().map_or(0, RandomXCache::generation)),
+                        linked_cache: cache,
+                        linked_dataset: dataset,
+                        stale_key_policy: StaleKeyPolicy::default(),
+                    })
+                },
+            }
+        })
     }
 
     /// Re-initializes the `VM` with a new cache that was initialised without
     /// RandomXFlag::FLAG_FULL_MEM.
+    ///
+    /// Does nothing if `cache` is already the VM's cache and was not re-initialized with another key since it was set.
     pub fn reinit_cache(&mut self, cache: RandomXCache) -> Result<(), RandomXError> {
-        if self.flags.contains(RandomXFlag::FLAG_FULL_MEM) {
-            Err(RandomXError::FlagConfigError(
-                "Cannot reinit cache with FLAG_FULL_MEM set".to_string(),
-            ))
-        } else {
-            let cache_ptr = *cache.inner.cache_ptr.lock().unwrap();
-            unsafe {
-                randomx_vm_set_cache(self.vm, cache_ptr);
+        traced!("RandomXVM::reinit_cache", { flags = %self.flags }, {
+            if self.flags.contains(RandomXFlag::FLAG_FULL_MEM) {
+                Err(RandomXError::InvalidFlagCombination {
+                    flags: self.flags,
+                    reason: "Cannot reinit cache with FLAG_FULL_MEM set",
+                })
+            } else if !ptr::eq(self.backend, cache.inner.backend) {
+                Err(RandomXError::VariantMismatch)
+            } else {
+                let generation = cache.generation();
+                let is_linked = matches!(&self.linked_cache, Some(linked) if Arc::ptr_eq(&linked.inner, &cache.inner));
+                if !is_linked || generation != self.cache_generation.get() {
+                    let cache_ptr = cache.inner.ptr();
+                    unsafe {
+                        (self.backend.vm_set_cache)(self.vm, cache_ptr);
+                    }
+                    self.linked_cache = Some(cache);
+                    self.cache_generation.set(generation);
+                }
+                Ok(())
             }
-            self.linked_cache = Some(cache);
-            Ok(())
-        }
+        })
     }
 
     /// Re-initializes the `VM` with a new dataset that was initialised with
     /// RandomXFlag::FLAG_FULL_MEM.
+    ///
+    /// Fails with [`RandomXError::KeyMismatch`] if `dataset` is stale (see [`RandomXDataset::is_stale`]).
     pub fn reinit_dataset(&mut self, dataset: RandomXDataset) -> Result<(), RandomXError> {
+        traced!("RandomXVM::reinit_dataset", { flags = %self.flags }, {
+            if !ptr::eq(self.backend, dataset.backend()) {
+                Err(RandomXError::VariantMismatch)
+            } else if self.flags.contains(RandomXFlag::FLAG_FULL_MEM) {
+                Self::check_dataset(&dataset)?;
+                unsafe {
+                    (self.backend.vm_set_dataset)(self.vm, dataset.inner.dataset_ptr);
+                }
+                self.linked_dataset = Some(dataset);
+                Ok(())
+            } else {
+                Err(RandomXError::InvalidFlagCombination {
+                    flags: self.flags,
+                    reason: "Cannot reinit dataset without FLAG_FULL_MEM set",
+                })
+            }
+        })
+    }
+
+    /// Sets what the VM does when its cache is re-initialized with another key. The default is
+    /// [`StaleKeyPolicy::Error`].
+    pub fn set_stale_key_policy(&mut self, policy: StaleKeyPolicy) {
+        self.stale_key_policy = policy;
+    }
+
+    /// Checks that the cache (in light mode) or the dataset (with FLAG_FULL_MEM) still holds the key the VM was set up
+    /// with. A stale cache is handled by the [`StaleKeyPolicy`], a stale dataset is always an error.
+    fn check_keys(&self) -> Result<(), RandomXError> {
         if self.flags.contains(RandomXFlag::FLAG_FULL_MEM) {
+            self.linked_dataset.as_ref().map_or(Ok(()), Self::check_dataset)
+        } else {
+            self.linked_cache
+                .as_ref()
+                .map_or(Ok(()), |cache| self.check_cache(cache))
+        }
+    }
+
+    fn check_cache(&self, cache: &RandomXCache) -> Result<(), RandomXError> {
+        let generation = cache.generation();
+        if generation != self.cache_generation.get() {
+            if self.stale_key_policy == StaleKeyPolicy::Error {
+                return Err(RandomXError::KeyMismatch {
+                    object: RandomXObject::Vm,
+                });
+            }
             unsafe {
-                randomx_vm_set_dataset(self.vm, dataset.inner.dataset_ptr);
+                (self.backend.vm_set_cache)(self.vm, cache.inner.ptr());
             }
-            self.linked_dataset = Some(dataset);
-            Ok(())
-        } else {
-            Err(RandomXError::FlagConfigError(
-                "Cannot reinit dataset without FLAG_FULL_MEM set".to_string(),
-            ))
+            self.cache_generation.set(generation);
+        }
+        Ok(())
+    }
+
+    // Re-initializing the dataset here would race with the other VMs hashing with it, so a stale dataset is an error
+    // even with `StaleKeyPolicy::Rebuild`.
+    fn check_dataset(dataset: &RandomXDataset) -> Result<(), RandomXError> {
+        if dataset.is_stale() {
+            return Err(RandomXError::KeyMismatch {
+                object: RandomXObject::Dataset,
+            });
         }
+        Ok(())
     }
 
     /// Calculates a RandomX hash value and returns it, error on failure.
     ///
-    /// `input` is a sequence of u8 to be hashed.
+    /// `input` is a sequence of u8 to be hashed. Fails with [`RandomXError::KeyMismatch`] if the dataset of the VM is
+    /// stale, or if its cache was re-initialized with another key and the [`StaleKeyPolicy`] is `Error`.
     pub fn calculate_hash(&self, input: &[u8]) -> Result<Vec<u8>, RandomXError> {
         if input.is_empty() {
-            Err(RandomXError::ParameterError("input was empty".to_string()))
+            Err(RandomXError::EmptyInput)
         } else {
+            self.check_keys()?;
             let size_input = input.len();
             let input_ptr = input.as_ptr() as *const c_void;
             let mut arr = [0; RANDOMX_HASH_SIZE as usize];
             let output_ptr = arr.as_mut_ptr() as *mut c_void;
             unsafe {
-                randomx_calculate_hash(self.vm, input_ptr, size_input, output_ptr);
+                (self.backend.calculate_hash)(self.vm, input_ptr, size_input, output_ptr);
             }
             // if this failed, arr should still be empty
             if arr == [0; RANDOMX_HASH_SIZE as usize] {
-                Err(RandomXError::Other("RandomX calculated hash was empty".to_string()))
+                Err(RandomXError::EmptyHash)
             } else {
+                #[cfg(feature = "metrics")]
+                metrics::HASHES.add(1);
                 let result = arr.to_vec();
                 Ok(result)
             }
@@ -468,71 +896,76 @@ impl RandomXVM {
 
     /// Calculates hashes from a set of inputs.
     ///
-    /// `input` is an array of a sequence of u8 to be hashed.
+    /// `input` is an array of a sequence of u8 to be hashed. Keys are checked as for [`RandomXVM::calculate_hash`].
     #[allow(clippy::needless_range_loop)] // Range loop is not only for indexing `input`
     pub fn calculate_hash_set(&self, input: &[&[u8]]) -> Result<Vec<Vec<u8>>, RandomXError> {
-        if input.is_empty() {
-            // Empty set
-            return Err(RandomXError::ParameterError("input was empty".to_string()));
-        }
-
-        let mut result = Vec::new();
-        // For single input
-        if input.len() == 1 {
-            let hash = self.calculate_hash(input[0])?;
-            result.push(hash);
-            return Ok(result);
-        }
+        traced!("RandomXVM::calculate_hash_set", { inputs = input.len() }, {
+            if input.is_empty() {
+                // Empty set
+                return Err(RandomXError::EmptyInput);
+            }
+            self.check_keys()?;
+
+            let mut result = Vec::new();
+            // For single input
+            if input.len() == 1 {
+                let hash = self.calculate_hash(input[0])?;
+                result.push(hash);
+                return Ok(result);
+            }
 
-        // For multiple inputs
-        let mut output_ptr: *mut c_void = ptr::null_mut();
-        let mut arr = [0; RANDOMX_HASH_SIZE as usize];
+            // For multiple inputs
+            let mut output_ptr: *mut c_void = ptr::null_mut();
+            let mut arr = [0; RANDOMX_HASH_SIZE as usize];
 
-        // Not len() as last iteration assigns final hash
-        let iterations = input.len() + 1;
-        for i in 0..iterations {
-            if i == iterations - 1 {
-                // For last iteration
-                unsafe {
-                    randomx_calculate_hash_last(self.vm, output_ptr);
-                }
-            } else {
-                if input[i].is_empty() {
-                    // Stop calculations
-                    if arr != [0; RANDOMX_HASH_SIZE as usize] {
-                        // Complete what was started
-                        unsafe {
-                            randomx_calculate_hash_last(self.vm, output_ptr);
-                        }
-                    }
-                    return Err(RandomXError::ParameterError("input was empty".to_string()));
-                };
-                let size_input = input[i].len();
-                let input_ptr = input[i].as_ptr() as *mut c_void;
-                output_ptr = arr.as_mut_ptr() as *mut c_void;
-                if i == 0 {
-                    // For first iteration
+            // Not len() as last iteration assigns final hash
+            let iterations = input.len() + 1;
+            for i in 0..iterations {
+                if i == iterations - 1 {
+                    // For last iteration
                     unsafe {
-                        randomx_calculate_hash_first(self.vm, input_ptr, size_input);
+                        (self.backend.calculate_hash_last)(self.vm, output_ptr);
                     }
                 } else {
-                    unsafe {
-                        // For every other iteration
-                        randomx_calculate_hash_next(self.vm, input_ptr, size_input, output_ptr);
+                    if input[i].is_empty() {
+                        // Stop calculations
+                        if arr != [0; RANDOMX_HASH_SIZE as usize] {
+                            // Complete what was started
+                            unsafe {
+                                (self.backend.calculate_hash_last)(self.vm, output_ptr);
+                            }
+                        }
+                        return Err(RandomXError::EmptyInput);
+                    };
+                    let size_input = input[i].len();
+                    let input_ptr = input[i].as_ptr() as *mut c_void;
+                    output_ptr = arr.as_mut_ptr() as *mut c_void;
+                    if i == 0 {
+                        // For first iteration
+                        unsafe {
+                            (self.backend.calculate_hash_first)(self.vm, input_ptr, size_input);
+                        }
+                    } else {
+                        unsafe {
+                            // For every other iteration
+                            (self.backend.calculate_hash_next)(self.vm, input_ptr, size_input, output_ptr);
+                        }
                     }
                 }
-            }
 
-            if i != 0 {
-                // First hash is only available in 2nd iteration
-                if arr == [0; RANDOMX_HASH_SIZE as usize] {
-                    return Err(RandomXError::Other("RandomX hash was zero".to_string()));
+                if i != 0 {
+                    // First hash is only available in 2nd iteration
+                    if arr == [0; RANDOMX_HASH_SIZE as usize] {
+                        return Err(RandomXError::EmptyHash);
+                    }
+                    let output: Vec<u8> = arr.to_vec();
+                    result.push(output);
                 }
-                let output: Vec<u8> = arr.to_vec();
-                result.push(output);
             }
-        }
-        Ok(result)
+            #[cfg(feature = "metrics")]
+            metrics::HASHES.add(result.len());
+            Ok(result)
+        })
     }
 }
 
@@ -540,11 +973,33 @@ impl RandomXVM {
 mod tests {
     use std::{
         ptr,
-        sync::{Arc, Mutex},
+        sync::{
+            atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+            Arc,
+        },
         thread,
     };
 
-    use crate::{RandomXCache, RandomXCacheInner, RandomXDataset, RandomXDatasetInner, RandomXFlag, RandomXVM};
+    #[cfg(feature = "std")]
+    use crate::worker::WorkerConfig;
+    use crate::{
+        RandomXCache,
+        RandomXCacheInner,
+        RandomXDataset,
+        RandomXDatasetInner,
+        RandomXError,
+        RandomXFlag,
+        RandomXObject,
+        RandomXVM,
+        SharedKeyDigest,
+        StaleKeyPolicy,
+        DEFAULT_BACKEND,
+    };
+
+    /// Backends without fast mode (the pure-Rust one) cannot allocate datasets, so tests that need one return early.
+    pub(crate) fn datasets_disabled() -> bool {
+        RandomXFlag::disabled().contains(RandomXFlag::FLAG_FULL_MEM)
+    }
 
     #[test]
     fn lib_alloc_cache() {
@@ -556,14 +1011,126 @@ mod tests {
 
     #[test]
     fn lib_alloc_dataset() {
+        if datasets_disabled() {
+            return;
+        }
         let flags = RandomXFlag::default();
         let key = "Key";
         let cache = RandomXCache::new(flags, key.as_bytes()).unwrap();
         let dataset = RandomXDataset::new(flags, cache.clone(), 0).expect("Failed to allocate dataset");
+        assert_eq!(dataset.key_digest(), cache.key_digest());
         drop(dataset);
         drop(cache);
     }
 
+    #[test]
+    fn lib_cache_key_digest() {
+        let flags = RandomXFlag::get_recommended_flags();
+        let input = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+        let cache = RandomXCache::new(flags, b"test key 000").unwrap();
+        let digest = cache.key_digest();
+        assert_eq!(
+            hex::encode(digest),
+            "3a68d993a0fac8216e62a4328361616e45de8ef96a9707bb9b4f25c8f748d200"
+        );
+        assert!(!cache.get_or_init(b"test key 000").unwrap());
+        assert!(matches!(cache.get_or_init(b""), Err(RandomXError::EmptyKey)));
+
+        let mut vm = RandomXVM::new(flags, Some(cache.clone()), None).unwrap();
+        vm.reinit_cache(cache.clone()).unwrap();
+        assert!(cache.get_or_init(b"test key 001").unwrap());
+        assert_ne!(cache.key_digest(), digest);
+        vm.reinit_cache(cache.clone()).unwrap();
+        assert_eq!(
+            hex::encode(vm.calculate_hash(input).unwrap()),
+            "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc"
+        );
+    }
+
+    #[test]
+    fn shared_key_digest_is_never_torn() {
+        let digest = SharedKeyDigest::default();
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let loaded = digest.load();
+                    assert!(loaded == [0; 32] || loaded == [1; 32], "torn digest {:?}", loaded);
+                }
+            });
+            for i in 0..100_000 {
+                digest.store(&[u8::from(i % 2 == 0); 32]);
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn lib_rekeyed_cache_is_detected() {
+        let flags = RandomXFlag::get_recommended_flags();
+        let input = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+        let cache = RandomXCache::new(flags, b"test key 000").unwrap();
+        let generation = cache.generation();
+        let mut vm = RandomXVM::new(flags, Some(cache.clone()), None).unwrap();
+
+        cache.force_init(b"test key 000").unwrap();
+        assert_eq!(cache.generation(), generation);
+        cache.init(b"test key 001").unwrap();
+        assert_eq!(cache.generation(), generation + 1);
+        assert!(matches!(
+            vm.calculate_hash(input),
+            Err(RandomXError::KeyMismatch {
+                object: RandomXObject::Vm
+            })
+        ));
+
+        vm.set_stale_key_policy(StaleKeyPolicy::Rebuild);
+        assert_eq!(
+            hex::encode(vm.calculate_hash(input).unwrap()),
+            "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc"
+        );
+    }
+
+    #[test]
+    fn lib_stale_dataset_is_detected() {
+        if datasets_disabled() {
+            return;
+        }
+        let flags = RandomXFlag::get_recommended_flags() | RandomXFlag::FLAG_FULL_MEM;
+        let input = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+        let cache = RandomXCache::new(flags, b"test key 000").unwrap();
+        let dataset = RandomXDataset::new(flags, cache.clone(), 0).unwrap();
+        let mut vm = RandomXVM::new(flags, None, Some(dataset.clone())).unwrap();
+
+        cache.init(b"test key 001").unwrap();
+        assert!(dataset.is_stale());
+        assert!(matches!(
+            vm.calculate_hash(input),
+            Err(RandomXError::KeyMismatch {
+                object: RandomXObject::Dataset
+            })
+        ));
+        assert!(matches!(
+            RandomXVM::new(flags, None, Some(dataset.clone())),
+            Err(RandomXError::KeyMismatch { .. })
+        ));
+
+        vm.set_stale_key_policy(StaleKeyPolicy::Rebuild);
+        assert!(matches!(
+            vm.calculate_hash(input),
+            Err(RandomXError::KeyMismatch {
+                object: RandomXObject::Dataset
+            })
+        ));
+
+        dataset.init(0, RandomXDataset::count().unwrap()).unwrap();
+        assert!(!dataset.is_stale());
+        assert_eq!(
+            hex::encode(vm.calculate_hash(input).unwrap()),
+            "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc"
+        );
+    }
+
     #[test]
     fn lib_alloc_vm() {
         let flags = RandomXFlag::default();
@@ -571,6 +1138,9 @@ mod tests {
         let cache = RandomXCache::new(flags, key.as_bytes()).unwrap();
         let mut vm = RandomXVM::new(flags, Some(cache.clone()), None).expect("Failed to allocate VM");
         drop(vm);
+        if datasets_disabled() {
+            return;
+        }
         let dataset = RandomXDataset::new(flags, cache.clone(), 0).unwrap();
         vm = RandomXVM::new(flags, Some(cache.clone()), Some(dataset.clone())).expect("Failed to allocate VM");
         drop(dataset);
@@ -578,8 +1148,30 @@ mod tests {
         drop(vm);
     }
 
+    #[test]
+    fn disabled_flags_are_rejected() {
+        let disabled = RandomXFlag::disabled();
+        assert!(!RandomXFlag::get_recommended_flags().intersects(disabled));
+        if disabled.is_empty() {
+            return;
+        }
+        let key = "Key";
+        assert!(matches!(
+            RandomXCache::new(disabled | RandomXFlag::FLAG_DEFAULT, key.as_bytes()),
+            Err(RandomXError::DisabledFlag { flags }) if flags.bits() == disabled.bits()
+        ));
+        let cache = RandomXCache::new(RandomXFlag::default(), key.as_bytes()).unwrap();
+        assert!(matches!(
+            RandomXVM::new(disabled, Some(cache), None),
+            Err(RandomXError::DisabledFlag { flags }) if flags.bits() == disabled.bits()
+        ));
+    }
+
     #[test]
     fn lib_dataset_memory() {
+        if datasets_disabled() {
+            return;
+        }
         let flags = RandomXFlag::default();
         let key = "Key";
         let cache = RandomXCache::new(flags, key.as_bytes()).unwrap();
@@ -598,7 +1190,10 @@ mod tests {
         if let Ok(mut vm) = RandomXVM::new(flags, None, None) {
             let cache = RandomXCache {
                 inner: Arc::new(RandomXCacheInner {
-                    cache_ptr: Mutex::new(ptr::null_mut()),
+                    cache_ptr: AtomicPtr::new(ptr::null_mut()),
+                    backend: &DEFAULT_BACKEND,
+                    key_digest: SharedKeyDigest::default(),
+                    generation: AtomicUsize::new(0),
                 }),
             };
             assert!(vm.reinit_cache(cache.clone()).is_err());
@@ -607,6 +1202,8 @@ mod tests {
                     dataset_ptr: ptr::null_mut(),
                     dataset_count: 0,
                     cache,
+                    key_digest: SharedKeyDigest::default(),
+                    cache_generation: AtomicUsize::new(0),
                 }),
             };
             assert!(vm.reinit_dataset(dataset.clone()).is_err());
@@ -634,6 +1231,9 @@ mod tests {
         let vm2 = RandomXVM::new(flags, Some(cache2.clone()), None).unwrap();
         let hash3 = vm2.calculate_hash(input.as_bytes()).expect("no data");
         assert_eq!(hash2, hash3);
+        if datasets_disabled() {
+            return;
+        }
 
         let cache3 = RandomXCache::new(flags, key.as_bytes()).unwrap();
         let dataset3 = RandomXDataset::new(flags, cache3.clone(), 0).unwrap();
@@ -687,6 +1287,9 @@ mod tests {
 
     #[test]
     fn lib_calculate_hash_is_consistent() {
+        if datasets_disabled() {
+            return;
+        }
         let flags = RandomXFlag::get_recommended_flags();
         let key = "Key";
         let input = "Input";
@@ -723,6 +1326,9 @@ mod tests {
 
     #[test]
     fn lib_check_cache_and_dataset_lifetimes() {
+        if datasets_disabled() {
+            return;
+        }
         let flags = RandomXFlag::get_recommended_flags();
         let key = "Key";
         let input = "Input";
@@ -759,6 +1365,9 @@ mod tests {
 
     #[test]
     fn randomx_hash_fast_vs_light() {
+        if datasets_disabled() {
+            return;
+        }
         let input = b"input";
         let key = b"key";
 
@@ -776,8 +1385,30 @@ mod tests {
         assert_eq!(fast, light);
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn lib_dataset_parallel_init() {
+        if datasets_disabled() {
+            return;
+        }
+        let key = b"test key 000";
+        let input = b"This is a test";
+        let flags = RandomXFlag::get_recommended_flags() | RandomXFlag::FLAG_FULL_MEM;
+        let cache = RandomXCache::new(flags, key).unwrap();
+        let dataset = RandomXDataset::new_parallel(flags, cache, 4, &WorkerConfig::default()).unwrap();
+        let vm = RandomXVM::new(flags, None, Some(dataset)).unwrap();
+        let hash = vm.calculate_hash(input).unwrap();
+        assert_eq!(
+            hex::decode("639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f").unwrap(),
+            hash
+        );
+    }
+
     #[test]
     fn test_vectors_fast_mode() {
+        if datasets_disabled() {
+            return;
+        }
         // test vectors from https://github.com/tevador/RandomX/blob/040f4500a6e79d54d84a668013a94507045e786f/src/tests/tests.cpp#L963-L979
         let key = b"test key 000";
         let vectors = [
@@ -864,6 +1495,9 @@ mod tests {
 
     #[test]
     fn test_thread_safety_in_practice() {
+        if datasets_disabled() {
+            return;
+        }
         let flags = RandomXFlag::default();
         let key = "ThreadTestKey";
         let input = "ThreadTestInput";
@@ -0,0 +1,543 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Verifying merge-mining proofs, as Tari's `MoneroPowData` carries them.
+//!
+//! A chain merge-mined with Monero commits to its own block by putting a hash of it (the merge-mining hash) in the
+//! extra field of a Monero coinbase, under the merge-mining tag. The proof is the Monero header, the coinbase, the
+//! Merkle branch from the coinbase to the transaction tree hash and the number of transactions: enough to rebuild the
+//! hashing blob and hash it with RandomX under the Monero seed, without the other transactions.
+//!
+//! Only proofs for a single merged chain are verified, whose tag holds the merge-mining hash itself (depth 0).
+
+use std::convert::TryInto;
+
+use thiserror::Error;
+
+use crate::{
+    difficulty::achieved_difficulty,
+    monero::{fast_hash, hash_pair, read_varint, BlockHeader, HashingBlob},
+    verifier::Verifier,
+    RandomXError,
+};
+
+const TX_EXTRA_PADDING: u8 = 0x00;
+const TX_EXTRA_PUBKEY: u8 = 0x01;
+const TX_EXTRA_NONCE: u8 = 0x02;
+const TX_EXTRA_MERGE_MINING_TAG: u8 = 0x03;
+const TX_EXTRA_ADDITIONAL_PUBKEYS: u8 = 0x04;
+const TX_EXTRA_MYSTERIOUS_MINERGATE: u8 = 0xde;
+
+/// Errors from verifying a merge-mining proof.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum MergeMiningError {
+    #[error("Malformed coinbase transaction: {0}")]
+    MalformedCoinbase(&'static str),
+    #[error("The coinbase has no merge-mining tag")]
+    MissingMergeMiningTag,
+    #[error("The merge-mining tag does not commit to the expected merge-mining hash")]
+    MergeMiningHashMismatch,
+    #[error("Merge-mining tags of depth {0} are not supported, only a single merged chain")]
+    UnsupportedMergeMiningDepth(u64),
+    #[error("The coinbase Merkle branch does not lead to the Merkle root")]
+    MerkleRootMismatch,
+    #[error("The coinbase Merkle branch does not match the transaction count")]
+    InvalidBranchLength,
+    #[error(transparent)]
+    RandomX(#[from] RandomXError),
+}
+
+/// The merge-mining tag of a coinbase's extra field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MergeMiningTag {
+    /// The depth of the merged chains' Merkle tree, 0 for a single chain.
+    pub depth: u64,
+    /// The merge-mining hash, or the root of the merged chains' tree.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes"))]
+    pub merkle_root: [u8; 32],
+}
+
+/// A parsed Monero coinbase (miner) transaction, version 1 or 2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coinbase {
+    version: u64,
+    height: u64,
+    hash: [u8; 32],
+    merge_mining_tag: Option<MergeMiningTag>,
+}
+
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self, field: &'static str) -> Result<u64, MergeMiningError> {
+        read_varint(&mut self.input).ok_or(MergeMiningError::MalformedCoinbase(field))
+    }
+
+    fn length(&mut self, field: &'static str) -> Result<usize, MergeMiningError> {
+        self.varint(field)?
+            .try_into()
+            .map_err(|_| MergeMiningError::MalformedCoinbase(field))
+    }
+
+    fn byte(&mut self, field: &'static str) -> Result<u8, MergeMiningError> {
+        Ok(self.bytes(1, field)?[0])
+    }
+
+    fn bytes(&mut self, len: usize, field: &'static str) -> Result<&'a [u8], MergeMiningError> {
+        if self.input.len() < len {
+            return Err(MergeMiningError::MalformedCoinbase(field));
+        }
+        let (bytes, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn hash(&mut self, field: &'static str) -> Result<[u8; 32], MergeMiningError> {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(self.bytes(32, field)?);
+        Ok(hash)
+    }
+}
+
+impl Coinbase {
+    /// Parses a serialised coinbase transaction.
+    pub fn parse(tx: &[u8]) -> Result<Coinbase, MergeMiningError> {
+        let mut reader = Reader { input: tx };
+        let version = reader.varint("version")?;
+        if version != 1 && version != 2 {
+            return Err(MergeMiningError::MalformedCoinbase("unsupported version"));
+        }
+        reader.varint("unlock time")?;
+        if reader.varint("input count")? != 1 || reader.byte("input")? != 0xff {
+            return Err(MergeMiningError::MalformedCoinbase("not a single generating input"));
+        }
+        let height = reader.varint("height")?;
+        for _ in 0..reader.varint("output count")? {
+            reader.varint("output amount")?;
+            // A public key, or a public key and a view tag.
+            match reader.byte("output target")? {
+                0x02 => reader.bytes(32, "output key")?,
+                0x03 => reader.bytes(33, "output key")?,
+                _ => return Err(MergeMiningError::MalformedCoinbase("unknown output target")),
+            };
+        }
+        let extra_len = reader.length("extra length")?;
+        let merge_mining_tag = parse_extra(reader.bytes(extra_len, "extra")?)?;
+        let prefix = &tx[..tx.len() - reader.input.len()];
+        let hash = match (version, reader.input) {
+            (1, []) => fast_hash(prefix),
+            // Version 2 hashes the prefix, the RingCT base (a null signature type for coinbases) and the prunable part,
+            // which is empty for coinbases and hashed as zeros.
+            (2, [0]) => {
+                let mut hashes = [0u8; 96];
+                hashes[..32].copy_from_slice(&fast_hash(prefix));
+                hashes[32..64].copy_from_slice(&fast_hash(&[0]));
+                fast_hash(&hashes)
+            },
+            _ => return Err(MergeMiningError::MalformedCoinbase("unexpected data after the prefix")),
+        };
+        Ok(Coinbase {
+            version,
+            height,
+            hash,
+            merge_mining_tag,
+        })
+    }
+
+    /// Returns the transaction version, 1 or 2.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the height of the block the coinbase belongs to.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Returns the transaction hash.
+    pub fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    /// Returns the merge-mining tag in the extra field, if there is one.
+    pub fn merge_mining_tag(&self) -> Option<MergeMiningTag> {
+        self.merge_mining_tag
+    }
+}
+
+// Returns the first merge-mining tag in the fields of `extra`.
+fn parse_extra(extra: &[u8]) -> Result<Option<MergeMiningTag>, MergeMiningError> {
+    let mut reader = Reader { input: extra };
+    while !reader.input.is_empty() {
+        match reader.byte("extra tag")? {
+            // Padding runs to the end of the field.
+            TX_EXTRA_PADDING => return Ok(None),
+            TX_EXTRA_PUBKEY => {
+                reader.bytes(32, "extra public key")?;
+            },
+            TX_EXTRA_NONCE | TX_EXTRA_MYSTERIOUS_MINERGATE => {
+                let len = reader.length("extra field length")?;
+                reader.bytes(len, "extra field")?;
+            },
+            TX_EXTRA_ADDITIONAL_PUBKEYS => {
+                let count = reader.length("extra public key count")?;
+                let len = count
+                    .checked_mul(32)
+                    .ok_or(MergeMiningError::MalformedCoinbase("extra public key count"))?;
+                reader.bytes(len, "extra public keys")?;
+            },
+            TX_EXTRA_MERGE_MINING_TAG => {
+                let len = reader.length("merge-mining tag length")?;
+                let mut tag = Reader {
+                    input: reader.bytes(len, "merge-mining tag")?,
+                };
+                return Ok(Some(MergeMiningTag {
+                    depth: tag.varint("merge-mining depth")?,
+                    merkle_root: tag.hash("merge-mining hash")?,
+                }));
+            },
+            _ => return Err(MergeMiningError::MalformedCoinbase("unknown extra tag")),
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the Merkle branch from the first of `hashes` (the coinbase) to their tree hash, bottom up.
+pub fn coinbase_branch(hashes: &[[u8; 32]]) -> Result<Vec<[u8; 32]>, RandomXError> {
+    if hashes.is_empty() {
+        return Err(RandomXError::InvalidParameter {
+            name: "hashes",
+            reason: "must not be empty",
+        });
+    }
+    // The leaves beyond the largest power of two below their count are paired first, see `monero::tree_hash`.
+    let count = hashes.len().next_power_of_two() / 2;
+    let direct = (2 * count).saturating_sub(hashes.len());
+    let mut branch = Vec::new();
+    let mut level = if direct == 0 && hashes.len() > 1 {
+        branch.push(hashes[1]);
+        hashes
+            .chunks_exact(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect()
+    } else {
+        let mut level = hashes[..direct].to_vec();
+        level.extend(
+            hashes[direct..]
+                .chunks_exact(2)
+                .map(|pair| hash_pair(&pair[0], &pair[1])),
+        );
+        level
+    };
+    while level.len() > 1 {
+        branch.push(level[1]);
+        level = level
+            .chunks_exact(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    Ok(branch)
+}
+
+// The length of the coinbase branch in a tree of `tx_count` transactions, if there can be that many.
+fn coinbase_branch_len(tx_count: u64) -> Option<usize> {
+    if tx_count < 2 {
+        return Some(0);
+    }
+    let count = tx_count.checked_next_power_of_two()? / 2;
+    // The coinbase is carried up a level when there is an odd leaf to spare.
+    let levels = if 2 * count > tx_count {
+        count.trailing_zeros()
+    } else {
+        count.trailing_zeros() + 1
+    };
+    levels.try_into().ok()
+}
+
+/// A merge-mining proof: what a merge-mined block carries to show the Monero proof of work commits to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MergeMiningProof {
+    /// The header of the Monero block.
+    pub header: BlockHeader,
+    /// The RandomX key of the Monero block: the id of the block at its seed height.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes"))]
+    pub seed_hash: [u8; 32],
+    /// The number of transactions in the Monero block, the coinbase included.
+    pub tx_count: u64,
+    /// The tree hash of the Monero block's transactions.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes"))]
+    pub merkle_root: [u8; 32],
+    /// The Merkle branch from the coinbase hash to `merkle_root`, see [`coinbase_branch`].
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes::vec"))]
+    pub coinbase_branch: Vec<[u8; 32]>,
+    /// The serialised coinbase transaction.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes"))]
+    pub coinbase_tx: Vec<u8>,
+}
+
+impl MergeMiningProof {
+    /// Verifies that the proof commits to `merge_mining_hash` and returns the difficulty the Monero block achieved,
+    /// hashing it with a VM for `seed_hash` from `verifier`.
+    ///
+    /// Only a single merged chain is supported: the proof carries no branch through the merged chains' tree, so a tag
+    /// with a depth other than 0 is rejected with [`MergeMiningError::UnsupportedMergeMiningDepth`].
+    pub fn verify(&self, verifier: &Verifier, merge_mining_hash: &[u8; 32]) -> Result<u128, MergeMiningError> {
+        let coinbase = Coinbase::parse(&self.coinbase_tx)?;
+        let tag = coinbase
+            .merge_mining_tag()
+            .ok_or(MergeMiningError::MissingMergeMiningTag)?;
+        if tag.depth != 0 {
+            return Err(MergeMiningError::UnsupportedMergeMiningDepth(tag.depth));
+        }
+        if &tag.merkle_root != merge_mining_hash {
+            return Err(MergeMiningError::MergeMiningHashMismatch);
+        }
+        if coinbase_branch_len(self.tx_count) != Some(self.coinbase_branch.len()) {
+            return Err(MergeMiningError::InvalidBranchLength);
+        }
+        let root = self
+            .coinbase_branch
+            .iter()
+            .fold(coinbase.hash(), |hash, sibling| hash_pair(&hash, sibling));
+        if root != self.merkle_root {
+            return Err(MergeMiningError::MerkleRootMismatch);
+        }
+        let blob = HashingBlob::new(&self.header, &self.merkle_root, self.tx_count);
+        let hash = verifier.calculate_hash(&self.seed_hash, blob.as_bytes())?;
+        let hash: [u8; 32] = hash.as_slice().try_into().map_err(|_| RandomXError::EmptyHash)?;
+        Ok(achieved_difficulty(&hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use crate::{
+        difficulty::achieved_difficulty,
+        merge_mining::{coinbase_branch, coinbase_branch_len, Coinbase, MergeMiningError, MergeMiningProof},
+        monero::{fast_hash, hash_pair, tree_hash, write_varint, Block, BlockHeader},
+        verifier::Verifier,
+        RandomXFlag,
+    };
+
+    // A version 2 coinbase paying one output with a view tag, committing to `merge_mining_hash`.
+    fn coinbase(height: u64, merge_mining_hash: &[u8; 32]) -> Vec<u8> {
+        let mut tx = vec![2];
+        write_varint(&mut tx, height + 60);
+        tx.extend_from_slice(&[1, 0xff]);
+        write_varint(&mut tx, height);
+        tx.push(1);
+        write_varint(&mut tx, 600_000_000_000);
+        tx.push(0x03);
+        tx.extend_from_slice(&fast_hash(b"output key"));
+        tx.push(0x5a);
+        let mut extra = vec![0x01];
+        extra.extend_from_slice(&fast_hash(b"tx public key"));
+        extra.extend_from_slice(&[0x02, 4, 0xde, 0xad, 0xbe, 0xef]);
+        extra.extend_from_slice(&[0x03, 33, 0]);
+        extra.extend_from_slice(merge_mining_hash);
+        write_varint(&mut tx, extra.len() as u64);
+        tx.extend_from_slice(&extra);
+        // The RingCT signature type, none.
+        tx.push(0);
+        tx
+    }
+
+    #[test]
+    fn merge_mining_mainnet_coinbases() {
+        let fixtures = include_str!("../tests/fixtures/monero_mainnet_coinbases.txt");
+        let mut count = 0;
+        for line in fixtures.lines().filter(|line| !line.starts_with('#')) {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let [height, tx, hash]: [&str; 3] = fields.try_into().unwrap();
+            let coinbase = Coinbase::parse(&hex::decode(tx).unwrap()).unwrap();
+            assert_eq!(coinbase.height(), height.parse::<u64>().unwrap());
+            assert_eq!(hex::encode(coinbase.hash()), hash, "block {}", height);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    // A version 12 block at height 558175 from July 2020, so not a mainnet block, and the hashing blob monerod
+    // accepted for it, from the block serialisation test of the monero-rs crate (0.17.3). Its version 2 coinbase pays
+    // an output without a view tag, and is the only transaction.
+    #[test]
+    fn merge_mining_monero_v12_block() {
+        let coinbase_tx = hex::decode(
+            "029b892201ffdf882201b699d4c8b1ec020223df524af2a2ef5f870adb6e1ceb03a475c39f8b9ef76aa50b46ddd2a18349402b012839bf\
+             a19b7524ec7488917714c216ca254b38ed0424ca65ae828a7c006aeaf10208f5316a7f6b99cca600",
+        )
+        .unwrap();
+        let coinbase = Coinbase::parse(&coinbase_tx).unwrap();
+        assert_eq!(coinbase.version(), 2);
+        assert_eq!(coinbase.height(), 558_175);
+        assert_eq!(coinbase.merge_mining_tag(), None);
+        let block = Block {
+            header: BlockHeader {
+                major_version: 12,
+                minor_version: 12,
+                timestamp: 1_594_797_844,
+                prev_id: hex::decode("beb3489c722a285c092a32e7c6893abfc7d069699c8326fc3445a749c5276b62")
+                    .unwrap()
+                    .as_slice()
+                    .try_into()
+                    .unwrap(),
+                nonce: 0,
+            },
+            miner_tx_hash: coinbase.hash(),
+            tx_hashes: vec![],
+        };
+        assert_eq!(
+            hex::encode(block.hashing_blob().as_bytes()),
+            "0c0c94debaf805beb3489c722a285c092a32e7c6893abfc7d069699c8326fc3445a749c5276b6200000000602d0d4710e2c2d38da0cc\
+             e097accdf5dc18b1d34323880c1aae90ab8f6be6e201"
+        );
+    }
+
+    #[test]
+    fn merge_mining_coinbase_branch() {
+        let hashes: Vec<[u8; 32]> = (0u8..9).map(|i| fast_hash(&[i])).collect();
+        for count in 1..=hashes.len() {
+            let branch = coinbase_branch(&hashes[..count]).unwrap();
+            let root = branch.iter().fold(hashes[0], |hash, sibling| hash_pair(&hash, sibling));
+            assert_eq!(root, tree_hash(&hashes[..count]).unwrap(), "{} hashes", count);
+            assert_eq!(
+                coinbase_branch_len(count as u64),
+                Some(branch.len()),
+                "{} hashes",
+                count
+            );
+        }
+        assert!(coinbase_branch(&[]).is_err());
+        assert_eq!(coinbase_branch_len(u64::MAX), None);
+    }
+
+    #[test]
+    fn merge_mining_proof_verification() {
+        let merge_mining_hash = fast_hash(b"merge-mined block");
+        let coinbase_tx = coinbase(3_000_000, &merge_mining_hash);
+        let coinbase = Coinbase::parse(&coinbase_tx).unwrap();
+        assert_eq!(coinbase.version(), 2);
+        assert_eq!(coinbase.height(), 3_000_000);
+        assert_eq!(coinbase.merge_mining_tag().unwrap().merkle_root, merge_mining_hash);
+
+        let block = Block {
+            header: BlockHeader {
+                major_version: 16,
+                minor_version: 16,
+                timestamp: 1_700_000_000,
+                prev_id: fast_hash(b"previous block"),
+                nonce: 0x1234_5678,
+            },
+            miner_tx_hash: coinbase.hash(),
+            tx_hashes: (0u8..4).map(|i| fast_hash(&[i])).collect(),
+        };
+        let mut hashes = vec![block.miner_tx_hash];
+        hashes.extend_from_slice(&block.tx_hashes);
+        let proof = MergeMiningProof {
+            header: block.header.clone(),
+            seed_hash: fast_hash(b"seed block"),
+            tx_count: 5,
+            merkle_root: block.tx_tree_hash(),
+            coinbase_branch: coinbase_branch(&hashes).unwrap(),
+            coinbase_tx,
+        };
+
+        let verifier = Verifier::new(RandomXFlag::get_recommended_flags(), 1).unwrap();
+        let hash = verifier
+            .calculate_hash(&proof.seed_hash, block.hashing_blob().as_bytes())
+            .unwrap();
+        let difficulty = proof.verify(&verifier, &merge_mining_hash).unwrap();
+        assert_eq!(difficulty, achieved_difficulty(&hash.as_slice().try_into().unwrap()));
+
+        assert!(matches!(
+            proof.verify(&verifier, &fast_hash(b"another block")),
+            Err(MergeMiningError::MergeMiningHashMismatch)
+        ));
+        let mut tampered = proof.clone();
+        tampered.coinbase_branch[0][0] ^= 1;
+        assert!(matches!(
+            tampered.verify(&verifier, &merge_mining_hash),
+            Err(MergeMiningError::MerkleRootMismatch)
+        ));
+        let mut tampered = proof.clone();
+        tampered.tx_count = 3;
+        assert!(matches!(
+            tampered.verify(&verifier, &merge_mining_hash),
+            Err(MergeMiningError::InvalidBranchLength)
+        ));
+        let mut tampered = proof.clone();
+        let depth = tampered.coinbase_tx.len() - 34;
+        tampered.coinbase_tx[depth] = 1;
+        assert!(matches!(
+            tampered.verify(&verifier, &merge_mining_hash),
+            Err(MergeMiningError::UnsupportedMergeMiningDepth(1))
+        ));
+        let mut tampered = proof;
+        tampered.coinbase_tx.truncate(tampered.coinbase_tx.len() - 10);
+        assert!(matches!(
+            tampered.verify(&verifier, &merge_mining_hash),
+            Err(MergeMiningError::MalformedCoinbase(_))
+        ));
+    }
+
+    #[test]
+    #[ignore = "tests/fixtures/tari_merge_mined_blocks.txt does not list any blocks yet"]
+    fn merge_mining_tari_mainnet_proofs() {
+        let fixtures = include_str!("../tests/fixtures/tari_merge_mined_blocks.txt");
+        let hash = |hex: &str| -> [u8; 32] { hex::decode(hex).unwrap().as_slice().try_into().unwrap() };
+        let verifier = Verifier::new(RandomXFlag::get_recommended_flags(), 2).unwrap();
+        let mut count = 0;
+        for line in fixtures.lines().filter(|line| !line.starts_with('#')) {
+            let mut fields = line.split_whitespace();
+            let mut next = || fields.next().unwrap();
+            let height = next();
+            let merge_mining_hash = hash(next());
+            let proof = MergeMiningProof {
+                header: BlockHeader {
+                    major_version: next().parse().unwrap(),
+                    minor_version: next().parse().unwrap(),
+                    timestamp: next().parse().unwrap(),
+                    prev_id: hash(next()),
+                    nonce: next().parse().unwrap(),
+                },
+                seed_hash: hash(next()),
+                tx_count: next().parse().unwrap(),
+                merkle_root: hash(next()),
+                coinbase_branch: next().split(',').filter(|h| *h != "-").map(hash).collect(),
+                coinbase_tx: hex::decode(next()).unwrap(),
+            };
+            let difficulty: u128 = next().parse().unwrap();
+            assert_eq!(fields.next(), None, "Tari block {}", height);
+            let achieved = proof.verify(&verifier, &merge_mining_hash).unwrap();
+            assert!(achieved >= difficulty, "Tari block {}", height);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+}
@@ -0,0 +1,309 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Prometheus-style metrics for caches, datasets, VMs and the [`Verifier`](crate::verifier::Verifier).
+//!
+//! With the `metrics` feature the crate records into a process-wide registry as its objects are used; without it
+//! nothing is recorded. [`render`] writes every metric in the Prometheus text exposition format, to be served from a
+//! scrape endpoint. The hashrate is `rate(randomx_hashes_total[1m])`, and the number of cache or dataset rebuilds
+//! the `_count` of their duration histograms.
+
+use std::{
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+        PoisonError,
+    },
+    time::Duration,
+};
+
+/// A count that only goes up.
+#[derive(Debug)]
+pub(crate) struct Counter(AtomicUsize);
+
+impl Counter {
+    const fn new() -> Counter {
+        Counter(AtomicUsize::new(0))
+    }
+
+    pub(crate) fn add(&self, count: usize) {
+        self.0.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+/// A count of live objects.
+#[derive(Debug)]
+pub(crate) struct Gauge(AtomicUsize);
+
+impl Gauge {
+    const fn new() -> Gauge {
+        Gauge(AtomicUsize::new(0))
+    }
+
+    pub(crate) fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Durations in seconds, counted in buckets with the given upper bounds.
+#[derive(Debug)]
+pub(crate) struct Histogram {
+    bounds: [f64; 8],
+    state: Mutex<HistogramState>,
+}
+
+#[derive(Debug)]
+struct HistogramState {
+    // Not cumulative, the last bucket is for durations above all bounds.
+    buckets: [usize; 9],
+    sum: f64,
+}
+
+impl Histogram {
+    const fn new(bounds: [f64; 8]) -> Histogram {
+        Histogram {
+            bounds,
+            state: Mutex::new(HistogramState {
+                buckets: [0; 9],
+                sum: 0.0,
+            }),
+        }
+    }
+
+    pub(crate) fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = self.bounds.iter().take_while(|bound| seconds > **bound).count();
+        // The state is updated in one step, so a panic elsewhere does not invalidate it.
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.buckets[bucket] += 1;
+        state.sum += seconds;
+    }
+}
+
+/// Caches currently allocated.
+pub(crate) static CACHES: Gauge = Gauge::new();
+/// Datasets currently allocated.
+pub(crate) static DATASETS: Gauge = Gauge::new();
+/// VMs currently allocated.
+pub(crate) static VMS: Gauge = Gauge::new();
+/// Hashes calculated by VMs.
+pub(crate) static HASHES: Counter = Counter::new();
+/// Dataset items initialized, by full builds and by [`RandomXDataset::init`](crate::RandomXDataset::init).
+pub(crate) static DATASET_ITEMS: Counter = Counter::new();
+/// Verifier lookups that found a cache for the key.
+pub(crate) static VERIFIER_HITS: Counter = Counter::new();
+/// Verifier lookups that had to build a cache.
+pub(crate) static VERIFIER_MISSES: Counter = Counter::new();
+/// Cache (re-)initializations with a key, i.e. Argon2 runs.
+pub(crate) static CACHE_INIT_SECONDS: Histogram = Histogram::new([0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]);
+/// Builds of a whole dataset.
+pub(crate) static DATASET_BUILD_SECONDS: Histogram = Histogram::new([1.0, 2.5, 5.0, 10.0, 20.0, 40.0, 80.0, 160.0]);
+
+#[derive(Debug)]
+enum Metric {
+    Counter(&'static Counter),
+    Gauge(&'static Gauge),
+    Histogram(&'static Histogram),
+}
+
+static REGISTRY: [(&str, &str, Metric); 9] = [
+    (
+        "randomx_caches",
+        "RandomX caches currently allocated.",
+        Metric::Gauge(&CACHES),
+    ),
+    (
+        "randomx_datasets",
+        "RandomX datasets currently allocated.",
+        Metric::Gauge(&DATASETS),
+    ),
+    ("randomx_vms", "RandomX VMs currently allocated.", Metric::Gauge(&VMS)),
+    (
+        "randomx_hashes_total",
+        "RandomX hashes calculated.",
+        Metric::Counter(&HASHES),
+    ),
+    (
+        "randomx_dataset_items_total",
+        "RandomX dataset items initialized.",
+        Metric::Counter(&DATASET_ITEMS),
+    ),
+    (
+        "randomx_verifier_cache_hits_total",
+        "Verifier hashes with a cache for the key already built.",
+        Metric::Counter(&VERIFIER_HITS),
+    ),
+    (
+        "randomx_verifier_cache_misses_total",
+        "Verifier hashes that had to build a cache for the key.",
+        Metric::Counter(&VERIFIER_MISSES),
+    ),
+    (
+        "randomx_cache_init_seconds",
+        "Time to initialize a RandomX cache with a key.",
+        Metric::Histogram(&CACHE_INIT_SECONDS),
+    ),
+    (
+        "randomx_dataset_build_seconds",
+        "Time to initialize a whole RandomX dataset.",
+        Metric::Histogram(&DATASET_BUILD_SECONDS),
+    ),
+];
+
+/// Returns all metrics in the Prometheus text exposition format (version 0.0.4).
+pub fn render() -> String {
+    let mut text = String::new();
+    // Writing to a `String` does not fail.
+    let _ignored = write_metrics(&mut text);
+    text
+}
+
+fn write_metrics(out: &mut String) -> fmt::Result {
+    for (name, help, metric) in &REGISTRY {
+        writeln!(out, "# HELP {} {}", name, help)?;
+        match metric {
+            Metric::Counter(counter) => {
+                writeln!(out, "# TYPE {} counter", name)?;
+                writeln!(out, "{} {}", name, counter.0.load(Ordering::Relaxed))?;
+            },
+            Metric::Gauge(gauge) => {
+                writeln!(out, "# TYPE {} gauge", name)?;
+                writeln!(out, "{} {}", name, gauge.0.load(Ordering::Relaxed))?;
+            },
+            Metric::Histogram(histogram) => {
+                writeln!(out, "# TYPE {} histogram", name)?;
+                let state = histogram.state.lock().unwrap_or_else(PoisonError::into_inner);
+                let mut count = 0;
+                for (bound, bucket) in histogram.bounds.iter().zip(state.buckets.iter()) {
+                    count += bucket;
+                    writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count)?;
+                }
+                count += state.buckets[8];
+                writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count)?;
+                writeln!(out, "{}_sum {}", name, state.sum)?;
+                writeln!(out, "{}_count {}", name, count)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use crate::{metrics, verifier::Verifier, RandomXCache, RandomXFlag, RandomXVM};
+
+    // Serves one scrape like a `/metrics` endpoint would.
+    fn endpoint(listener: &TcpListener) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 1024];
+        let length = stream.read(&mut request).unwrap();
+        assert!(request[..length].starts_with(b"GET /metrics HTTP/1.1\r\n"));
+        let body = metrics::render();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: \
+             close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+    }
+
+    fn scrape() -> HashMap<String, f64> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || endpoint(&listener));
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        body.lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let (name, value) = line.rsplit_once(' ').unwrap();
+                (name.to_string(), value.parse().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn metrics_scraped_over_http() {
+        let flags = RandomXFlag::get_recommended_flags();
+        let before = scrape();
+        {
+            let cache = RandomXCache::new(flags, b"metrics key").unwrap();
+            let vm = RandomXVM::new(flags, Some(cache), None).unwrap();
+            vm.calculate_hash(b"input").unwrap();
+            vm.calculate_hash_set(&[b"one", b"two", b"three"]).unwrap();
+            let verifier = Verifier::new(flags, 1).unwrap();
+            verifier.calculate_hash(b"metrics key", b"input").unwrap();
+            verifier.calculate_hash(b"metrics key", b"input").unwrap();
+            let during = scrape();
+            assert!(during["randomx_vms"] >= 1.0);
+            assert!(during["randomx_caches"] >= 2.0);
+        }
+        let after = scrape();
+
+        // Other tests run in parallel, so the counters may have gone up further.
+        let increase = |name: &str| after[name] - before[name];
+        assert!(increase("randomx_hashes_total") >= 6.0);
+        assert!(increase("randomx_verifier_cache_hits_total") >= 1.0);
+        assert!(increase("randomx_verifier_cache_misses_total") >= 1.0);
+        assert!(increase("randomx_cache_init_seconds_count") >= 2.0);
+        assert!(increase("randomx_cache_init_seconds_sum") > 0.0);
+        let buckets = after
+            .iter()
+            .filter(|(name, _)| name.starts_with("randomx_cache_init_seconds_bucket"))
+            .map(|(_, count)| *count)
+            .fold(0.0, f64::max);
+        assert_eq!(buckets, after["randomx_cache_init_seconds_bucket{le=\"+Inf\"}"]);
+        assert_eq!(buckets, after["randomx_cache_init_seconds_count"]);
+    }
+
+    #[test]
+    fn metrics_render_format() {
+        let text = metrics::render();
+        assert!(text.contains("# TYPE randomx_hashes_total counter\nrandomx_hashes_total "));
+        assert!(text.contains("# TYPE randomx_vms gauge\n"));
+        assert!(text.contains("# TYPE randomx_dataset_build_seconds histogram\n"));
+        assert!(text.contains("randomx_dataset_build_seconds_bucket{le=\"2.5\"} "));
+        assert!(text.ends_with('\n'));
+    }
+}
@@ -0,0 +1,398 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Monero block hashing blobs, for hashing blocks with RandomX.
+//!
+//! Monero does not hash a whole block for its proof of work, but a hashing blob: the block header, the root of the
+//! tree hash of its transactions (the miner transaction first) and the number of transactions. The header fields are
+//! varints, so the nonce is at offset 39 only for blocks with a 5-byte timestamp, which is every block since 2014.
+//! All hashes other than RandomX are Keccak-256 (`cn_fast_hash`).
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::convert::TryInto;
+
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::RandomXError;
+#[cfg(feature = "std")]
+use crate::{difficulty::meets_difficulty, verifier::Verifier};
+
+/// Returns the Keccak-256 hash of `data`, which Monero calls `cn_fast_hash`.
+pub fn fast_hash(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+    hash
+}
+
+pub(crate) fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(left);
+    hasher.update(right);
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+    hash
+}
+
+/// Appends `value` to `out` as a Monero varint: 7 bits per byte, least significant first.
+// Each byte keeps the low 7 bits of what is left of the value.
+#[allow(clippy::cast_possible_truncation)]
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a Monero varint from the front of `input` and advances it past the varint. Returns `None` if the varint is
+/// truncated or does not fit in 64 bits.
+pub fn read_varint(input: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, &byte) in input.iter().enumerate().take(10) {
+        let bits = u64::from(byte & 0x7f);
+        let shift = 7 * i;
+        if shift == 63 && bits > 1 {
+            return None;
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            *input = &input[i + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Returns Monero's tree hash of `hashes`, the Merkle root of a block's transaction hashes.
+///
+/// The tree is not a plain binary tree: the leaves beyond the largest power of two below their count are hashed in
+/// pairs first.
+pub fn tree_hash(hashes: &[[u8; 32]]) -> Result<[u8; 32], RandomXError> {
+    match hashes {
+        [] => Err(RandomXError::InvalidParameter {
+            name: "hashes",
+            reason: "must not be empty",
+        }),
+        [hash] => Ok(*hash),
+        [left, right] => Ok(hash_pair(left, right)),
+        _ => {
+            // The largest power of two below the count.
+            let mut count = hashes.len().next_power_of_two() / 2;
+            let direct = 2 * count - hashes.len();
+            let mut level = hashes[..direct].to_vec();
+            level.extend(
+                hashes[direct..]
+                    .chunks_exact(2)
+                    .map(|pair| hash_pair(&pair[0], &pair[1])),
+            );
+            while count > 2 {
+                count /= 2;
+                level = level
+                    .chunks_exact(2)
+                    .map(|pair| hash_pair(&pair[0], &pair[1]))
+                    .collect();
+            }
+            Ok(hash_pair(&level[0], &level[1]))
+        },
+    }
+}
+
+/// The fields of a Monero block header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockHeader {
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub timestamp: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes"))]
+    pub prev_id: [u8; 32],
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    /// Returns the serialised header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.prefix();
+        bytes.extend_from_slice(&self.nonce.to_le_bytes());
+        bytes
+    }
+
+    // The header up to the nonce.
+    fn prefix(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(76);
+        write_varint(&mut bytes, u64::from(self.major_version));
+        write_varint(&mut bytes, u64::from(self.minor_version));
+        write_varint(&mut bytes, self.timestamp);
+        bytes.extend_from_slice(&self.prev_id);
+        bytes
+    }
+}
+
+/// A Monero block, reduced to what its hashing blob is built from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+    pub header: BlockHeader,
+    /// The hash of the miner (coinbase) transaction.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes"))]
+    pub miner_tx_hash: [u8; 32],
+    /// The hashes of the other transactions, in block order.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes::vec"))]
+    pub tx_hashes: Vec<[u8; 32]>,
+}
+
+impl Block {
+    /// Returns the root of the tree hash of the miner transaction and the other transactions.
+    pub fn tx_tree_hash(&self) -> [u8; 32] {
+        let mut hashes = Vec::with_capacity(1 + self.tx_hashes.len());
+        hashes.push(self.miner_tx_hash);
+        hashes.extend_from_slice(&self.tx_hashes);
+        // There is always the miner transaction.
+        tree_hash(&hashes).unwrap_or_default()
+    }
+
+    /// Builds the hashing blob of the block.
+    pub fn hashing_blob(&self) -> HashingBlob {
+        HashingBlob::new(&self.header, &self.tx_tree_hash(), 1 + self.tx_hashes.len() as u64)
+    }
+
+    /// Returns the block id, see [`HashingBlob::block_id`].
+    pub fn id(&self) -> [u8; 32] {
+        self.hashing_blob().block_id()
+    }
+}
+
+/// The input to hash for a block's proof of work, with the nonce at a known offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashingBlob {
+    blob: Vec<u8>,
+    nonce_offset: usize,
+}
+
+impl HashingBlob {
+    /// Builds the hashing blob of a block with `header` and `tx_count` transactions, the miner transaction included,
+    /// whose tree hash is `tx_tree_hash`.
+    pub fn new(header: &BlockHeader, tx_tree_hash: &[u8; 32], tx_count: u64) -> HashingBlob {
+        let mut blob = header.prefix();
+        let nonce_offset = blob.len();
+        blob.extend_from_slice(&header.nonce.to_le_bytes());
+        blob.extend_from_slice(tx_tree_hash);
+        write_varint(&mut blob, tx_count);
+        HashingBlob { blob, nonce_offset }
+    }
+
+    /// Returns the blob, the input to [`RandomXVM::calculate_hash`](crate::RandomXVM::calculate_hash).
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.blob
+    }
+
+    /// Returns the offset of the 4-byte little-endian nonce in the blob.
+    pub fn nonce_offset(&self) -> usize {
+        self.nonce_offset
+    }
+
+    /// Returns the nonce in the blob.
+    pub fn nonce(&self) -> u32 {
+        let mut nonce = [0u8; 4];
+        nonce.copy_from_slice(&self.blob[self.nonce_offset..self.nonce_offset + 4]);
+        u32::from_le_bytes(nonce)
+    }
+
+    /// Writes `nonce` into the blob in place, as a miner does for each attempt.
+    pub fn set_nonce(&mut self, nonce: u32) {
+        self.blob[self.nonce_offset..self.nonce_offset + 4].copy_from_slice(&nonce.to_le_bytes());
+    }
+
+    /// Returns the block id: the fast hash of the blob, prefixed with its length as a varint. This is the wrong id
+    /// for mainnet block 202612: Monero hardcodes the id of that malformed block, which this function does not.
+    pub fn block_id(&self) -> [u8; 32] {
+        let mut data = Vec::with_capacity(self.blob.len() + 1);
+        write_varint(&mut data, self.blob.len() as u64);
+        data.extend_from_slice(&self.blob);
+        fast_hash(&data)
+    }
+
+    /// Returns whether the RandomX hash of the blob under `seed_hash`, the id of the block the seed height points at,
+    /// meets `difficulty`.
+    #[cfg(feature = "std")]
+    pub fn verify(&self, verifier: &Verifier, seed_hash: &[u8], difficulty: u128) -> Result<bool, RandomXError> {
+        let hash = verifier.calculate_hash(seed_hash, &self.blob)?;
+        let hash: Result<&[u8; 32], _> = hash.as_slice().try_into();
+        Ok(matches!(hash, Ok(hash) if meets_difficulty(hash, difficulty)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use crate::monero::{fast_hash, read_varint, tree_hash, write_varint, Block, BlockHeader};
+    #[cfg(feature = "std")]
+    use crate::{verifier::Verifier, RandomXFlag};
+
+    fn hash(hex: &str) -> [u8; 32] {
+        hex::decode(hex).unwrap().as_slice().try_into().unwrap()
+    }
+
+    fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        fast_hash(&[&left[..], &right[..]].concat())
+    }
+
+    /// Parses a line of the block fixtures into the height, the block, its id and the fields after the id.
+    fn parse_block(line: &str) -> (&str, Block, &str, Vec<&str>) {
+        let mut fields = line.split_whitespace();
+        let mut next = || fields.next().unwrap();
+        let height = next();
+        let block = Block {
+            header: BlockHeader {
+                major_version: next().parse().unwrap(),
+                minor_version: next().parse().unwrap(),
+                timestamp: next().parse().unwrap(),
+                prev_id: hash(next()),
+                nonce: next().parse().unwrap(),
+            },
+            miner_tx_hash: hash(next()),
+            tx_hashes: next().split(',').filter(|h| *h != "-").map(hash).collect(),
+        };
+        let id = next();
+        (height, block, id, fields.collect())
+    }
+
+    #[test]
+    fn monero_mainnet_blocks() {
+        let fixtures = include_str!("../tests/fixtures/monero_mainnet_blocks.txt");
+        let blocks = fixtures.lines().filter(|line| !line.starts_with('#'));
+        let mut count = 0;
+        for line in blocks {
+            let (height, block, id, rest) = parse_block(line);
+            assert!(rest.is_empty(), "block {}", height);
+            assert_eq!(hex::encode(block.id()), id, "block {}", height);
+            let blob = block.hashing_blob();
+            assert_eq!(blob.nonce(), block.header.nonce);
+            assert_eq!(
+                &blob.as_bytes()[..blob.nonce_offset()],
+                &block.header.to_bytes()[..blob.nonce_offset()]
+            );
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn monero_tree_hash() {
+        let hashes: Vec<[u8; 32]> = (0u8..5).map(|i| fast_hash(&[i])).collect();
+        assert!(tree_hash(&[]).is_err());
+        assert_eq!(tree_hash(&hashes[..1]).unwrap(), hashes[0]);
+        assert_eq!(tree_hash(&hashes[..2]).unwrap(), hash_pair(&hashes[0], &hashes[1]));
+        // The first leaf is carried up a level, the others are paired.
+        assert_eq!(
+            tree_hash(&hashes[..3]).unwrap(),
+            hash_pair(&hashes[0], &hash_pair(&hashes[1], &hashes[2]))
+        );
+        assert_eq!(
+            tree_hash(&hashes[..4]).unwrap(),
+            hash_pair(&hash_pair(&hashes[0], &hashes[1]), &hash_pair(&hashes[2], &hashes[3]))
+        );
+        assert_eq!(
+            tree_hash(&hashes).unwrap(),
+            hash_pair(
+                &hash_pair(&hashes[0], &hashes[1]),
+                &hash_pair(&hashes[2], &hash_pair(&hashes[3], &hashes[4]))
+            )
+        );
+    }
+
+    #[test]
+    fn monero_varint() {
+        for (value, bytes) in [
+            (0, "00"),
+            (0x7f, "7f"),
+            (0x80, "8001"),
+            (1_700_000_000, "80e2cfaa06"),
+            (u64::MAX, "ffffffffffffffffff01"),
+        ] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            assert_eq!(hex::encode(&out), bytes);
+            let mut input = &out[..];
+            assert_eq!(read_varint(&mut input), Some(value));
+            assert!(input.is_empty());
+        }
+        assert_eq!(read_varint(&mut &[0x80, 0x80][..]), None);
+        assert_eq!(
+            read_varint(&mut &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02][..]),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn monero_nonce_patching_and_verification() {
+        let mut block = Block {
+            header: BlockHeader {
+                major_version: 16,
+                minor_version: 16,
+                timestamp: 1_700_000_000,
+                prev_id: fast_hash(b"previous block"),
+                nonce: 0,
+            },
+            miner_tx_hash: fast_hash(b"miner transaction"),
+            tx_hashes: vec![fast_hash(b"transaction")],
+        };
+        let mut blob = block.hashing_blob();
+        assert_eq!(blob.nonce_offset(), 39);
+        assert_eq!(blob.as_bytes().len(), 76);
+
+        blob.set_nonce(0xdead_beef);
+        assert_eq!(blob.nonce(), 0xdead_beef);
+        block.header.nonce = 0xdead_beef;
+        assert_eq!(blob, block.hashing_blob());
+
+        let verifier = Verifier::new(RandomXFlag::get_recommended_flags(), 1).unwrap();
+        assert!(blob.verify(&verifier, b"test key 000", 1).unwrap());
+        assert!(!blob.verify(&verifier, b"test key 000", u128::MAX).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    #[ignore = "tests/fixtures/monero_mainnet_randomx_blocks.txt does not list any blocks yet"]
+    fn monero_mainnet_randomx_blocks_verify() {
+        let fixtures = include_str!("../tests/fixtures/monero_mainnet_randomx_blocks.txt");
+        let blocks = fixtures.lines().filter(|line| !line.starts_with('#'));
+        let verifier = Verifier::new(RandomXFlag::get_recommended_flags(), 2).unwrap();
+        let mut count = 0;
+        for line in blocks {
+            let (height, block, id, rest) = parse_block(line);
+            let [seed_hash, difficulty]: [&str; 2] = rest.try_into().unwrap();
+            assert!(block.header.major_version >= 12, "block {}", height);
+            assert_eq!(hex::encode(block.id()), id, "block {}", height);
+            let blob = block.hashing_blob();
+            let difficulty = difficulty.parse().unwrap();
+            assert!(blob.verify(&verifier, &hash(seed_hash), difficulty).unwrap(), "block {}", height);
+            count += 1;
+        }
+        assert!(count >= 2);
+    }
+}
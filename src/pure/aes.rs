@@ -0,0 +1,264 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Software AES rounds (the semantics of x86 `AESENC` and `AESDEC`) and RandomX's AES-based generators and hash.
+//!
+//! A 128-bit value is held as four little-endian 32-bit columns, so `[a, b, c, d]` is `_mm_set_epi32(d, c, b, a)`.
+
+#![forbid(unsafe_code)]
+// RandomX is defined on the bits of fixed-width integers, which are converted between widths and signedness as-is.
+#![allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+
+type Block = [u32; 4];
+
+const fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 };
+        b >>= 1;
+    }
+    product
+}
+
+const fn sbox() -> [u8; 256] {
+    let mut sbox = [0u8; 256];
+    let mut x = 0;
+    while x < 256 {
+        // The multiplicative inverse is x^254.
+        let mut inverse = 1u8;
+        let mut i = 0;
+        while i < 254 {
+            inverse = gf_mul(inverse, x as u8);
+            i += 1;
+        }
+        let b = inverse;
+        sbox[x] = b ^ b.rotate_left(1) ^ b.rotate_left(2) ^ b.rotate_left(3) ^ b.rotate_left(4) ^ 0x63;
+        x += 1;
+    }
+    sbox
+}
+
+const fn inverse_sbox(sbox: &[u8; 256]) -> [u8; 256] {
+    let mut inverse = [0u8; 256];
+    let mut x = 0;
+    while x < 256 {
+        inverse[sbox[x] as usize] = x as u8;
+        x += 1;
+    }
+    inverse
+}
+
+/// Builds the table of SubBytes (or InvSubBytes) followed by one column of MixColumns (or InvMixColumns), for a byte in
+/// row 0; the other rows are rotations of it.
+const fn table(sbox: &[u8; 256], coefficients: [u8; 4]) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut x = 0;
+    while x < 256 {
+        let s = sbox[x];
+        table[x] = gf_mul(s, coefficients[0]) as u32 |
+            (gf_mul(s, coefficients[1]) as u32) << 8 |
+            (gf_mul(s, coefficients[2]) as u32) << 16 |
+            (gf_mul(s, coefficients[3]) as u32) << 24;
+        x += 1;
+    }
+    table
+}
+
+const SBOX: [u8; 256] = sbox();
+static ENC_TABLE: [u32; 256] = table(&SBOX, [2, 1, 1, 3]);
+static DEC_TABLE: [u32; 256] = table(&inverse_sbox(&SBOX), [14, 9, 13, 11]);
+
+#[inline]
+fn byte(column: u32, row: usize) -> usize {
+    ((column >> (8 * row)) & 0xff) as usize
+}
+
+/// One AES encryption round: ShiftRows, SubBytes, MixColumns, AddRoundKey.
+#[inline]
+pub(crate) fn aesenc(state: Block, key: Block) -> Block {
+    let mut out = [0u32; 4];
+    for (c, column) in out.iter_mut().enumerate() {
+        *column = ENC_TABLE[byte(state[c], 0)] ^
+            ENC_TABLE[byte(state[(c + 1) % 4], 1)].rotate_left(8) ^
+            ENC_TABLE[byte(state[(c + 2) % 4], 2)].rotate_left(16) ^
+            ENC_TABLE[byte(state[(c + 3) % 4], 3)].rotate_left(24) ^
+            key[c];
+    }
+    out
+}
+
+/// One AES decryption round: InvShiftRows, InvSubBytes, InvMixColumns, AddRoundKey.
+#[inline]
+pub(crate) fn aesdec(state: Block, key: Block) -> Block {
+    let mut out = [0u32; 4];
+    for (c, column) in out.iter_mut().enumerate() {
+        *column = DEC_TABLE[byte(state[c], 0)] ^
+            DEC_TABLE[byte(state[(c + 3) % 4], 1)].rotate_left(8) ^
+            DEC_TABLE[byte(state[(c + 2) % 4], 2)].rotate_left(16) ^
+            DEC_TABLE[byte(state[(c + 1) % 4], 3)].rotate_left(24) ^
+            key[c];
+    }
+    out
+}
+
+const GEN_1R_KEYS: [Block; 4] = [
+    [0x6dac_a553, 0x6271_6609, 0xdbb5_552b, 0xb4f4_4917],
+    [0x6d7c_af07, 0x846a_710d, 0x1725_d378, 0x0da1_dc4e],
+    [0x3f12_62f1, 0x9f94_7ec6, 0xf4c0_794f, 0x3e20_e345],
+    [0x6aef_8135, 0xb1ba_317c, 0x1631_4c88, 0x4916_9154],
+];
+
+const GEN_4R_KEYS: [Block; 8] = [
+    [0x6421_aadd, 0xd183_3ddb, 0x2f54_6d2b, 0x99e5_d23f],
+    [0xb20e_3450, 0xb691_3f55, 0x06f7_9d53, 0xa5df_cde5],
+    [0x5c3e_d904, 0x515e_7baf, 0x0aa4_679f, 0x171c_02bf],
+    [0x8562_3763, 0xe78f_5d08, 0xcd67_3785, 0xd8de_d291],
+    [0xb582_6f73, 0xe3d6_a7a6, 0x3d51_8b6d, 0x229e_ffb4],
+    [0xc756_6bf3, 0x9c10_b3d9, 0xe902_4d4e, 0xb272_b7d2],
+    [0xf273_c9e7, 0xf765_a38b, 0x2ba9_660a, 0xf63b_efa7],
+    [0x7a7c_d609, 0x9158_39de, 0x0c06_d1fd, 0xc0b0_762d],
+];
+
+const HASH_1R_STATE: [Block; 4] = [
+    [0x92b5_2c0d, 0x9fa8_56de, 0xcc82_db47, 0xd798_3aad],
+    [0x338d_996e, 0x15c7_b798, 0xf59e_125a, 0xace7_8057],
+    [0x6a77_0017, 0xae62_c7d0, 0x5079_506b, 0xe8a0_7ce4],
+    [0x630a_240c, 0x07ad_828d, 0x79a1_0005, 0x7e99_4948],
+];
+
+const HASH_1R_XKEYS: [Block; 2] = [[0xf6fa_8389, 0x8b24_949f, 0x90dc_56bf, 0x0689_0201], [
+    0x61b2_63d1,
+    0x51f4_e03c,
+    0xee10_43c6,
+    0xed18_f99b,
+]];
+
+/// Splits a 64-byte state into four blocks.
+pub(crate) fn load_state(bytes: &[u8; 64]) -> [Block; 4] {
+    let mut state = [[0u32; 4]; 4];
+    for (i, chunk) in bytes.chunks_exact(4).enumerate() {
+        state[i / 4][i % 4] = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    state
+}
+
+fn store_state(state: &[Block; 4], bytes: &mut [u8; 64]) {
+    for (i, chunk) in bytes.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&state[i / 4][i % 4].to_le_bytes());
+    }
+}
+
+/// Writes one block as two little-endian 64-bit words.
+#[inline]
+fn store_words(block: Block, out: &mut [u64]) {
+    out[0] = u64::from(block[0]) | u64::from(block[1]) << 32;
+    out[1] = u64::from(block[2]) | u64::from(block[3]) << 32;
+}
+
+/// AesGenerator1R: fills `out` (a multiple of 8 words) and leaves the final generator state in `seed`.
+pub(crate) fn fill_aes_1rx4(seed: &mut [u8; 64], out: &mut [u64]) {
+    let mut state = load_state(seed);
+    for chunk in out.chunks_exact_mut(8) {
+        state[0] = aesdec(state[0], GEN_1R_KEYS[0]);
+        state[1] = aesenc(state[1], GEN_1R_KEYS[1]);
+        state[2] = aesdec(state[2], GEN_1R_KEYS[2]);
+        state[3] = aesenc(state[3], GEN_1R_KEYS[3]);
+        for (i, block) in state.iter().enumerate() {
+            store_words(*block, &mut chunk[2 * i..]);
+        }
+    }
+    store_state(&state, seed);
+}
+
+/// AesGenerator4R: fills `out` (a multiple of 8 words) from `seed`.
+pub(crate) fn fill_aes_4rx4(seed: &[u8; 64], out: &mut [u64]) {
+    let mut state = load_state(seed);
+    for chunk in out.chunks_exact_mut(8) {
+        for round in 0..4 {
+            state[0] = aesdec(state[0], GEN_4R_KEYS[round]);
+            state[1] = aesenc(state[1], GEN_4R_KEYS[round]);
+            state[2] = aesdec(state[2], GEN_4R_KEYS[round + 4]);
+            state[3] = aesenc(state[3], GEN_4R_KEYS[round + 4]);
+        }
+        for (i, block) in state.iter().enumerate() {
+            store_words(*block, &mut chunk[2 * i..]);
+        }
+    }
+}
+
+/// AesHash1R: hashes `input` (a multiple of 8 words) into 64 bytes.
+pub(crate) fn hash_aes_1rx4(input: &[u64]) -> [u8; 64] {
+    let mut state = HASH_1R_STATE;
+    for chunk in input.chunks_exact(8) {
+        for (i, block) in state.iter_mut().enumerate() {
+            let lo = chunk[2 * i];
+            let hi = chunk[2 * i + 1];
+            let data = [lo as u32, (lo >> 32) as u32, hi as u32, (hi >> 32) as u32];
+            *block = if i % 2 == 0 {
+                aesenc(*block, data)
+            } else {
+                aesdec(*block, data)
+            };
+        }
+    }
+    for key in &HASH_1R_XKEYS {
+        state[0] = aesenc(state[0], *key);
+        state[1] = aesdec(state[1], *key);
+        state[2] = aesenc(state[2], *key);
+        state[3] = aesdec(state[3], *key);
+    }
+    let mut out = [0u8; 64];
+    store_state(&state, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sbox_known_entries() {
+        assert_eq!(SBOX[0x00], 0x63);
+        assert_eq!(SBOX[0x53], 0xed);
+        assert_eq!(SBOX[0xff], 0x16);
+    }
+
+    fn block(hex: &str) -> Block {
+        let bytes = hex::decode(hex).unwrap();
+        let mut block = [0u32; 4];
+        for (column, chunk) in block.iter_mut().zip(bytes.chunks_exact(4)) {
+            *column = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        block
+    }
+
+    #[test]
+    fn rounds_match_aesenc_and_aesdec() {
+        let state = block("000102030405060708090a0b0c0d0e0f");
+        let key = block("031425364758697a8b9cadbecfe0f102");
+        assert_eq!(aesenc(state, key), block("697e79736b355a2b3b45f0dfe87cd05e"));
+        assert_eq!(aesdec(state, key), block("10b7b32a694140149cd35f0c095d6a53"));
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! The Argon2d memory fill that initializes the RandomX cache.
+//!
+//! RandomX uses the filled memory itself, so there is no final tag; the tag length that goes into the initial hash is
+//! zero.
+
+#![forbid(unsafe_code)]
+// RandomX is defined on the bits of fixed-width integers, which are converted between widths and signedness as-is.
+#![allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+
+use core::convert::TryInto;
+
+use crate::blake2b::Blake2b;
+
+/// Size of an Argon2 block in 64-bit words.
+pub(crate) const BLOCK_WORDS: usize = 128;
+const SYNC_POINTS: u32 = 4;
+const VERSION: u32 = 0x13;
+const ARGON2D: u32 = 0;
+
+pub(crate) struct Params<'a> {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub lanes: u32,
+    pub salt: &'a [u8],
+    pub tag_length: u32,
+}
+
+impl Params<'_> {
+    /// The number of blocks that are filled, `memory_kib` rounded down to a multiple of `4 * lanes`.
+    pub fn block_count(&self) -> usize {
+        (self.memory_kib / (SYNC_POINTS * self.lanes) * SYNC_POINTS * self.lanes) as usize
+    }
+}
+
+/// Fills `memory`, which must hold `params.block_count()` blocks, from `password`.
+pub(crate) fn fill(memory: &mut [u64], params: &Params<'_>, password: &[u8]) {
+    let block_count = params.block_count();
+    assert_eq!(memory.len(), block_count * BLOCK_WORDS);
+    let lanes = params.lanes as usize;
+    let lane_length = block_count / lanes;
+    let segment_length = lane_length / SYNC_POINTS as usize;
+
+    let mut h0 = [0u8; 72];
+    let mut hasher = Blake2b::new(64);
+    hasher
+        .update(&params.lanes.to_le_bytes())
+        .update(&params.tag_length.to_le_bytes())
+        .update(&params.memory_kib.to_le_bytes())
+        .update(&params.iterations.to_le_bytes())
+        .update(&VERSION.to_le_bytes())
+        .update(&ARGON2D.to_le_bytes())
+        .update(&(password.len() as u32).to_le_bytes())
+        .update(password)
+        .update(&(params.salt.len() as u32).to_le_bytes())
+        .update(params.salt)
+        // No secret and no associated data.
+        .update(&0u32.to_le_bytes())
+        .update(&0u32.to_le_bytes())
+        .finalize(&mut h0[..64]);
+
+    for lane in 0..lanes {
+        for i in 0..2 {
+            h0[64..68].copy_from_slice(&(i as u32).to_le_bytes());
+            h0[68..72].copy_from_slice(&(lane as u32).to_le_bytes());
+            let mut block = [0u8; BLOCK_WORDS * 8];
+            hash_long(&mut block, &h0);
+            let start = (lane * lane_length + i) * BLOCK_WORDS;
+            for (word, chunk) in memory[start..start + BLOCK_WORDS].iter_mut().zip(block.chunks_exact(8)) {
+                *word = u64::from_le_bytes(chunk.try_into().unwrap());
+            }
+        }
+    }
+
+    for pass in 0..params.iterations {
+        for slice in 0..SYNC_POINTS as usize {
+            for lane in 0..lanes {
+                let first = if pass == 0 && slice == 0 { 2 } else { 0 };
+                for index in first..segment_length {
+                    let current = lane * lane_length + slice * segment_length + index;
+                    let previous = if slice == 0 && index == 0 {
+                        current + lane_length - 1
+                    } else {
+                        current - 1
+                    };
+                    let pseudo_random = memory[previous * BLOCK_WORDS];
+                    let ref_lane = if pass == 0 && slice == 0 {
+                        lane
+                    } else {
+                        ((pseudo_random >> 32) % lanes as u64) as usize
+                    };
+                    let ref_index = reference_index(
+                        pass,
+                        slice,
+                        index,
+                        segment_length,
+                        lane_length,
+                        ref_lane == lane,
+                        pseudo_random as u32,
+                    );
+                    fill_block(memory, previous, ref_lane * lane_length + ref_index, current, pass != 0);
+                }
+            }
+        }
+    }
+}
+
+/// Maps the pseudo-random value of a block to the index of its reference block within the reference lane.
+fn reference_index(
+    pass: u32,
+    slice: usize,
+    index: usize,
+    segment_length: usize,
+    lane_length: usize,
+    same_lane: bool,
+    pseudo_random: u32,
+) -> usize {
+    let area_size = if pass == 0 {
+        if slice == 0 {
+            index - 1
+        } else if same_lane {
+            slice * segment_length + index - 1
+        } else {
+            slice * segment_length - usize::from(index == 0)
+        }
+    } else if same_lane {
+        lane_length - segment_length + index - 1
+    } else {
+        lane_length - segment_length - usize::from(index == 0)
+    };
+    let mut relative = u64::from(pseudo_random);
+    relative = (relative * relative) >> 32;
+    let relative = area_size - 1 - ((area_size as u64 * relative) >> 32) as usize;
+    let start = if pass != 0 && slice != SYNC_POINTS as usize - 1 {
+        (slice + 1) * segment_length
+    } else {
+        0
+    };
+    (start + relative) % lane_length
+}
+
+fn fill_block(memory: &mut [u64], previous: usize, reference: usize, current: usize, with_xor: bool) {
+    let mut r = [0u64; BLOCK_WORDS];
+    for (i, word) in r.iter_mut().enumerate() {
+        *word = memory[previous * BLOCK_WORDS + i] ^ memory[reference * BLOCK_WORDS + i];
+    }
+    let mut next = r;
+    if with_xor {
+        for (i, word) in next.iter_mut().enumerate() {
+            *word ^= memory[current * BLOCK_WORDS + i];
+        }
+    }
+    for row in 0..8 {
+        let mut v = [0usize; 16];
+        for (i, index) in v.iter_mut().enumerate() {
+            *index = 16 * row + i;
+        }
+        round(&mut r, &v);
+    }
+    for column in 0..8 {
+        let mut v = [0usize; 16];
+        for (i, index) in v.iter_mut().enumerate() {
+            *index = 2 * column + (i % 2) + 16 * (i / 2);
+        }
+        round(&mut r, &v);
+    }
+    for (i, word) in memory[current * BLOCK_WORDS..(current + 1) * BLOCK_WORDS]
+        .iter_mut()
+        .enumerate()
+    {
+        *word = next[i] ^ r[i];
+    }
+}
+
+#[inline]
+fn round(r: &mut [u64; BLOCK_WORDS], v: &[usize; 16]) {
+    g(r, v[0], v[4], v[8], v[12]);
+    g(r, v[1], v[5], v[9], v[13]);
+    g(r, v[2], v[6], v[10], v[14]);
+    g(r, v[3], v[7], v[11], v[15]);
+    g(r, v[0], v[5], v[10], v[15]);
+    g(r, v[1], v[6], v[11], v[12]);
+    g(r, v[2], v[7], v[8], v[13]);
+    g(r, v[3], v[4], v[9], v[14]);
+}
+
+#[inline]
+fn g(r: &mut [u64; BLOCK_WORDS], a: usize, b: usize, c: usize, d: usize) {
+    r[a] = blamka(r[a], r[b]);
+    r[d] = (r[d] ^ r[a]).rotate_right(32);
+    r[c] = blamka(r[c], r[d]);
+    r[b] = (r[b] ^ r[c]).rotate_right(24);
+    r[a] = blamka(r[a], r[b]);
+    r[d] = (r[d] ^ r[a]).rotate_right(16);
+    r[c] = blamka(r[c], r[d]);
+    r[b] = (r[b] ^ r[c]).rotate_right(63);
+}
+
+#[inline]
+fn blamka(x: u64, y: u64) -> u64 {
+    let product = (x & 0xffff_ffff) * (y & 0xffff_ffff);
+    x.wrapping_add(y).wrapping_add(product.wrapping_mul(2))
+}
+
+/// The variable-length hash H' of Argon2, for outputs longer than 64 bytes.
+fn hash_long(out: &mut [u8], input: &[u8]) {
+    let mut v = [0u8; 64];
+    Blake2b::new(64)
+        .update(&(out.len() as u32).to_le_bytes())
+        .update(input)
+        .finalize(&mut v);
+    out[..32].copy_from_slice(&v[..32]);
+    let mut written = 32;
+    while out.len() - written > 64 {
+        let previous = v;
+        Blake2b::new(64).update(&previous).finalize(&mut v);
+        out[written..written + 32].copy_from_slice(&v[..32]);
+        written += 32;
+    }
+    let remaining = out.len() - written;
+    let previous = v;
+    Blake2b::new(remaining).update(&previous).finalize(&mut out[written..]);
+}
@@ -0,0 +1,261 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! The pure-Rust implementation behind the C interface of [`Backend`], so that the crate's objects can use it like a
+//! build of the C++ library. Pointers to the opaque RandomX types are boxed Rust values.
+//!
+//! Unlike the C++ library, re-initializing a cache while VMs on other threads hash with it is not a data race: a VM
+//! finishes the hash it started with the old key.
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    ffi::{c_uint, c_ulong, c_void},
+    ptr,
+    slice,
+};
+
+use spin::mutex::SpinMutex;
+
+use super::{vm::Machine, CacheData};
+use crate::{
+    bindings::{randomx_cache, randomx_dataset, randomx_vm, Backend},
+    RandomXConfig,
+    RandomXFlag,
+};
+
+/// The cache behind a `randomx_cache` pointer.
+///
+/// Data is never changed once it is published: `init` fills new data and swaps it in, and every hash holds on to the
+/// data it started with until it is done.
+struct CacheCell {
+    config: RandomXConfig,
+    /// The memory allocated by `alloc_cache`, so that a failed allocation is reported there, until the first key.
+    unkeyed: SpinMutex<Option<CacheData>>,
+    /// Only locked to clone or replace the `Arc`, never for the length of a hash or an initialization.
+    keyed: SpinMutex<Option<Arc<CacheData>>>,
+}
+
+impl CacheCell {
+    fn init(&self, key: &[u8]) {
+        let unkeyed = self.unkeyed.lock().take();
+        let mut data = unkeyed.unwrap_or_else(|| CacheData::allocate_or_abort(self.config));
+        data.init(key);
+        let previous = self.keyed.lock().replace(Arc::new(data));
+        // Frees the previous data, unless a VM is still hashing with it.
+        drop(previous);
+    }
+
+    fn data(&self) -> Option<Arc<CacheData>> {
+        self.keyed.lock().clone()
+    }
+}
+
+struct VmState {
+    machine: Machine,
+    cache: *const CacheCell,
+    /// The input of `calculate_hash_first` or `calculate_hash_next`, hashed by the next call.
+    pending: Option<Vec<u8>>,
+}
+
+impl VmState {
+    /// Leaves `output` untouched if the cache was never initialized, which the bindings report as an empty hash.
+    unsafe fn hash(&mut self, input: &[u8], output: *mut c_void) {
+        if let Some(cache) = (*self.cache).data() {
+            let mut hash = [0u8; 32];
+            self.machine.calculate_hash(&cache, input, &mut hash);
+            ptr::copy_nonoverlapping(hash.as_ptr(), output.cast::<u8>(), hash.len());
+        }
+    }
+}
+
+unsafe fn bytes<'a>(data: *const c_void, size: usize) -> &'a [u8] {
+    if size == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data.cast::<u8>(), size)
+    }
+}
+
+unsafe extern "C" fn alloc_cache(_flags: c_uint) -> *mut randomx_cache {
+    let config = *RandomXConfig::active();
+    match CacheData::allocate(config) {
+        Some(data) => Box::into_raw(Box::new(CacheCell {
+            config,
+            unkeyed: SpinMutex::new(Some(data)),
+            keyed: SpinMutex::new(None),
+        }))
+        .cast(),
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn init_cache(cache: *mut randomx_cache, key: *const c_void, key_size: usize) {
+    (*cache.cast::<CacheCell>()).init(bytes(key, key_size));
+}
+
+unsafe extern "C" fn release_cache(cache: *mut randomx_cache) {
+    drop(Box::from_raw(cache.cast::<CacheCell>()));
+}
+
+// Datasets are not implemented; `RandomXFlag::disabled` includes FLAG_FULL_MEM.
+unsafe extern "C" fn alloc_dataset(_flags: c_uint) -> *mut randomx_dataset {
+    ptr::null_mut()
+}
+
+unsafe extern "C" fn dataset_item_count() -> c_ulong {
+    let config = RandomXConfig::active();
+    // Both sizes are multiples of 64, see `RandomXConfig::validate`
+    c_ulong::from(config.dataset_base_size / 64 + config.dataset_extra_size / 64)
+}
+
+unsafe extern "C" fn init_dataset(
+    _dataset: *mut randomx_dataset,
+    _cache: *mut randomx_cache,
+    _start_item: c_ulong,
+    _item_count: c_ulong,
+) {
+}
+
+unsafe extern "C" fn get_dataset_memory(_dataset: *mut randomx_dataset) -> *mut c_void {
+    ptr::null_mut()
+}
+
+unsafe extern "C" fn release_dataset(_dataset: *mut randomx_dataset) {}
+
+unsafe extern "C" fn create_vm(
+    flags: c_uint,
+    cache: *mut randomx_cache,
+    _dataset: *mut randomx_dataset,
+) -> *mut randomx_vm {
+    if cache.is_null() || RandomXFlag::from_bits_truncate(flags).contains(RandomXFlag::FLAG_FULL_MEM) {
+        return ptr::null_mut();
+    }
+    let cache = cache.cast::<CacheCell>() as *const CacheCell;
+    match Machine::new(&(*cache).config) {
+        Some(machine) => Box::into_raw(Box::new(VmState {
+            machine,
+            cache,
+            pending: None,
+        }))
+        .cast(),
+        None => ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn vm_set_cache(machine: *mut randomx_vm, cache: *mut randomx_cache) {
+    (*machine.cast::<VmState>()).cache = cache.cast::<CacheCell>();
+}
+
+unsafe extern "C" fn vm_set_dataset(_machine: *mut randomx_vm, _dataset: *mut randomx_dataset) {}
+
+unsafe extern "C" fn destroy_vm(machine: *mut randomx_vm) {
+    drop(Box::from_raw(machine.cast::<VmState>()));
+}
+
+unsafe extern "C" fn calculate_hash(
+    machine: *mut randomx_vm,
+    input: *const c_void,
+    input_size: usize,
+    output: *mut c_void,
+) {
+    (*machine.cast::<VmState>()).hash(bytes(input, input_size), output);
+}
+
+unsafe extern "C" fn calculate_hash_first(machine: *mut randomx_vm, input: *const c_void, input_size: usize) {
+    (*machine.cast::<VmState>()).pending = Some(bytes(input, input_size).to_vec());
+}
+
+unsafe extern "C" fn calculate_hash_next(
+    machine: *mut randomx_vm,
+    input_next: *const c_void,
+    input_size_next: usize,
+    output: *mut c_void,
+) {
+    let state = &mut *machine.cast::<VmState>();
+    let input = state.pending.replace(bytes(input_next, input_size_next).to_vec());
+    state.hash(&input.unwrap_or_default(), output);
+}
+
+unsafe extern "C" fn calculate_hash_last(machine: *mut randomx_vm, output: *mut c_void) {
+    let state = &mut *machine.cast::<VmState>();
+    let input = state.pending.take();
+    state.hash(&input.unwrap_or_default(), output);
+}
+
+/// The pure-Rust implementation, with the parameters of [`RandomXConfig::active`].
+pub(crate) const BACKEND: Backend = Backend {
+    alloc_cache,
+    init_cache,
+    release_cache,
+    alloc_dataset,
+    dataset_item_count,
+    init_dataset,
+    get_dataset_memory,
+    release_dataset,
+    create_vm,
+    vm_set_cache,
+    vm_set_dataset,
+    destroy_vm,
+    calculate_hash,
+    calculate_hash_first,
+    calculate_hash_next,
+    calculate_hash_last,
+};
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+
+    use crate::{RandomXCache, RandomXFlag, RandomXVM, StaleKeyPolicy};
+
+    #[test]
+    fn rekeying_while_hashing() {
+        const INPUT: &[u8] = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+        // The hashes of INPUT under "test key 000" and "test key 001"
+        let expected = [
+            "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8",
+            "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc",
+        ];
+        let flags = RandomXFlag::get_recommended_flags();
+        let cache = RandomXCache::new(flags, b"test key 000").unwrap();
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let hasher = scope.spawn(|| {
+                let mut vm = RandomXVM::new(flags, Some(cache.clone()), None).unwrap();
+                // Follow the re-keyed cache instead of failing with `KeyMismatch`
+                vm.set_stale_key_policy(StaleKeyPolicy::Rebuild);
+                while !done.load(Ordering::Acquire) {
+                    let hash = hex::encode(vm.calculate_hash(INPUT).unwrap());
+                    assert!(expected.contains(&hash.as_str()), "{}", hash);
+                }
+            });
+            for key in [b"test key 001", b"test key 000", b"test key 001"] {
+                cache.init(key).unwrap();
+            }
+            done.store(true, Ordering::Release);
+            hasher.join().unwrap();
+        });
+    }
+}
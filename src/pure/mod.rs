@@ -0,0 +1,211 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Light-mode RandomX in safe Rust, for targets that cannot build or link the C++ library.
+//!
+//! With the `pure-rust` feature this module is the backend of [`RandomXCache`](crate::RandomXCache) and
+//! [`RandomXVM`](crate::RandomXVM), and the C++ library is not built. It computes the same hashes as the C++ library
+//! in light mode with the interpreter and software AES; datasets (`FLAG_FULL_MEM`), the JIT compiler and hardware AES
+//! are not available. [`Cache`] and [`Vm`] can also be used directly, which the `differential` feature allows next to
+//! the C++ backend.
+//!
+//! Only the glue that implements the C interface of the other backends contains `unsafe` code; the submodules with
+//! the algorithms forbid it.
+
+mod aes;
+mod argon2;
+#[cfg(not(randomx_ffi))]
+pub(crate) mod backend;
+mod superscalar;
+mod vm;
+
+use alloc::vec::Vec;
+
+use self::{
+    argon2::{Params, BLOCK_WORDS},
+    superscalar::Program,
+    vm::Machine,
+};
+use crate::{blake2b::Blake2Generator, RandomXConfig, RandomXError, RandomXFlag, RandomXObject};
+
+/// The cache memory and the SuperscalarHash programs generated from a key.
+#[derive(Debug)]
+pub(crate) struct CacheData {
+    config: RandomXConfig,
+    memory: Vec<u64>,
+    programs: Vec<Program>,
+}
+
+impl CacheData {
+    /// Returns `None` if the memory cannot be allocated.
+    fn allocate(config: RandomXConfig) -> Option<CacheData> {
+        let words = argon_params(&config).block_count() * BLOCK_WORDS;
+        let mut memory = Vec::new();
+        memory.try_reserve_exact(words).ok()?;
+        memory.resize(words, 0);
+        Some(CacheData {
+            config,
+            memory,
+            programs: Vec::new(),
+        })
+    }
+
+    /// Same as [`CacheData::allocate`], but aborts like any other allocation if the memory cannot be allocated.
+    #[cfg(not(randomx_ffi))]
+    fn allocate_or_abort(config: RandomXConfig) -> CacheData {
+        let words = argon_params(&config).block_count() * BLOCK_WORDS;
+        CacheData {
+            config,
+            memory: alloc::vec![0; words],
+            programs: Vec::new(),
+        }
+    }
+
+    fn init(&mut self, key: &[u8]) {
+        argon2::fill(&mut self.memory, &argon_params(&self.config), key);
+        let mut gen = Blake2Generator::new(key, 0);
+        self.programs = (0..self.config.cache_accesses)
+            .map(|_| Program::generate(&self.config, &mut gen))
+            .collect();
+    }
+
+    fn dataset_item(&self, item_number: u64) -> [u64; 8] {
+        superscalar::dataset_item(&self.memory, &self.programs, item_number)
+    }
+}
+
+fn argon_params(config: &RandomXConfig) -> Params<'_> {
+    Params {
+        memory_kib: config.argon_memory,
+        iterations: config.argon_iterations,
+        lanes: config.argon_lanes,
+        salt: config.argon_salt,
+        tag_length: 0,
+    }
+}
+
+/// Returns the 64-bit fixed-point reciprocal `2^x / divisor` that RandomX multiplies with instead of dividing, for a
+/// divisor that is neither zero nor a power of two.
+fn reciprocal(divisor: u64) -> u64 {
+    const P2EXP63: u64 = 1 << 63;
+    let mut quotient = P2EXP63 / divisor;
+    let mut remainder = P2EXP63 % divisor;
+    let bits = 64 - divisor.leading_zeros();
+    for _ in 0..bits {
+        if remainder >= divisor - remainder {
+            quotient = quotient.wrapping_mul(2).wrapping_add(1);
+            remainder = remainder.wrapping_mul(2).wrapping_sub(divisor);
+        } else {
+            quotient = quotient.wrapping_mul(2);
+            remainder = remainder.wrapping_mul(2);
+        }
+    }
+    quotient
+}
+
+/// A light-mode cache, initialized with a key and the parameters of [`RandomXConfig::active`].
+#[derive(Debug)]
+pub struct Cache {
+    data: CacheData,
+}
+
+impl Cache {
+    /// Allocates the cache and initializes it with `key`.
+    pub fn new(key: &[u8]) -> Result<Cache, RandomXError> {
+        if key.is_empty() {
+            return Err(RandomXError::EmptyKey);
+        }
+        let mut data = CacheData::allocate(*RandomXConfig::active()).ok_or(RandomXError::AllocationFailed {
+            object: RandomXObject::Cache,
+            flags: RandomXFlag::FLAG_DEFAULT,
+        })?;
+        data.init(key);
+        Ok(Cache { data })
+    }
+}
+
+/// A light-mode VM that hashes with a [`Cache`].
+#[derive(Debug)]
+pub struct Vm<'a> {
+    cache: &'a Cache,
+    machine: Machine,
+}
+
+impl<'a> Vm<'a> {
+    /// Allocates a VM for `cache`.
+    pub fn new(cache: &'a Cache) -> Result<Vm<'a>, RandomXError> {
+        let machine = Machine::new(&cache.data.config).ok_or(RandomXError::AllocationFailed {
+            object: RandomXObject::Vm,
+            flags: RandomXFlag::FLAG_DEFAULT,
+        })?;
+        Ok(Vm { cache, machine })
+    }
+
+    /// Returns the RandomX hash of `input`.
+    pub fn calculate_hash(&mut self, input: &[u8]) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        self.machine.calculate_hash(&self.cache.data, input, &mut hash);
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // test values from RandomX's src/tests/tests.cpp
+    #[test]
+    fn reciprocals() {
+        assert_eq!(reciprocal(3), 12_297_829_382_473_034_410);
+        assert_eq!(reciprocal(13), 11_351_842_506_898_185_609);
+        assert_eq!(reciprocal(33), 17_887_751_829_051_686_415);
+        assert_eq!(reciprocal(65537), 18_446_462_603_027_742_720);
+        assert_eq!(reciprocal(15_000_001), 10_316_166_306_300_415_204);
+        assert_eq!(reciprocal(3_845_182_035), 10_302_264_209_224_146_340);
+        assert_eq!(reciprocal(0xffff_ffff), 9_223_372_039_002_259_456);
+    }
+
+    #[test]
+    fn dataset_items() {
+        let cache = Cache::new(b"test key 000").unwrap();
+        let items = [
+            (0, 0x6805_88a8_5ae2_22db),
+            (10_000_000, 0x7943_a1f6_186f_fb72),
+            (20_000_000, 0x9035_244d_7180_95e1),
+            (30_000_000, 0x145a_5091_f785_3099),
+        ];
+        for (item_number, first_word) in items {
+            assert_eq!(cache.data.dataset_item(item_number)[0], first_word);
+        }
+    }
+
+    #[test]
+    fn vm_hashes() {
+        let cache = Cache::new(b"test key 000").unwrap();
+        let mut vm = Vm::new(&cache).unwrap();
+        assert_eq!(
+            hex::encode(vm.calculate_hash(b"This is a test")),
+            "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f"
+        );
+        assert!(matches!(Cache::new(b""), Err(RandomXError::EmptyKey)));
+    }
+}
@@ -0,0 +1,726 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! SuperscalarHash: the generator of the random programs that turn cache lines into dataset items, and their
+//! execution.
+//!
+//! The generator simulates the decoder and the execution ports of an Intel CPU to pick instructions and registers, so
+//! the simulation has to follow RandomX's reference implementation step by step for the programs to be the same.
+
+#![forbid(unsafe_code)]
+// RandomX is defined on the bits of fixed-width integers, which are converted between widths and signedness as-is.
+#![allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+
+use alloc::{vec, vec::Vec};
+
+use super::reciprocal;
+use crate::{blake2b::Blake2Generator, RandomXConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstructionType {
+    IsubR,
+    IxorR,
+    IaddRs,
+    ImulR,
+    IrorC,
+    IaddC7,
+    IxorC7,
+    IaddC8,
+    IxorC8,
+    IaddC9,
+    IxorC9,
+    ImulhR,
+    IsmulhR,
+    ImulRcp,
+    Invalid,
+}
+
+use InstructionType::{
+    IaddC7,
+    IaddC8,
+    IaddC9,
+    IaddRs,
+    ImulR,
+    ImulRcp,
+    ImulhR,
+    Invalid,
+    IrorC,
+    IsmulhR,
+    IsubR,
+    IxorC7,
+    IxorC8,
+    IxorC9,
+    IxorR,
+};
+
+// Execution ports that a micro-op can go to.
+const P0: u8 = 1;
+const P1: u8 = 2;
+const P5: u8 = 4;
+const P01: u8 = P0 | P1;
+const P05: u8 = P0 | P5;
+const P015: u8 = P0 | P1 | P5;
+
+/// An x86 macro-op, made of one or two micro-ops; one without micro-ops is eliminated by the register renamer.
+#[derive(Debug, Clone, Copy)]
+struct MacroOp {
+    latency: i32,
+    uop1: u8,
+    uop2: u8,
+    dependent: bool,
+}
+
+impl MacroOp {
+    const fn new(latency: i32, uop1: u8, uop2: u8) -> MacroOp {
+        MacroOp {
+            latency,
+            uop1,
+            uop2,
+            dependent: false,
+        }
+    }
+
+    const fn dependent(self) -> MacroOp {
+        MacroOp {
+            dependent: true,
+            ..self
+        }
+    }
+
+    fn is_eliminated(self) -> bool {
+        self.uop1 == 0
+    }
+
+    fn is_simple(self) -> bool {
+        self.uop2 == 0
+    }
+}
+
+const SUB_RR: MacroOp = MacroOp::new(1, P015, 0);
+const XOR_RR: MacroOp = MacroOp::new(1, P015, 0);
+const IMUL_R: MacroOp = MacroOp::new(4, P1, P5);
+const MUL_R: MacroOp = MacroOp::new(4, P1, P5);
+const MOV_RR: MacroOp = MacroOp::new(0, 0, 0);
+const LEA_SIB: MacroOp = MacroOp::new(1, P01, 0);
+const IMUL_RR: MacroOp = MacroOp::new(3, P1, 0);
+const ROR_RI: MacroOp = MacroOp::new(1, P05, 0);
+const ADD_RI: MacroOp = MacroOp::new(1, P015, 0);
+const XOR_RI: MacroOp = MacroOp::new(1, P015, 0);
+const MOV_RI64: MacroOp = MacroOp::new(1, P015, 0);
+
+/// The macro-ops an instruction decodes to, and which of them reads the source, reads the destination and writes the
+/// result.
+struct InstructionInfo {
+    kind: InstructionType,
+    ops: &'static [MacroOp],
+    result_op: usize,
+    dst_op: usize,
+    src_op: Option<usize>,
+}
+
+impl InstructionInfo {
+    const fn simple(kind: InstructionType, op: &'static [MacroOp], src_op: Option<usize>) -> InstructionInfo {
+        InstructionInfo {
+            kind,
+            ops: op,
+            result_op: 0,
+            dst_op: 0,
+            src_op,
+        }
+    }
+}
+
+static ISUB_R: InstructionInfo = InstructionInfo::simple(IsubR, &[SUB_RR], Some(0));
+static IXOR_R: InstructionInfo = InstructionInfo::simple(IxorR, &[XOR_RR], Some(0));
+static IADD_RS: InstructionInfo = InstructionInfo::simple(IaddRs, &[LEA_SIB], Some(0));
+static IMUL_R_INFO: InstructionInfo = InstructionInfo::simple(ImulR, &[IMUL_RR], Some(0));
+static IROR_C: InstructionInfo = InstructionInfo::simple(IrorC, &[ROR_RI], None);
+static IADD_C7: InstructionInfo = InstructionInfo::simple(IaddC7, &[ADD_RI], None);
+static IXOR_C7: InstructionInfo = InstructionInfo::simple(IxorC7, &[XOR_RI], None);
+static IADD_C8: InstructionInfo = InstructionInfo::simple(IaddC8, &[ADD_RI], None);
+static IXOR_C8: InstructionInfo = InstructionInfo::simple(IxorC8, &[XOR_RI], None);
+static IADD_C9: InstructionInfo = InstructionInfo::simple(IaddC9, &[ADD_RI], None);
+static IXOR_C9: InstructionInfo = InstructionInfo::simple(IxorC9, &[XOR_RI], None);
+static IMULH_R: InstructionInfo = InstructionInfo {
+    kind: ImulhR,
+    ops: &[MOV_RR, MUL_R, MOV_RR],
+    result_op: 1,
+    dst_op: 0,
+    src_op: Some(1),
+};
+static ISMULH_R: InstructionInfo = InstructionInfo {
+    kind: IsmulhR,
+    ops: &[MOV_RR, IMUL_R, MOV_RR],
+    result_op: 1,
+    dst_op: 0,
+    src_op: Some(1),
+};
+static IMUL_RCP: InstructionInfo = InstructionInfo {
+    kind: ImulRcp,
+    ops: &[MOV_RI64, IMUL_RR.dependent()],
+    result_op: 1,
+    dst_op: 1,
+    src_op: None,
+};
+static NOP: InstructionInfo = InstructionInfo::simple(Invalid, &[], None);
+
+static SLOT_3: [&InstructionInfo; 2] = [&ISUB_R, &IXOR_R];
+static SLOT_3L: [&InstructionInfo; 4] = [&ISUB_R, &IXOR_R, &IMULH_R, &ISMULH_R];
+static SLOT_4: [&InstructionInfo; 2] = [&IROR_C, &IADD_RS];
+static SLOT_7: [&InstructionInfo; 2] = [&IXOR_C7, &IADD_C7];
+static SLOT_8: [&InstructionInfo; 2] = [&IXOR_C8, &IADD_C8];
+static SLOT_9: [&InstructionInfo; 2] = [&IXOR_C9, &IADD_C9];
+
+/// One way of splitting a 16-byte decode window into instruction slots.
+struct DecoderBuffer {
+    index: usize,
+    slots: &'static [usize],
+}
+
+static BUFFER_484: DecoderBuffer = DecoderBuffer {
+    index: 0,
+    slots: &[4, 8, 4],
+};
+static BUFFER_7333: DecoderBuffer = DecoderBuffer {
+    index: 1,
+    slots: &[7, 3, 3, 3],
+};
+static BUFFER_3733: DecoderBuffer = DecoderBuffer {
+    index: 2,
+    slots: &[3, 7, 3, 3],
+};
+static BUFFER_493: DecoderBuffer = DecoderBuffer {
+    index: 3,
+    slots: &[4, 9, 3],
+};
+static BUFFER_4444: DecoderBuffer = DecoderBuffer {
+    index: 4,
+    slots: &[4, 4, 4, 4],
+};
+static BUFFER_3310: DecoderBuffer = DecoderBuffer {
+    index: 5,
+    slots: &[3, 3, 10],
+};
+static RANDOM_BUFFERS: [&DecoderBuffer; 4] = [&BUFFER_484, &BUFFER_7333, &BUFFER_3733, &BUFFER_493];
+
+fn fetch_next(
+    previous: InstructionType,
+    cycle: usize,
+    mul_count: usize,
+    gen: &mut Blake2Generator,
+) -> &'static DecoderBuffer {
+    // A 128-bit multiplication decodes to 2 micro-ops and needs a 3-3-10 window.
+    if previous == ImulhR || previous == IsmulhR {
+        return &BUFFER_3310;
+    }
+    // Keep the multiplication port saturated.
+    if mul_count < cycle + 1 {
+        return &BUFFER_4444;
+    }
+    // The multiplication of IMUL_RCP goes into a 4-byte slot at the start of the next window.
+    if previous == ImulRcp {
+        return if gen.get_byte() & 1 == 1 {
+            &BUFFER_484
+        } else {
+            &BUFFER_493
+        };
+    }
+    RANDOM_BUFFERS[(gen.get_byte() & 3) as usize]
+}
+
+#[derive(Clone, Copy)]
+struct RegisterInfo {
+    latency: i32,
+    last_op_group: InstructionType,
+    last_op_par: i32,
+}
+
+/// The instruction being generated.
+struct Candidate {
+    info: &'static InstructionInfo,
+    src: i32,
+    dst: i32,
+    modifier: u8,
+    imm32: u32,
+    op_group: InstructionType,
+    op_group_par: i32,
+    can_reuse: bool,
+    group_par_is_source: bool,
+}
+
+/// The destination of IADD_RS cannot be r5, which `lea` can only use with a displacement.
+const REGISTER_NEEDS_DISPLACEMENT: i32 = 5;
+
+impl Candidate {
+    fn null() -> Candidate {
+        Candidate {
+            info: &NOP,
+            src: -1,
+            dst: -1,
+            modifier: 0,
+            imm32: 0,
+            op_group: Invalid,
+            op_group_par: -1,
+            can_reuse: false,
+            group_par_is_source: false,
+        }
+    }
+
+    fn kind(&self) -> InstructionType {
+        self.info.kind
+    }
+
+    fn create_for_slot(&mut self, gen: &mut Blake2Generator, slot_size: usize, buffer_index: usize, is_last: bool) {
+        let info = match slot_size {
+            // IMULH and ISMULH are only possible in the last slot.
+            3 if is_last => SLOT_3L[(gen.get_byte() & 3) as usize],
+            3 => SLOT_3[(gen.get_byte() & 1) as usize],
+            // The 4-4-4-4 window issues multiplications, except in its last slot.
+            4 if buffer_index == BUFFER_4444.index && !is_last => &IMUL_R_INFO,
+            4 => SLOT_4[(gen.get_byte() & 1) as usize],
+            7 => SLOT_7[(gen.get_byte() & 1) as usize],
+            8 => SLOT_8[(gen.get_byte() & 1) as usize],
+            9 => SLOT_9[(gen.get_byte() & 1) as usize],
+            10 => &IMUL_RCP,
+            _ => unreachable!("no instruction fits a slot of {} bytes", slot_size),
+        };
+        self.create(info, gen);
+    }
+
+    fn create(&mut self, info: &'static InstructionInfo, gen: &mut Blake2Generator) {
+        self.info = info;
+        self.src = -1;
+        self.dst = -1;
+        self.can_reuse = false;
+        self.group_par_is_source = false;
+        self.modifier = 0;
+        self.imm32 = 0;
+        match info.kind {
+            IsubR => {
+                self.op_group = IaddRs;
+                self.group_par_is_source = true;
+            },
+            IxorR => {
+                self.op_group = IxorR;
+                self.group_par_is_source = true;
+            },
+            IaddRs => {
+                self.modifier = gen.get_byte();
+                self.op_group = IaddRs;
+                self.group_par_is_source = true;
+            },
+            ImulR => {
+                self.op_group = ImulR;
+                self.group_par_is_source = true;
+            },
+            IrorC => {
+                while self.imm32 == 0 {
+                    self.imm32 = u32::from(gen.get_byte() & 63);
+                }
+                self.op_group = IrorC;
+                self.op_group_par = -1;
+            },
+            IaddC7 | IaddC8 | IaddC9 => {
+                self.imm32 = gen.get_u32();
+                self.op_group = IaddC7;
+                self.op_group_par = -1;
+            },
+            IxorC7 | IxorC8 | IxorC9 => {
+                self.imm32 = gen.get_u32();
+                self.op_group = IxorC7;
+                self.op_group_par = -1;
+            },
+            ImulhR | IsmulhR => {
+                self.can_reuse = true;
+                self.op_group = info.kind;
+                self.op_group_par = gen.get_u32() as i32;
+            },
+            ImulRcp => {
+                loop {
+                    self.imm32 = gen.get_u32();
+                    if self.imm32 & self.imm32.wrapping_sub(1) != 0 {
+                        break;
+                    }
+                }
+                self.op_group = ImulRcp;
+                self.op_group_par = -1;
+            },
+            Invalid => {},
+        }
+    }
+
+    fn select_destination(
+        &mut self,
+        cycle: i32,
+        allow_chained_mul: bool,
+        registers: &[RegisterInfo; 8],
+        gen: &mut Blake2Generator,
+    ) -> bool {
+        let mut available = [0i32; 8];
+        let mut count = 0;
+        for (i, register) in (0..8).zip(registers.iter()) {
+            // The register must be ready, must not be the source unless the instruction allows it, must not be
+            // multiplied twice in a row, must not repeat its last operation, and r5 cannot take IADD_RS.
+            if register.latency <= cycle &&
+                (self.can_reuse || i != self.src) &&
+                (allow_chained_mul || self.op_group != ImulR || register.last_op_group != ImulR) &&
+                (register.last_op_group != self.op_group || register.last_op_par != self.op_group_par) &&
+                (self.kind() != IaddRs || i != REGISTER_NEEDS_DISPLACEMENT)
+            {
+                available[count] = i;
+                count += 1;
+            }
+        }
+        select_register(&available[..count], gen)
+            .map(|dst| self.dst = dst)
+            .is_some()
+    }
+
+    fn select_source(&mut self, cycle: i32, registers: &[RegisterInfo; 8], gen: &mut Blake2Generator) -> bool {
+        let mut available = [0i32; 8];
+        let mut count = 0;
+        for (i, register) in (0..8).zip(registers.iter()) {
+            if register.latency <= cycle {
+                available[count] = i;
+                count += 1;
+            }
+        }
+        // With only two registers ready and one of them r5, IADD_RS has to take r5 as its source.
+        if count == 2 &&
+            self.kind() == IaddRs &&
+            (available[0] == REGISTER_NEEDS_DISPLACEMENT || available[1] == REGISTER_NEEDS_DISPLACEMENT)
+        {
+            self.src = REGISTER_NEEDS_DISPLACEMENT;
+            self.op_group_par = REGISTER_NEEDS_DISPLACEMENT;
+            return true;
+        }
+        match select_register(&available[..count], gen) {
+            Some(src) => {
+                self.src = src;
+                if self.group_par_is_source {
+                    self.op_group_par = src;
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn to_instruction(&self) -> Instruction {
+        let src = if self.src >= 0 { self.src } else { self.dst };
+        let operand = match self.kind() {
+            IaddRs => u64::from((self.modifier >> 2) % 4),
+            IrorC => u64::from(self.imm32),
+            IaddC7 | IaddC8 | IaddC9 | IxorC7 | IxorC8 | IxorC9 => self.imm32 as i32 as u64,
+            ImulRcp => reciprocal(u64::from(self.imm32)),
+            _ => 0,
+        };
+        Instruction {
+            kind: self.kind(),
+            dst: self.dst as usize,
+            src: src as usize,
+            operand,
+        }
+    }
+}
+
+fn select_register(available: &[i32], gen: &mut Blake2Generator) -> Option<i32> {
+    match available.len() {
+        0 => None,
+        1 => Some(available[0]),
+        len => Some(available[(gen.get_u32() % len as u32) as usize]),
+    }
+}
+
+/// Returns the cycle at which `uop` can execute, at or after `cycle`, checking the ports in the order P5, P0, P1 so
+/// that P1 is left for multiplications.
+fn schedule_uop(uop: u8, port_busy: &mut [[u8; 3]], cycle: usize, commit: bool) -> Option<usize> {
+    for (cycle, ports) in port_busy.iter_mut().enumerate().skip(cycle) {
+        for &(port, slot) in &[(P5, 2), (P0, 0), (P1, 1)] {
+            if uop & port != 0 && ports[slot] == 0 {
+                if commit {
+                    ports[slot] = uop;
+                }
+                return Some(cycle);
+            }
+        }
+    }
+    None
+}
+
+fn schedule_mop(
+    mop: MacroOp,
+    port_busy: &mut [[u8; 3]],
+    mut cycle: usize,
+    dep_cycle: usize,
+    commit: bool,
+) -> Option<usize> {
+    // The multiplication of IMUL_RCP depends on the `mov` that loads its constant.
+    if mop.dependent {
+        cycle = cycle.max(dep_cycle);
+    }
+    if mop.is_eliminated() {
+        return Some(cycle);
+    }
+    if mop.is_simple() {
+        return schedule_uop(mop.uop1, port_busy, cycle, commit);
+    }
+    // Both micro-ops of a macro-op are scheduled in the same cycle.
+    for cycle in cycle..port_busy.len() {
+        let cycle1 = schedule_uop(mop.uop1, port_busy, cycle, false);
+        let cycle2 = schedule_uop(mop.uop2, port_busy, cycle, false);
+        if cycle1.is_some() && cycle1 == cycle2 {
+            if commit {
+                schedule_uop(mop.uop1, port_busy, cycle, true);
+                schedule_uop(mop.uop2, port_busy, cycle, true);
+            }
+            return cycle1;
+        }
+    }
+    None
+}
+
+/// A SuperscalarHash instruction. `operand` is the shift, the rotation, the sign-extended constant or the reciprocal.
+#[derive(Debug, Clone, Copy)]
+struct Instruction {
+    kind: InstructionType,
+    dst: usize,
+    src: usize,
+    operand: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Program {
+    instructions: Vec<Instruction>,
+    address_register: usize,
+}
+
+const LOOK_FORWARD_CYCLES: usize = 4;
+const MAX_THROWAWAY_COUNT: usize = 256;
+
+impl Program {
+    // A port of the generator of RandomX's superscalar.cpp, kept in one piece to follow the original.
+    #[allow(clippy::too_many_lines)]
+    pub fn generate(config: &RandomXConfig, gen: &mut Blake2Generator) -> Program {
+        let latency = config.superscalar_latency as usize;
+        let max_size = 3 * latency + 2;
+        let mut port_busy = vec![[0u8; 3]; latency + 4];
+        let mut registers = [RegisterInfo {
+            latency: 0,
+            last_op_group: Invalid,
+            last_op_par: -1,
+        }; 8];
+        let mut instructions = Vec::with_capacity(max_size);
+
+        let mut current = Candidate::null();
+        let mut macro_op_index = 0;
+        let mut cycle = 0;
+        let mut dep_cycle = 0;
+        let mut ports_saturated = false;
+        let mut mul_count = 0;
+        let mut throw_away_count = 0;
+
+        // Each decode cycle decodes 16 bytes of x86 code. The execution ports are saturated long before the cycle
+        // limit, which only guarantees termination.
+        let mut decode_cycle = 0;
+        while decode_cycle < latency && !ports_saturated && instructions.len() < max_size {
+            let buffer = fetch_next(current.kind(), decode_cycle, mul_count, gen);
+            let mut buffer_index = 0;
+            while buffer_index < buffer.slots.len() {
+                let top_cycle = cycle;
+                if macro_op_index >= current.info.ops.len() {
+                    if ports_saturated || instructions.len() >= max_size {
+                        break;
+                    }
+                    current.create_for_slot(
+                        gen,
+                        buffer.slots[buffer_index],
+                        buffer.index,
+                        buffer.slots.len() == buffer_index + 1,
+                    );
+                    macro_op_index = 0;
+                }
+                let mop = current.info.ops[macro_op_index];
+
+                let mut schedule_cycle = match schedule_mop(mop, &mut port_busy, cycle, dep_cycle, false) {
+                    Some(schedule_cycle) => schedule_cycle,
+                    None => {
+                        ports_saturated = true;
+                        break;
+                    },
+                };
+
+                if current.info.src_op == Some(macro_op_index) {
+                    let mut forward = 0;
+                    while forward < LOOK_FORWARD_CYCLES &&
+                        !current.select_source(schedule_cycle as i32, &registers, gen)
+                    {
+                        schedule_cycle += 1;
+                        cycle += 1;
+                        forward += 1;
+                    }
+                    if forward == LOOK_FORWARD_CYCLES {
+                        if throw_away_count < MAX_THROWAWAY_COUNT {
+                            throw_away_count += 1;
+                            macro_op_index = current.info.ops.len();
+                            continue;
+                        }
+                        current = Candidate::null();
+                        break;
+                    }
+                }
+                if current.info.dst_op == macro_op_index {
+                    let mut forward = 0;
+                    while forward < LOOK_FORWARD_CYCLES &&
+                        !current.select_destination(schedule_cycle as i32, throw_away_count > 0, &registers, gen)
+                    {
+                        schedule_cycle += 1;
+                        cycle += 1;
+                        forward += 1;
+                    }
+                    if forward == LOOK_FORWARD_CYCLES {
+                        if throw_away_count < MAX_THROWAWAY_COUNT {
+                            throw_away_count += 1;
+                            macro_op_index = current.info.ops.len();
+                            continue;
+                        }
+                        current = Candidate::null();
+                        break;
+                    }
+                }
+                throw_away_count = 0;
+
+                let schedule_cycle = match schedule_mop(mop, &mut port_busy, schedule_cycle, schedule_cycle, true) {
+                    Some(schedule_cycle) => schedule_cycle,
+                    None => {
+                        ports_saturated = true;
+                        break;
+                    },
+                };
+                dep_cycle = schedule_cycle + mop.latency as usize;
+
+                if macro_op_index == current.info.result_op {
+                    let register = &mut registers[current.dst as usize];
+                    register.latency = dep_cycle as i32;
+                    register.last_op_group = current.op_group;
+                    register.last_op_par = current.op_group_par;
+                }
+                buffer_index += 1;
+                macro_op_index += 1;
+
+                if schedule_cycle >= latency {
+                    ports_saturated = true;
+                }
+                cycle = top_cycle;
+
+                if macro_op_index >= current.info.ops.len() {
+                    instructions.push(current.to_instruction());
+                    if matches!(current.kind(), ImulR | ImulhR | IsmulhR | ImulRcp) {
+                        mul_count += 1;
+                    }
+                }
+            }
+            cycle += 1;
+            decode_cycle += 1;
+        }
+
+        // The address register is the one with the longest dependency chain, assuming one cycle per instruction.
+        let mut asic_latencies = [0u32; 8];
+        for instruction in &instructions {
+            let latency_dst = asic_latencies[instruction.dst] + 1;
+            let latency_src = if instruction.dst == instruction.src {
+                0
+            } else {
+                asic_latencies[instruction.src] + 1
+            };
+            asic_latencies[instruction.dst] = latency_dst.max(latency_src);
+        }
+        let mut address_register = 0;
+        for i in 1..8 {
+            if asic_latencies[i] > asic_latencies[address_register] {
+                address_register = i;
+            }
+        }
+
+        Program {
+            instructions,
+            address_register,
+        }
+    }
+
+    pub fn execute(&self, r: &mut [u64; 8]) {
+        for instruction in &self.instructions {
+            let src = r[instruction.src];
+            let dst = &mut r[instruction.dst];
+            *dst = match instruction.kind {
+                IsubR => dst.wrapping_sub(src),
+                IxorR => *dst ^ src,
+                IaddRs => dst.wrapping_add(src << instruction.operand),
+                ImulR => dst.wrapping_mul(src),
+                IrorC => dst.rotate_right(instruction.operand as u32),
+                IaddC7 | IaddC8 | IaddC9 => dst.wrapping_add(instruction.operand),
+                IxorC7 | IxorC8 | IxorC9 => *dst ^ instruction.operand,
+                ImulhR => ((u128::from(*dst) * u128::from(src)) >> 64) as u64,
+                IsmulhR => ((i128::from(*dst as i64) * i128::from(src as i64)) >> 64) as u64,
+                ImulRcp => dst.wrapping_mul(instruction.operand),
+                Invalid => *dst,
+            };
+        }
+    }
+
+    pub fn address_register(&self) -> usize {
+        self.address_register
+    }
+}
+
+const SUPERSCALAR_MUL0: u64 = 6_364_136_223_846_793_005;
+const SUPERSCALAR_ADD: [u64; 8] = [
+    0,
+    9_298_411_001_130_361_340,
+    12_065_312_585_734_608_966,
+    9_306_329_213_124_626_780,
+    5_281_919_268_842_080_866,
+    10_536_153_434_571_861_004,
+    3_398_623_926_847_679_864,
+    9_549_104_520_008_361_294,
+];
+
+/// Computes dataset item `item_number` from the cache `memory` (as 64-bit words) and its programs.
+pub(crate) fn dataset_item(memory: &[u64], programs: &[Program], item_number: u64) -> [u64; 8] {
+    // The cache size is a power of two.
+    let line_mask = (memory.len() / 8 - 1) as u64;
+    let r0 = item_number.wrapping_add(1).wrapping_mul(SUPERSCALAR_MUL0);
+    let mut r = [0u64; 8];
+    for (register, add) in r.iter_mut().zip(SUPERSCALAR_ADD.iter()) {
+        *register = r0 ^ add;
+    }
+    let mut register_value = item_number;
+    for program in programs {
+        let line = ((register_value & line_mask) * 8) as usize;
+        program.execute(&mut r);
+        for (register, word) in r.iter_mut().zip(&memory[line..line + 8]) {
+            *register ^= word;
+        }
+        register_value = r[program.address_register()];
+    }
+    r
+}
@@ -0,0 +1,943 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! The RandomX virtual machine, interpreted, in light mode.
+//!
+//! The floating-point instructions round according to the mode that `CFROUND` selects. Rust only computes with
+//! round-to-nearest, so the other modes are emulated: the exact error of the rounded result is computed with an
+//! error-free transformation, and the result moves one ulp in the direction of the rounding mode if needed.
+
+#![forbid(unsafe_code)]
+// RandomX is defined on the bits of fixed-width integers, which are converted between widths and signedness as-is.
+#![allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+
+use alloc::{vec, vec::Vec};
+
+use super::{
+    aes::{fill_aes_1rx4, fill_aes_4rx4, hash_aes_1rx4},
+    reciprocal,
+    CacheData,
+};
+use crate::{blake2b::blake2b, RandomXConfig};
+
+const CACHE_LINE_SIZE: u64 = 64;
+/// The destination register of IADD_RS that takes a displacement.
+const REGISTER_NEEDS_DISPLACEMENT: usize = 5;
+/// ISTORE writes to L3 if the condition bits of its modifier are at least this.
+const STORE_L3_CONDITION: u8 = 14;
+const SCALE_MASK: u64 = 0x80f0_0000_0000_0000;
+const MANTISSA_MASK: u64 = (1 << 52) - 1;
+/// The mantissa and the 4 low exponent bits, which are kept when a value is masked into the `e` registers.
+const DYNAMIC_MANTISSA_MASK: u64 = (1 << 56) - 1;
+
+/// The parameters of the active `RandomXConfig`, in the form the interpreter uses them.
+#[derive(Debug, Clone)]
+struct Parameters {
+    /// The exclusive upper bound of each opcode range, in the order of the instructions in `Op`.
+    ceilings: [u32; 29],
+    program_size: usize,
+    iterations: u32,
+    program_count: u32,
+    scratchpad_words: usize,
+    l1_mask: u64,
+    l2_mask: u64,
+    l3_mask: u64,
+    l3_mask64: u32,
+    cache_line_align_mask: u32,
+    dataset_extra_items: u64,
+    jump_bits: u32,
+    jump_offset: u32,
+}
+
+impl Parameters {
+    fn new(config: &RandomXConfig) -> Parameters {
+        let f = &config.frequencies;
+        let frequencies = [
+            f.iadd_rs, f.iadd_m, f.isub_r, f.isub_m, f.imul_r, f.imul_m, f.imulh_r, f.imulh_m, f.ismulh_r, f.ismulh_m,
+            f.imul_rcp, f.ineg_r, f.ixor_r, f.ixor_m, f.iror_r, f.irol_r, f.iswap_r, f.fswap_r, f.fadd_r, f.fadd_m,
+            f.fsub_r, f.fsub_m, f.fscal_r, f.fmul_r, f.fdiv_m, f.fsqrt_r, f.cbranch, f.cfround, f.istore,
+        ];
+        let mut ceilings = [0; 29];
+        let mut ceiling = 0;
+        for (bound, frequency) in ceilings.iter_mut().zip(frequencies.iter()) {
+            ceiling += frequency;
+            *bound = ceiling;
+        }
+        Parameters {
+            ceilings,
+            program_size: config.program_size as usize,
+            iterations: config.program_iterations,
+            program_count: config.program_count,
+            scratchpad_words: config.scratchpad_l3 as usize / 8,
+            l1_mask: u64::from((config.scratchpad_l1 - 1) & !7),
+            l2_mask: u64::from((config.scratchpad_l2 - 1) & !7),
+            l3_mask: u64::from((config.scratchpad_l3 - 1) & !7),
+            l3_mask64: (config.scratchpad_l3 - 1) & !63,
+            cache_line_align_mask: (config.dataset_base_size - 1) & !63,
+            dataset_extra_items: u64::from(config.dataset_extra_size) / CACHE_LINE_SIZE,
+            jump_bits: config.jump_bits,
+            jump_offset: config.jump_offset,
+        }
+    }
+}
+
+/// The second operand of an integer instruction: a register, or the immediate if the source is the destination.
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Immediate(u64),
+}
+
+/// A scratchpad address: `(base + offset) & mask`, where the base is a register or zero.
+#[derive(Debug, Clone, Copy)]
+struct Address {
+    base: Option<usize>,
+    offset: u64,
+    mask: u64,
+}
+
+/// A decoded VM instruction.
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    IaddRs {
+        dst: usize,
+        src: usize,
+        shift: u32,
+        offset: u64,
+    },
+    IaddM {
+        dst: usize,
+        address: Address,
+    },
+    IsubR {
+        dst: usize,
+        src: Operand,
+    },
+    IsubM {
+        dst: usize,
+        address: Address,
+    },
+    ImulR {
+        dst: usize,
+        src: Operand,
+    },
+    ImulM {
+        dst: usize,
+        address: Address,
+    },
+    ImulhR {
+        dst: usize,
+        src: usize,
+    },
+    ImulhM {
+        dst: usize,
+        address: Address,
+    },
+    IsmulhR {
+        dst: usize,
+        src: usize,
+    },
+    IsmulhM {
+        dst: usize,
+        address: Address,
+    },
+    InegR {
+        dst: usize,
+    },
+    IxorR {
+        dst: usize,
+        src: Operand,
+    },
+    IxorM {
+        dst: usize,
+        address: Address,
+    },
+    IrorR {
+        dst: usize,
+        src: Operand,
+    },
+    IrolR {
+        dst: usize,
+        src: Operand,
+    },
+    IswapR {
+        dst: usize,
+        src: usize,
+    },
+    /// `dst` 0 to 3 is an `f` register, 4 to 7 an `e` register.
+    FswapR {
+        dst: usize,
+    },
+    FaddR {
+        dst: usize,
+        src: usize,
+    },
+    FaddM {
+        dst: usize,
+        address: Address,
+    },
+    FsubR {
+        dst: usize,
+        src: usize,
+    },
+    FsubM {
+        dst: usize,
+        address: Address,
+    },
+    FscalR {
+        dst: usize,
+    },
+    FmulR {
+        dst: usize,
+        src: usize,
+    },
+    FdivM {
+        dst: usize,
+        address: Address,
+    },
+    FsqrtR {
+        dst: usize,
+    },
+    /// Jumps to `next` if the condition bits are zero.
+    Cbranch {
+        register: usize,
+        add: u64,
+        mask: u64,
+        next: usize,
+    },
+    Cfround {
+        src: usize,
+        rotate: u32,
+    },
+    Istore {
+        dst: usize,
+        src: usize,
+        offset: u64,
+        mask: u64,
+    },
+    Nop,
+}
+
+/// The rounding modes, in the encoding of `CFROUND` (and of the x86 MXCSR).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rounding {
+    Nearest,
+    Down,
+    Up,
+    TowardZero,
+}
+
+/// The register file, laid out as RandomX hashes it.
+#[derive(Debug, Clone, Copy, Default)]
+struct Registers {
+    r: [u64; 8],
+    f: [[f64; 2]; 4],
+    e: [[f64; 2]; 4],
+    a: [[f64; 2]; 4],
+}
+
+impl Registers {
+    fn to_bytes(self) -> [u8; 256] {
+        let mut bytes = [0u8; 256];
+        let floats = self
+            .f
+            .iter()
+            .chain(self.e.iter())
+            .chain(self.a.iter())
+            .flat_map(|lanes| lanes.iter());
+        let words = self.r.iter().copied().chain(floats.map(|x| x.to_bits()));
+        for (chunk, word) in bytes.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// The scratchpad and the program buffer of a light-mode VM.
+#[derive(Debug, Clone)]
+pub(crate) struct Machine {
+    parameters: Parameters,
+    scratchpad: Vec<u64>,
+    /// 128 bytes of entropy followed by the program, as 64-bit words.
+    program: Vec<u64>,
+    ops: Vec<Op>,
+    registers: Registers,
+    rounding: Rounding,
+}
+
+impl Machine {
+    /// Returns `None` if the scratchpad cannot be allocated.
+    pub fn new(config: &RandomXConfig) -> Option<Machine> {
+        let parameters = Parameters::new(config);
+        let mut scratchpad = Vec::new();
+        scratchpad.try_reserve_exact(parameters.scratchpad_words).ok()?;
+        scratchpad.resize(parameters.scratchpad_words, 0);
+        Some(Machine {
+            program: vec![0; 16 + parameters.program_size],
+            ops: Vec::with_capacity(parameters.program_size),
+            scratchpad,
+            parameters,
+            registers: Registers::default(),
+            rounding: Rounding::Nearest,
+        })
+    }
+
+    pub fn calculate_hash(&mut self, cache: &CacheData, input: &[u8], output: &mut [u8; 32]) {
+        let mut seed = [0u8; 64];
+        blake2b(&mut seed, input);
+        fill_aes_1rx4(&mut seed, &mut self.scratchpad);
+        self.rounding = Rounding::Nearest;
+        for _ in 1..self.parameters.program_count {
+            self.run(cache, &seed);
+            blake2b(&mut seed, &self.registers.to_bytes());
+        }
+        self.run(cache, &seed);
+
+        // The final result replaces the `a` registers with a hash of the scratchpad.
+        let scratchpad_hash = hash_aes_1rx4(&self.scratchpad);
+        for (i, chunk) in scratchpad_hash.chunks_exact(8).enumerate() {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            self.registers.a[i / 2][i % 2] = f64::from_bits(u64::from_le_bytes(word));
+        }
+        blake2b(output, &self.registers.to_bytes());
+    }
+
+    fn run(&mut self, cache: &CacheData, seed: &[u8; 64]) {
+        fill_aes_4rx4(seed, &mut self.program);
+        self.compile();
+
+        let entropy = &self.program[..16];
+        let parameters = &self.parameters;
+        let mut registers = Registers::default();
+        for (i, lanes) in registers.a.iter_mut().enumerate() {
+            for (j, lane) in lanes.iter_mut().enumerate() {
+                *lane = f64::from_bits(small_positive_float_bits(entropy[2 * i + j]));
+            }
+        }
+        let mut ma = (entropy[8] & u64::from(parameters.cache_line_align_mask)) as u32;
+        let mut mx = entropy[10] as u32;
+        let address_registers = entropy[12];
+        let read_registers = [
+            (address_registers & 1) as usize,
+            2 + (address_registers >> 1 & 1) as usize,
+            4 + (address_registers >> 2 & 1) as usize,
+            6 + (address_registers >> 3 & 1) as usize,
+        ];
+        let dataset_offset = (entropy[13] % (parameters.dataset_extra_items + 1)) * CACHE_LINE_SIZE;
+        let e_mask = [float_mask(entropy[14]), float_mask(entropy[15])];
+
+        let mut sp_addr0 = mx;
+        let mut sp_addr1 = ma;
+        for _ in 0..parameters.iterations {
+            let sp_mix = registers.r[read_registers[0]] ^ registers.r[read_registers[1]];
+            sp_addr0 = (sp_addr0 ^ sp_mix as u32) & parameters.l3_mask64;
+            sp_addr1 = (sp_addr1 ^ (sp_mix >> 32) as u32) & parameters.l3_mask64;
+            let line0 = sp_addr0 as usize / 8;
+            let line1 = sp_addr1 as usize / 8;
+            for (register, word) in registers.r.iter_mut().zip(&self.scratchpad[line0..line0 + 8]) {
+                *register ^= word;
+            }
+            for i in 0..4 {
+                registers.f[i] = convert(self.scratchpad[line1 + i]);
+                registers.e[i] = mask_exponent_mantissa(convert(self.scratchpad[line1 + 4 + i]), &e_mask);
+            }
+
+            execute(
+                &self.ops,
+                &mut registers,
+                &mut self.scratchpad,
+                &e_mask,
+                &mut self.rounding,
+            );
+
+            mx ^= (registers.r[read_registers[2]] ^ registers.r[read_registers[3]]) as u32;
+            mx &= parameters.cache_line_align_mask;
+            let item = cache.dataset_item((dataset_offset + u64::from(ma)) / CACHE_LINE_SIZE);
+            for (register, word) in registers.r.iter_mut().zip(item.iter()) {
+                *register ^= word;
+            }
+            core::mem::swap(&mut mx, &mut ma);
+
+            self.scratchpad[line1..line1 + 8].copy_from_slice(&registers.r);
+            for i in 0..4 {
+                for lane in 0..2 {
+                    let bits = registers.f[i][lane].to_bits() ^ registers.e[i][lane].to_bits();
+                    registers.f[i][lane] = f64::from_bits(bits);
+                    self.scratchpad[line0 + 2 * i + lane] = bits;
+                }
+            }
+            sp_addr0 = 0;
+            sp_addr1 = 0;
+        }
+        self.registers = registers;
+    }
+
+    /// Decodes the program in the program buffer.
+    #[allow(clippy::too_many_lines)] // one arm per instruction
+    fn compile(&mut self) {
+        let parameters = &self.parameters;
+        // The last instruction that modified each register, the target of the branches that test it.
+        let mut register_usage: [Option<usize>; 8] = [None; 8];
+        self.ops.clear();
+        for (i, &word) in self.program[16..].iter().enumerate() {
+            let opcode = (word & 0xff) as u32;
+            let dst = (word >> 8 & 0xff) as usize % 8;
+            let src = (word >> 16 & 0xff) as usize % 8;
+            let modifier = (word >> 24 & 0xff) as u8;
+            let imm32 = (word >> 32) as u32;
+            let imm = imm32 as i32 as u64;
+            let mod_mem = modifier % 4;
+            let mod_shift = u32::from((modifier >> 2) % 4);
+            let mod_cond = u32::from(modifier >> 4);
+
+            let operand = if src == dst {
+                Operand::Immediate(imm)
+            } else {
+                Operand::Register(src)
+            };
+            let l1_or_l2 = if mod_mem == 0 {
+                parameters.l2_mask
+            } else {
+                parameters.l1_mask
+            };
+            // Integer loads read from L3 at a constant address if the source is the destination.
+            let int_address = if src == dst {
+                Address {
+                    base: None,
+                    offset: imm,
+                    mask: parameters.l3_mask,
+                }
+            } else {
+                Address {
+                    base: Some(src),
+                    offset: imm,
+                    mask: l1_or_l2,
+                }
+            };
+            let float_address = Address {
+                base: Some(src),
+                offset: imm,
+                mask: l1_or_l2,
+            };
+
+            let index = parameters.ceilings.iter().position(|&ceiling| opcode < ceiling);
+            let (op, written) = match index {
+                Some(0) => {
+                    let offset = if dst == REGISTER_NEEDS_DISPLACEMENT { imm } else { 0 };
+                    (
+                        Op::IaddRs {
+                            dst,
+                            src,
+                            shift: mod_shift,
+                            offset,
+                        },
+                        Some(dst),
+                    )
+                },
+                Some(1) => (
+                    Op::IaddM {
+                        dst,
+                        address: int_address,
+                    },
+                    Some(dst),
+                ),
+                Some(2) => (Op::IsubR { dst, src: operand }, Some(dst)),
+                Some(3) => (
+                    Op::IsubM {
+                        dst,
+                        address: int_address,
+                    },
+                    Some(dst),
+                ),
+                Some(4) => (Op::ImulR { dst, src: operand }, Some(dst)),
+                Some(5) => (
+                    Op::ImulM {
+                        dst,
+                        address: int_address,
+                    },
+                    Some(dst),
+                ),
+                Some(6) => (Op::ImulhR { dst, src }, Some(dst)),
+                Some(7) => (
+                    Op::ImulhM {
+                        dst,
+                        address: int_address,
+                    },
+                    Some(dst),
+                ),
+                Some(8) => (Op::IsmulhR { dst, src }, Some(dst)),
+                Some(9) => (
+                    Op::IsmulhM {
+                        dst,
+                        address: int_address,
+                    },
+                    Some(dst),
+                ),
+                Some(10) => {
+                    let divisor = u64::from(imm32);
+                    if divisor & divisor.wrapping_sub(1) != 0 {
+                        (
+                            Op::ImulR {
+                                dst,
+                                src: Operand::Immediate(reciprocal(divisor)),
+                            },
+                            Some(dst),
+                        )
+                    } else {
+                        (Op::Nop, None)
+                    }
+                },
+                Some(11) => (Op::InegR { dst }, Some(dst)),
+                Some(12) => (Op::IxorR { dst, src: operand }, Some(dst)),
+                Some(13) => (
+                    Op::IxorM {
+                        dst,
+                        address: int_address,
+                    },
+                    Some(dst),
+                ),
+                // The rotation immediate is not sign-extended.
+                Some(14) => (
+                    Op::IrorR {
+                        dst,
+                        src: if src == dst {
+                            Operand::Immediate(u64::from(imm32))
+                        } else {
+                            operand
+                        },
+                    },
+                    Some(dst),
+                ),
+                Some(15) => (
+                    Op::IrolR {
+                        dst,
+                        src: if src == dst {
+                            Operand::Immediate(u64::from(imm32))
+                        } else {
+                            operand
+                        },
+                    },
+                    Some(dst),
+                ),
+                Some(16) => {
+                    if src == dst {
+                        (Op::Nop, None)
+                    } else {
+                        register_usage[src] = Some(i);
+                        (Op::IswapR { dst, src }, Some(dst))
+                    }
+                },
+                Some(17) => (Op::FswapR { dst }, None),
+                Some(18) => (
+                    Op::FaddR {
+                        dst: dst % 4,
+                        src: src % 4,
+                    },
+                    None,
+                ),
+                Some(19) => (
+                    Op::FaddM {
+                        dst: dst % 4,
+                        address: float_address,
+                    },
+                    None,
+                ),
+                Some(20) => (
+                    Op::FsubR {
+                        dst: dst % 4,
+                        src: src % 4,
+                    },
+                    None,
+                ),
+                Some(21) => (
+                    Op::FsubM {
+                        dst: dst % 4,
+                        address: float_address,
+                    },
+                    None,
+                ),
+                Some(22) => (Op::FscalR { dst: dst % 4 }, None),
+                Some(23) => (
+                    Op::FmulR {
+                        dst: dst % 4,
+                        src: src % 4,
+                    },
+                    None,
+                ),
+                Some(24) => (
+                    Op::FdivM {
+                        dst: dst % 4,
+                        address: float_address,
+                    },
+                    None,
+                ),
+                Some(25) => (Op::FsqrtR { dst: dst % 4 }, None),
+                Some(26) => {
+                    let shift = mod_cond + parameters.jump_offset;
+                    let mut add = imm | 1 << shift;
+                    // Clearing the bit below the condition limits the number of successive jumps to 2.
+                    if parameters.jump_offset > 0 || shift > 0 {
+                        add &= !(1 << (shift - 1));
+                    }
+                    let op = Op::Cbranch {
+                        register: dst,
+                        add,
+                        mask: ((1 << parameters.jump_bits) - 1) << shift,
+                        next: register_usage[dst].map_or(0, |target| target + 1),
+                    };
+                    register_usage = [Some(i); 8];
+                    (op, None)
+                },
+                Some(27) => (
+                    Op::Cfround {
+                        src,
+                        rotate: imm32 & 63,
+                    },
+                    None,
+                ),
+                Some(28) => {
+                    let mask = if modifier >> 4 < STORE_L3_CONDITION {
+                        l1_or_l2
+                    } else {
+                        parameters.l3_mask
+                    };
+                    (
+                        Op::Istore {
+                            dst,
+                            src,
+                            offset: imm,
+                            mask,
+                        },
+                        None,
+                    )
+                },
+                _ => (Op::Nop, None),
+            };
+            if let Some(register) = written {
+                register_usage[register] = Some(i);
+            }
+            self.ops.push(op);
+        }
+    }
+}
+
+#[allow(clippy::too_many_lines)] // one arm per instruction
+fn execute(ops: &[Op], registers: &mut Registers, scratchpad: &mut [u64], e_mask: &[u64; 2], rounding: &mut Rounding) {
+    let load = |registers: &Registers, scratchpad: &[u64], address: &Address| -> u64 {
+        let base = address.base.map_or(0, |base| registers.r[base]);
+        scratchpad[(base.wrapping_add(address.offset) & address.mask) as usize / 8]
+    };
+    let operand = |registers: &Registers, operand: Operand| match operand {
+        Operand::Register(src) => registers.r[src],
+        Operand::Immediate(imm) => imm,
+    };
+
+    let mut pc = 0;
+    while pc < ops.len() {
+        let mode = *rounding;
+        match ops[pc] {
+            Op::IaddRs {
+                dst,
+                src,
+                shift,
+                offset,
+            } => {
+                registers.r[dst] = registers.r[dst]
+                    .wrapping_add(registers.r[src] << shift)
+                    .wrapping_add(offset);
+            },
+            Op::IaddM { dst, address } => {
+                registers.r[dst] = registers.r[dst].wrapping_add(load(registers, scratchpad, &address));
+            },
+            Op::IsubR { dst, src } => registers.r[dst] = registers.r[dst].wrapping_sub(operand(registers, src)),
+            Op::IsubM { dst, address } => {
+                registers.r[dst] = registers.r[dst].wrapping_sub(load(registers, scratchpad, &address));
+            },
+            Op::ImulR { dst, src } => registers.r[dst] = registers.r[dst].wrapping_mul(operand(registers, src)),
+            Op::ImulM { dst, address } => {
+                registers.r[dst] = registers.r[dst].wrapping_mul(load(registers, scratchpad, &address));
+            },
+            Op::ImulhR { dst, src } => registers.r[dst] = mulh(registers.r[dst], registers.r[src]),
+            Op::ImulhM { dst, address } => {
+                registers.r[dst] = mulh(registers.r[dst], load(registers, scratchpad, &address));
+            },
+            Op::IsmulhR { dst, src } => registers.r[dst] = smulh(registers.r[dst], registers.r[src]),
+            Op::IsmulhM { dst, address } => {
+                registers.r[dst] = smulh(registers.r[dst], load(registers, scratchpad, &address));
+            },
+            Op::InegR { dst } => registers.r[dst] = registers.r[dst].wrapping_neg(),
+            Op::IxorR { dst, src } => registers.r[dst] ^= operand(registers, src),
+            Op::IxorM { dst, address } => registers.r[dst] ^= load(registers, scratchpad, &address),
+            Op::IrorR { dst, src } => {
+                registers.r[dst] = registers.r[dst].rotate_right((operand(registers, src) & 63) as u32);
+            },
+            Op::IrolR { dst, src } => {
+                registers.r[dst] = registers.r[dst].rotate_left((operand(registers, src) & 63) as u32);
+            },
+            Op::IswapR { dst, src } => registers.r.swap(dst, src),
+            Op::FswapR { dst } => {
+                if dst < 4 {
+                    registers.f[dst].swap(0, 1);
+                } else {
+                    registers.e[dst - 4].swap(0, 1);
+                }
+            },
+            Op::FaddR { dst, src } => {
+                let a = registers.a[src];
+                let f = &mut registers.f[dst];
+                *f = [add(f[0], a[0], mode), add(f[1], a[1], mode)];
+            },
+            Op::FaddM { dst, address } => {
+                let x = convert(load(registers, scratchpad, &address));
+                let f = &mut registers.f[dst];
+                *f = [add(f[0], x[0], mode), add(f[1], x[1], mode)];
+            },
+            Op::FsubR { dst, src } => {
+                let a = registers.a[src];
+                let f = &mut registers.f[dst];
+                *f = [add(f[0], -a[0], mode), add(f[1], -a[1], mode)];
+            },
+            Op::FsubM { dst, address } => {
+                let x = convert(load(registers, scratchpad, &address));
+                let f = &mut registers.f[dst];
+                *f = [add(f[0], -x[0], mode), add(f[1], -x[1], mode)];
+            },
+            Op::FscalR { dst } => {
+                for lane in &mut registers.f[dst] {
+                    *lane = f64::from_bits(lane.to_bits() ^ SCALE_MASK);
+                }
+            },
+            Op::FmulR { dst, src } => {
+                let a = registers.a[src];
+                let e = &mut registers.e[dst];
+                *e = [mul(e[0], a[0], mode), mul(e[1], a[1], mode)];
+            },
+            Op::FdivM { dst, address } => {
+                let x = mask_exponent_mantissa(convert(load(registers, scratchpad, &address)), e_mask);
+                let e = &mut registers.e[dst];
+                *e = [div(e[0], x[0], mode), div(e[1], x[1], mode)];
+            },
+            Op::FsqrtR { dst } => {
+                let e = &mut registers.e[dst];
+                *e = [sqrt(e[0], mode), sqrt(e[1], mode)];
+            },
+            Op::Cbranch {
+                register,
+                add,
+                mask,
+                next,
+            } => {
+                registers.r[register] = registers.r[register].wrapping_add(add);
+                if registers.r[register] & mask == 0 {
+                    pc = next;
+                    continue;
+                }
+            },
+            Op::Cfround { src, rotate } => {
+                *rounding = match registers.r[src].rotate_right(rotate) % 4 {
+                    0 => Rounding::Nearest,
+                    1 => Rounding::Down,
+                    2 => Rounding::Up,
+                    _ => Rounding::TowardZero,
+                };
+            },
+            Op::Istore { dst, src, offset, mask } => {
+                scratchpad[(registers.r[dst].wrapping_add(offset) & mask) as usize / 8] = registers.r[src];
+            },
+            Op::Nop => {},
+        }
+        pc += 1;
+    }
+}
+
+fn mulh(a: u64, b: u64) -> u64 {
+    ((u128::from(a) * u128::from(b)) >> 64) as u64
+}
+
+fn smulh(a: u64, b: u64) -> u64 {
+    ((i128::from(a as i64) * i128::from(b as i64)) >> 64) as u64
+}
+
+/// Converts the two signed 32-bit integers in a scratchpad word to doubles.
+fn convert(word: u64) -> [f64; 2] {
+    [f64::from(word as u32 as i32), f64::from((word >> 32) as u32 as i32)]
+}
+
+fn mask_exponent_mantissa(x: [f64; 2], e_mask: &[u64; 2]) -> [f64; 2] {
+    [
+        f64::from_bits(x[0].to_bits() & DYNAMIC_MANTISSA_MASK | e_mask[0]),
+        f64::from_bits(x[1].to_bits() & DYNAMIC_MANTISSA_MASK | e_mask[1]),
+    ]
+}
+
+/// A positive double with an exponent of 0 to 31 and a random mantissa, for the `a` registers.
+fn small_positive_float_bits(entropy: u64) -> u64 {
+    let exponent = ((entropy >> 59) + 1023) & 0x7ff;
+    exponent << 52 | entropy & MANTISSA_MASK
+}
+
+/// The bits ORed into the values of the `e` registers: 22 low mantissa bits and an exponent of the form
+/// `0x300 | xxxx0000`.
+fn float_mask(entropy: u64) -> u64 {
+    let exponent = 0x300 | (entropy >> 60) << 4;
+    entropy & ((1 << 22) - 1) | exponent << 52
+}
+
+fn next_up(x: f64) -> f64 {
+    if x >= 0.0 {
+        f64::from_bits(x.to_bits() + 1)
+    } else {
+        f64::from_bits(x.to_bits() - 1)
+    }
+}
+
+fn next_down(x: f64) -> f64 {
+    -next_up(-x)
+}
+
+/// Rounds the exact value `rounded + error`, where `rounded` is the round-to-nearest result, in `mode`.
+fn round(rounded: f64, error: f64, mode: Rounding) -> f64 {
+    match mode {
+        Rounding::Nearest => rounded,
+        Rounding::Down if error < 0.0 => next_down(rounded),
+        Rounding::Up if error > 0.0 => next_up(rounded),
+        Rounding::TowardZero if rounded > 0.0 && error < 0.0 => next_down(rounded),
+        Rounding::TowardZero if rounded < 0.0 && error > 0.0 => next_up(rounded),
+        _ => rounded,
+    }
+}
+
+/// The result of an operation on finite operands that rounded to infinity: infinity, or the largest finite value of
+/// its sign if the mode rounds toward zero from there.
+fn overflow(infinity: f64, mode: Rounding) -> f64 {
+    let toward_zero = match mode {
+        Rounding::Nearest => false,
+        Rounding::Down => infinity > 0.0,
+        Rounding::Up => infinity < 0.0,
+        Rounding::TowardZero => true,
+    };
+    if toward_zero {
+        libm::copysign(f64::MAX, infinity)
+    } else {
+        infinity
+    }
+}
+
+fn add(a: f64, b: f64, mode: Rounding) -> f64 {
+    let sum = a + b;
+    if mode == Rounding::Nearest {
+        return sum;
+    }
+    if sum.is_infinite() {
+        return if a.is_finite() && b.is_finite() {
+            overflow(sum, mode)
+        } else {
+            sum
+        };
+    }
+    if sum == 0.0 {
+        // An exact zero sum is negative when rounding down, unless both operands are +0.
+        return if mode == Rounding::Down && (a.to_bits() != 0 || b.to_bits() != 0) {
+            -0.0
+        } else {
+            sum
+        };
+    }
+    // TwoSum
+    let b_virtual = sum - a;
+    let error = (a - (sum - b_virtual)) + (b - b_virtual);
+    round(sum, error, mode)
+}
+
+fn mul(a: f64, b: f64, mode: Rounding) -> f64 {
+    let product = a * b;
+    if mode == Rounding::Nearest {
+        return product;
+    }
+    if product.is_infinite() {
+        return if a.is_finite() && b.is_finite() {
+            overflow(product, mode)
+        } else {
+            product
+        };
+    }
+    round(product, libm::fma(a, b, -product), mode)
+}
+
+fn div(a: f64, b: f64, mode: Rounding) -> f64 {
+    let quotient = a / b;
+    if mode == Rounding::Nearest {
+        return quotient;
+    }
+    if quotient.is_infinite() {
+        return if a.is_finite() {
+            overflow(quotient, mode)
+        } else {
+            quotient
+        };
+    }
+    // The remainder a - quotient * b is exact, and has the sign of the error times the sign of b.
+    let remainder = libm::fma(-quotient, b, a);
+    round(quotient, if b < 0.0 { -remainder } else { remainder }, mode)
+}
+
+fn sqrt(x: f64, mode: Rounding) -> f64 {
+    let root = libm::sqrt(x);
+    if mode == Rounding::Nearest || root.is_infinite() {
+        return root;
+    }
+    round(root, libm::fma(-root, root, x), mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directed_rounding_of_inexact_results() {
+        let third = 1.0 / 3.0;
+        assert!(div(1.0, 3.0, Rounding::Down) < div(1.0, 3.0, Rounding::Up));
+        assert_eq!(div(1.0, 3.0, Rounding::Nearest), third);
+        assert_eq!(div(1.0, 3.0, Rounding::TowardZero), div(1.0, 3.0, Rounding::Down));
+        assert_eq!(add(1.0, 1e-20, Rounding::Up), next_up(1.0));
+        assert_eq!(add(1.0, 1e-20, Rounding::Down), 1.0);
+        assert_eq!(add(-1.0, -1e-20, Rounding::TowardZero), -1.0);
+        assert_eq!(mul(f64::MAX, 2.0, Rounding::TowardZero), f64::MAX);
+        assert_eq!(mul(f64::MAX, 2.0, Rounding::Up), f64::INFINITY);
+        // The nearest double to the square root of 2 is above it.
+        assert_eq!(sqrt(2.0, Rounding::Up), core::f64::consts::SQRT_2);
+        assert_eq!(sqrt(2.0, Rounding::Down), next_down(core::f64::consts::SQRT_2));
+        assert_eq!(add(1.0, -1.0, Rounding::Down).to_bits(), (-0.0f64).to_bits());
+        assert_eq!(add(1.0, -1.0, Rounding::Up).to_bits(), 0);
+    }
+
+    #[test]
+    fn exact_results_are_not_adjusted() {
+        for &mode in &[Rounding::Down, Rounding::Up, Rounding::TowardZero] {
+            assert_eq!(add(1.5, 2.25, mode), 3.75);
+            assert_eq!(mul(1.5, -4.0, mode), -6.0);
+            assert_eq!(div(1.0, 4.0, mode), 0.25);
+            assert_eq!(sqrt(16.0, mode), 4.0);
+        }
+    }
+}
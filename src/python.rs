@@ -0,0 +1,317 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Python bindings, built with [maturin](https://www.maturin.rs) as the `randomx_rs` extension module.
+//!
+//! `RandomXFlag`, `RandomXCache`, `RandomXDataset` and `RandomXVM` are Python classes over the types of this crate.
+//! Flags can also be given as an `int` or as a string in the format of [`RandomXFlag`]'s `FromStr`. Keys and inputs
+//! are `bytes`, read in place, and the GIL is released while caches and datasets are initialized and while hashing.
+//! Re-initializing a cache with another key waits for the VMs and datasets that are reading it on other threads.
+//! Failures raise `randomx_rs.RandomXError`.
+
+use std::sync::{Arc, PoisonError, RwLock};
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+
+use crate::{RandomXCache, RandomXDataset, RandomXError, RandomXFlag, RandomXVM};
+
+create_exception!(randomx_rs, PyRandomXError, PyException, "A RandomX operation failed.");
+
+impl From<RandomXError> for PyErr {
+    fn from(error: RandomXError) -> PyErr {
+        PyRandomXError::new_err(error.to_string())
+    }
+}
+
+/// Lets a VM, which is not `Send`, into [`Python::allow_threads`], which runs the closure on the calling thread.
+struct VmOnCallingThread<'a>(&'a RandomXVM);
+
+// SAFETY: `allow_threads` does not move the closure to another thread, and the Python `RandomXVM` class is unsendable,
+// so no other thread can use the VM while the GIL is released. The only state it shares is its cache, which
+// `RandomXCache.init` does not re-initialize while the closure holds the read lock of the cache (see `CacheLock`).
+unsafe impl Send for VmOnCallingThread<'_> {}
+
+/// Held for reading while a cache is read without the GIL (by a VM hashing or a dataset being built from it), and
+/// for writing while it is re-initialized, which would otherwise race with the readers.
+type CacheLock = Arc<RwLock<()>>;
+
+/// RandomX flags. Combine them with `|`; `str()` gives the flag names.
+#[pyclass(name = "RandomXFlag", module = "randomx_rs", frozen, eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PyRandomXFlag {
+    bits: u32,
+}
+
+impl PyRandomXFlag {
+    fn flags(&self) -> RandomXFlag {
+        RandomXFlag::from_bits_retain(self.bits)
+    }
+}
+
+impl From<RandomXFlag> for PyRandomXFlag {
+    fn from(flags: RandomXFlag) -> Self {
+        PyRandomXFlag { bits: flags.bits() }
+    }
+}
+
+/// Flags given as a `RandomXFlag`, an `int` or a string of flag names.
+#[derive(Clone, Copy)]
+struct Flags(RandomXFlag);
+
+impl<'py> FromPyObject<'py> for Flags {
+    fn extract_bound(value: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(flags) = value.downcast::<PyRandomXFlag>() {
+            Ok(Flags(flags.get().flags()))
+        } else if let Ok(bits) = value.extract::<u32>() {
+            Ok(Flags(RandomXFlag::from_bits_retain(bits)))
+        } else {
+            let names = value.extract::<&str>()?;
+            let flags = names
+                .parse()
+                .map_err(|error| PyValueError::new_err(format!("{}", error)))?;
+            Ok(Flags(flags))
+        }
+    }
+}
+
+#[pymethods]
+impl PyRandomXFlag {
+    #[classattr]
+    const FLAG_ARGON2: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_ARGON2.bits(),
+    };
+    #[classattr]
+    const FLAG_ARGON2_AVX2: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_ARGON2_AVX2.bits(),
+    };
+    #[classattr]
+    const FLAG_ARGON2_SSSE3: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_ARGON2_SSSE3.bits(),
+    };
+    #[classattr]
+    const FLAG_DEFAULT: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_DEFAULT.bits(),
+    };
+    #[classattr]
+    const FLAG_FULL_MEM: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_FULL_MEM.bits(),
+    };
+    #[classattr]
+    const FLAG_HARD_AES: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_HARD_AES.bits(),
+    };
+    #[classattr]
+    const FLAG_JIT: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_JIT.bits(),
+    };
+    #[classattr]
+    const FLAG_LARGE_PAGES: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_LARGE_PAGES.bits(),
+    };
+    #[classattr]
+    const FLAG_SECURE: PyRandomXFlag = PyRandomXFlag {
+        bits: RandomXFlag::FLAG_SECURE.bits(),
+    };
+
+    #[new]
+    #[pyo3(signature = (flags = Flags(RandomXFlag::FLAG_DEFAULT)))]
+    fn new(flags: Flags) -> Self {
+        flags.0.into()
+    }
+
+    #[staticmethod]
+    fn get_recommended_flags() -> Self {
+        RandomXFlag::get_recommended_flags().into()
+    }
+
+    #[staticmethod]
+    fn disabled() -> Self {
+        RandomXFlag::disabled().into()
+    }
+
+    fn __or__(&self, other: Flags) -> Self {
+        (self.flags() | other.0).into()
+    }
+
+    fn __and__(&self, other: Flags) -> Self {
+        (self.flags() & other.0).into()
+    }
+
+    fn __contains__(&self, other: Flags) -> bool {
+        self.flags().contains(other.0)
+    }
+
+    fn __int__(&self) -> u32 {
+        self.bits
+    }
+
+    fn __hash__(&self) -> u64 {
+        u64::from(self.bits)
+    }
+
+    fn __str__(&self) -> String {
+        self.flags().to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("RandomXFlag('{}')", self.flags())
+    }
+}
+
+/// A RandomX cache for light-mode hashing and dataset initialization.
+#[pyclass(name = "RandomXCache", module = "randomx_rs", frozen)]
+struct PyRandomXCache {
+    cache: RandomXCache,
+    lock: CacheLock,
+}
+
+#[pymethods]
+impl PyRandomXCache {
+    #[new]
+    fn new(py: Python<'_>, flags: Flags, key: &[u8]) -> PyResult<Self> {
+        let cache = py.allow_threads(|| RandomXCache::new(flags.0, key))?;
+        Ok(PyRandomXCache {
+            cache,
+            lock: CacheLock::default(),
+        })
+    }
+
+    /// Initializes the cache with `key`, unless it already holds it. Waits for the VMs hashing with the cache, and
+    /// the datasets being built from it, on other threads.
+    fn init(&self, py: Python<'_>, key: &[u8]) -> PyResult<()> {
+        Ok(py.allow_threads(|| {
+            let _writing = self.lock.write().unwrap_or_else(PoisonError::into_inner);
+            self.cache.init(key)
+        })?)
+    }
+
+    fn key_digest<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.cache.key_digest())
+    }
+}
+
+/// A RandomX dataset for fast-mode hashing, initialized from a cache.
+#[pyclass(name = "RandomXDataset", module = "randomx_rs", frozen)]
+struct PyRandomXDataset(RandomXDataset);
+
+#[pymethods]
+impl PyRandomXDataset {
+    #[new]
+    #[pyo3(signature = (flags, cache, start = 0))]
+    fn new(py: Python<'_>, flags: Flags, cache: &PyRandomXCache, start: u32) -> PyResult<Self> {
+        let dataset = py.allow_threads(|| {
+            let _reading = cache.lock.read().unwrap_or_else(PoisonError::into_inner);
+            RandomXDataset::new(flags.0, cache.cache.clone(), start)
+        })?;
+        Ok(PyRandomXDataset(dataset))
+    }
+
+    /// Returns the number of items in a dataset.
+    #[staticmethod]
+    fn count() -> PyResult<u32> {
+        Ok(RandomXDataset::count()?)
+    }
+}
+
+/// A RandomX VM. It can only be used from the thread that created it.
+#[pyclass(name = "RandomXVM", module = "randomx_rs", unsendable)]
+struct PyRandomXVM {
+    vm: RandomXVM,
+    // The lock of the cache the VM hashes with in light mode; a dataset is not changed by re-keying its cache.
+    cache_lock: Option<CacheLock>,
+}
+
+impl PyRandomXVM {
+    /// Runs `hash` with the VM without holding the GIL, but with the read lock of its cache.
+    fn hash_without_gil<T, F>(&self, py: Python<'_>, hash: F) -> Result<T, RandomXError>
+    where
+        T: Send,
+        F: FnOnce(&RandomXVM) -> Result<T, RandomXError> + Send,
+    {
+        let vm = VmOnCallingThread(&self.vm);
+        let cache_lock = self.cache_lock.as_deref();
+        py.allow_threads(move || {
+            let vm = vm;
+            let _reading = cache_lock.map(|lock| lock.read().unwrap_or_else(PoisonError::into_inner));
+            hash(vm.0)
+        })
+    }
+}
+
+#[pymethods]
+impl PyRandomXVM {
+    #[new]
+    #[pyo3(signature = (flags, cache = None, dataset = None))]
+    fn new(flags: Flags, cache: Option<&PyRandomXCache>, dataset: Option<&PyRandomXDataset>) -> PyResult<Self> {
+        let cache_lock = cache.map(|cache| Arc::clone(&cache.lock));
+        let cache = cache.map(|cache| cache.cache.clone());
+        let dataset = dataset.map(|dataset| dataset.0.clone());
+        Ok(PyRandomXVM {
+            vm: RandomXVM::new(flags.0, cache, dataset)?,
+            cache_lock,
+        })
+    }
+
+    /// Returns the hash of `input`, without holding the GIL while hashing.
+    fn calculate_hash<'py>(&self, py: Python<'py>, input: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+        let hash = self.hash_without_gil(py, |vm| vm.calculate_hash(input))?;
+        Ok(PyBytes::new(py, &hash))
+    }
+
+    /// Returns the hashes of `inputs`, without holding the GIL while hashing.
+    #[allow(clippy::needless_pass_by_value)] // Arguments are extracted by value by `pyo3`
+    fn calculate_hash_set<'py>(
+        &self,
+        py: Python<'py>,
+        inputs: Vec<Bound<'py, PyBytes>>,
+    ) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+        let inputs: Vec<&[u8]> = inputs.iter().map(|input| input.as_bytes()).collect();
+        let hashes = self.hash_without_gil(py, |vm| vm.calculate_hash_set(&inputs))?;
+        Ok(hashes.iter().map(|hash| PyBytes::new(py, hash)).collect())
+    }
+
+    fn reinit_cache(&mut self, cache: &PyRandomXCache) -> PyResult<()> {
+        self.vm.reinit_cache(cache.cache.clone())?;
+        self.cache_lock = Some(Arc::clone(&cache.lock));
+        Ok(())
+    }
+
+    fn reinit_dataset(&mut self, dataset: &PyRandomXDataset) -> PyResult<()> {
+        Ok(self.vm.reinit_dataset(dataset.0.clone())?)
+    }
+}
+
+/// RandomX proof-of-work hashing.
+#[pymodule]
+fn randomx_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyRandomXFlag>()?;
+    m.add_class::<PyRandomXCache>()?;
+    m.add_class::<PyRandomXDataset>()?;
+    m.add_class::<PyRandomXVM>()?;
+    m.add("RandomXError", m.py().get_type::<PyRandomXError>())?;
+    Ok(())
+}
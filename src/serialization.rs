@@ -0,0 +1,566 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Serde support, with the `serde` feature.
+//!
+//! [`RandomXFlag`] serialises as a list of flag names in human-readable formats, with any bits that have no name
+//! appended as a number, and as its bits otherwise. [`RandomXConfig`] serialises its name, salt and `configuration.h`
+//! defines, and only deserialises to a parameter set built into the crate. Hashes and other byte strings can use
+//! [`hex_bytes`], which writes hex strings in human-readable formats and raw bytes otherwise.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::{convert::TryFrom, fmt};
+
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::{SerializeSeq, SerializeStruct},
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+};
+
+use crate::{InstructionFrequencies, RandomXConfig, RandomXFlag};
+
+/// Serde functions for byte strings such as hashes, for use with `#[serde(with =
+/// "randomx_rs::serialization::hex_bytes")]` on `Vec<u8>` and `[u8; N]` fields.
+pub mod hex_bytes {
+    use alloc::vec::Vec;
+    use core::{convert::TryFrom, fmt};
+
+    use serde::{
+        de::{self, SeqAccess, Visitor},
+        Deserializer,
+        Serializer,
+    };
+
+    /// Serialises `bytes` as a hex string in human-readable formats, and as bytes otherwise.
+    pub fn serialize<T: AsRef<[u8]>, S: Serializer>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes.as_ref())
+        }
+    }
+
+    /// Deserialises bytes written by [`serialize`]; arrays fail on input of another length.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let bytes = if deserializer.is_human_readable() {
+            deserializer.deserialize_str(BytesVisitor)?
+        } else {
+            deserializer.deserialize_byte_buf(BytesVisitor)?
+        };
+        let len = bytes.len();
+        T::try_from(bytes).map_err(|_| de::Error::invalid_length(len, &"a byte string of the expected length"))
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a hex string or bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+            hex::decode(v).map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+
+    /// Serde functions for lists of byte strings, such as `Vec<[u8; 32]>`, each written as by [`hex_bytes`](self).
+    pub mod vec {
+        use alloc::vec::Vec;
+        use core::convert::TryFrom;
+
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        struct Bytes<T>(T);
+
+        impl<T: AsRef<[u8]>> Serialize for Bytes<&T> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                super::serialize(self.0, serializer)
+            }
+        }
+
+        impl<'de, T: TryFrom<Vec<u8>>> Deserialize<'de> for Bytes<T> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                super::deserialize(deserializer).map(Bytes)
+            }
+        }
+
+        /// Serialises each of `list` with [`hex_bytes::serialize`](super::serialize).
+        pub fn serialize<T: AsRef<[u8]>, S: Serializer>(list: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(list.iter().map(Bytes))
+        }
+
+        /// Deserialises a list written by [`serialize`].
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+        where
+            T: TryFrom<Vec<u8>>,
+            D: Deserializer<'de>,
+        {
+            let list = Vec::<Bytes<T>>::deserialize(deserializer)?;
+            Ok(list.into_iter().map(|bytes| bytes.0).collect())
+        }
+    }
+}
+
+impl Serialize for RandomXFlag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_u32(self.bits());
+        }
+        let unknown = self.bits() & !RandomXFlag::all().bits();
+        let mut seq = serializer.serialize_seq(None)?;
+        for (name, _) in self.iter_names() {
+            seq.serialize_element(name)?;
+        }
+        if unknown != 0 {
+            seq.serialize_element(&unknown)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for RandomXFlag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(FlagVisitor)
+        } else {
+            u32::deserialize(deserializer).map(RandomXFlag::from_bits_retain)
+        }
+    }
+}
+
+// Accepts a flag name, bits, or a list of either.
+struct FlagVisitor;
+
+impl<'de> Visitor<'de> for FlagVisitor {
+    type Value = RandomXFlag;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a list of RandomX flag names or bits")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<RandomXFlag, E> {
+        RandomXFlag::from_name(v).ok_or_else(|| E::custom(format_args!("unknown RandomX flag {}", v)))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<RandomXFlag, E> {
+        u32::try_from(v)
+            .map(RandomXFlag::from_bits_retain)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<RandomXFlag, A::Error> {
+        let mut flags = RandomXFlag::FLAG_DEFAULT;
+        while let Some(Flag(flag)) = seq.next_element()? {
+            flags |= flag;
+        }
+        Ok(flags)
+    }
+}
+
+struct Flag(RandomXFlag);
+
+impl<'de> Deserialize<'de> for Flag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FlagVisitor).map(Flag)
+    }
+}
+
+// The frequencies are the `RANDOMX_FREQ_*` defines of a parameter set.
+const FREQUENCY_PREFIX: &str = "RANDOMX_FREQ_";
+
+impl Serialize for InstructionFrequencies {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let config = RandomXConfig {
+            frequencies: *self,
+            ..RandomXConfig::MONERO
+        };
+        let frequencies: BTreeMap<_, _> = config
+            .parameters()
+            .iter()
+            .filter(|(name, _)| name.starts_with(FREQUENCY_PREFIX))
+            .copied()
+            .collect();
+        frequencies.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for InstructionFrequencies {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let frequencies = BTreeMap::<String, u32>::deserialize(deserializer)?;
+        let mut config = RandomXConfig::MONERO;
+        let expected = config
+            .parameters()
+            .iter()
+            .filter(|(name, _)| name.starts_with(FREQUENCY_PREFIX))
+            .count();
+        if frequencies.len() != expected {
+            return Err(de::Error::invalid_length(
+                frequencies.len(),
+                &"every RANDOMX_FREQ_* define",
+            ));
+        }
+        for (name, value) in frequencies {
+            match config.parameter_mut(&name) {
+                Some(frequency) if name.starts_with(FREQUENCY_PREFIX) => *frequency = value,
+                _ => {
+                    return Err(de::Error::custom(format_args!(
+                        "unknown instruction frequency {}",
+                        name
+                    )))
+                },
+            }
+        }
+        Ok(config.frequencies)
+    }
+}
+
+impl Serialize for RandomXConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let parameters: BTreeMap<_, _> = self.parameters().iter().copied().collect();
+        let mut state = serializer.serialize_struct("RandomXConfig", 3)?;
+        state.serialize_field("name", self.name)?;
+        state.serialize_field("argon_salt", &Salt(self.argon_salt))?;
+        state.serialize_field("parameters", &parameters)?;
+        state.end()
+    }
+}
+
+struct Salt<'a>(&'a [u8]);
+
+impl Serialize for Salt<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        hex_bytes::serialize(&self.0, serializer)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "RandomXConfig")]
+struct OwnedConfig {
+    name: String,
+    #[serde(with = "hex_bytes")]
+    argon_salt: Vec<u8>,
+    parameters: BTreeMap<String, u32>,
+}
+
+impl<'de> Deserialize<'de> for RandomXConfig {
+    // The parameter set must be the active one or a preset, with the same parameters.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let owned = OwnedConfig::deserialize(deserializer)?;
+        let known = [
+            RandomXConfig::active(),
+            &RandomXConfig::MONERO,
+            &RandomXConfig::WOWNERO,
+            &RandomXConfig::ARQMA,
+        ];
+        let base = known
+            .iter()
+            .find(|config| config.name == owned.name)
+            .ok_or_else(|| de::Error::custom(format_args!("unknown RandomX parameter set {}", owned.name)))?;
+        let mut config = **base;
+        for (name, value) in &owned.parameters {
+            *config
+                .parameter_mut(name)
+                .ok_or_else(|| de::Error::custom(format_args!("unknown RandomX parameter {}", name)))? = *value;
+        }
+        if config != **base || owned.argon_salt != base.argon_salt || owned.parameters.len() != base.parameters().len()
+        {
+            return Err(de::Error::custom(format_args!(
+                "the parameters do not match the {} parameter set of this build",
+                owned.name
+            )));
+        }
+        Ok(**base)
+    }
+}
+
+// QuickCheck does not build for wasm32-unknown-unknown
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::fmt::Debug;
+
+    use quickcheck::QuickCheck;
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+    use crate::{InstructionFrequencies, RandomXConfig, RandomXFlag, StaleKeyPolicy, Variant};
+
+    const TESTS: u64 = 200;
+
+    // Returns `value` after a round trip through JSON (human-readable) and through postcard (binary).
+    fn round_trips<T: Serialize + DeserializeOwned + Debug>(value: &T) -> [T; 2] {
+        let json = serde_json::to_string(value).unwrap();
+        let bytes = postcard::to_allocvec(value).unwrap();
+        [
+            serde_json::from_str(&json).unwrap_or_else(|e| panic!("{}: {}", json, e)),
+            postcard::from_bytes(&bytes).unwrap(),
+        ]
+    }
+
+    fn hash(bytes: &[u8]) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        for (h, b) in hash.iter_mut().zip(bytes) {
+            *h = *b;
+        }
+        hash
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        #[serde(with = "crate::serialization::hex_bytes")]
+        hash: [u8; 32],
+        #[serde(with = "crate::serialization::hex_bytes")]
+        blob: Vec<u8>,
+        #[serde(with = "crate::serialization::hex_bytes::vec")]
+        hashes: Vec<[u8; 32]>,
+    }
+
+    #[test]
+    fn serde_flags() {
+        fn flags_round_trip(bits: u32) -> bool {
+            let flags = RandomXFlag::from_bits_retain(bits);
+            round_trips(&flags).iter().all(|decoded| decoded.bits() == bits)
+        }
+        QuickCheck::new()
+            .tests(TESTS)
+            .quickcheck(flags_round_trip as fn(u32) -> bool);
+
+        let flags = RandomXFlag::FLAG_JIT | RandomXFlag::FLAG_HARD_AES;
+        assert_eq!(
+            serde_json::to_string(&flags).unwrap(),
+            r#"["FLAG_HARD_AES","FLAG_JIT"]"#
+        );
+        let flags = RandomXFlag::FLAG_JIT | RandomXFlag::from_bits_retain(0x300);
+        assert_eq!(serde_json::to_string(&flags).unwrap(), r#"["FLAG_JIT",768]"#);
+        assert_eq!(serde_json::to_string(&RandomXFlag::FLAG_DEFAULT).unwrap(), "[]");
+        assert_eq!(postcard::to_allocvec(&RandomXFlag::FLAG_JIT).unwrap(), [8]);
+
+        let parse = |json: &str| serde_json::from_str::<RandomXFlag>(json).map(|flags| flags.bits());
+        assert_eq!(parse(r#"["FLAG_ARGON2", 1]"#).unwrap(), 0b0110_0001);
+        assert_eq!(parse(r#""FLAG_FULL_MEM""#).unwrap(), 0b0100);
+        assert_eq!(parse("10").unwrap(), 10);
+        assert!(parse(r#"["FLAG_TURBO"]"#).is_err());
+        assert!(parse("4294967296").is_err());
+    }
+
+    #[test]
+    fn serde_hashes() {
+        #[allow(clippy::needless_pass_by_value)] // This is required by the `QuickCheck` fuzzing framework
+        fn hashes_round_trip(hash_bytes: Vec<u8>, blob: Vec<u8>, hashes: Vec<Vec<u8>>) -> bool {
+            let record = Record {
+                hash: hash(&hash_bytes),
+                blob,
+                hashes: hashes.iter().map(|bytes| hash(bytes)).collect(),
+            };
+            round_trips(&record).iter().all(|decoded| *decoded == record)
+        }
+        QuickCheck::new()
+            .tests(TESTS)
+            .quickcheck(hashes_round_trip as fn(Vec<u8>, Vec<u8>, Vec<Vec<u8>>) -> bool);
+
+        let record = Record {
+            hash: [0xab; 32],
+            blob: vec![1, 2],
+            hashes: vec![[0; 32]],
+        };
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["hash"], "ab".repeat(32));
+        assert_eq!(json["blob"], "0102");
+        assert_eq!(json["hashes"][0], "00".repeat(32));
+        let bytes = postcard::to_allocvec(&record).unwrap();
+        assert_eq!(&bytes[..33], &[[32].as_ref(), &[0xab; 32]].concat()[..]);
+
+        let short = serde_json::json!({ "hash": "abcd", "blob": "", "hashes": [] });
+        assert!(serde_json::from_value::<Record>(short).is_err());
+        let odd = serde_json::json!({ "hash": "ab".repeat(32), "blob": "abc", "hashes": [] });
+        assert!(serde_json::from_value::<Record>(odd).is_err());
+    }
+
+    #[test]
+    fn serde_configs() {
+        fn frequencies_round_trip(values: Vec<u32>) -> bool {
+            let mut config = RandomXConfig::MONERO;
+            let names: Vec<_> = config
+                .parameters()
+                .iter()
+                .map(|(name, _)| *name)
+                .filter(|name| name.starts_with("RANDOMX_FREQ_"))
+                .collect();
+            for (name, value) in names.iter().zip(values) {
+                *config.parameter_mut(name).unwrap() = value;
+            }
+            round_trips(&config.frequencies)
+                .iter()
+                .all(|decoded| *decoded == config.frequencies)
+        }
+        QuickCheck::new()
+            .tests(TESTS)
+            .quickcheck(frequencies_round_trip as fn(Vec<u32>) -> bool);
+
+        for config in [
+            *RandomXConfig::active(),
+            RandomXConfig::MONERO,
+            RandomXConfig::WOWNERO,
+            RandomXConfig::ARQMA,
+        ] {
+            assert_eq!(round_trips(&config), [config, config]);
+        }
+        let json = serde_json::to_value(RandomXConfig::MONERO).unwrap();
+        assert_eq!(json["argon_salt"], hex::encode(b"RandomX\x03"));
+        assert_eq!(json["parameters"]["RANDOMX_ARGON_MEMORY"], 262_144);
+        let mut changed = json.clone();
+        changed["parameters"]["RANDOMX_PROGRAM_COUNT"] = 4.into();
+        assert!(serde_json::from_value::<RandomXConfig>(changed).is_err());
+        let mut unknown = json;
+        unknown["name"] = "mycoin".into();
+        assert!(serde_json::from_value::<RandomXConfig>(unknown).is_err());
+        assert_eq!(round_trips(&InstructionFrequencies::DEFAULT), [
+            InstructionFrequencies::DEFAULT,
+            InstructionFrequencies::DEFAULT
+        ]);
+
+        for variant in [Variant::Default, Variant::Monero, Variant::Wow, Variant::Arq] {
+            assert_eq!(round_trips(&variant), [variant, variant]);
+        }
+        assert_eq!(serde_json::to_string(&Variant::Wow).unwrap(), r#""wow""#);
+        for policy in [StaleKeyPolicy::Error, StaleKeyPolicy::Rebuild] {
+            assert_eq!(round_trips(&policy), [policy, policy]);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn serde_worker_config() {
+        use crate::worker::{CoreAffinity, WorkerConfig};
+
+        fn worker_config_round_trip(affinity: u8, cores: Vec<usize>, nice: Option<i32>) -> bool {
+            let affinity = match affinity % 3 {
+                0 => CoreAffinity::Unpinned,
+                1 => CoreAffinity::Auto,
+                _ => CoreAffinity::Cores(cores),
+            };
+            let mut config = WorkerConfig::default().with_affinity(affinity).with_idle_hook(|| false);
+            if let Some(nice) = nice {
+                config = config.with_nice(nice);
+            }
+            round_trips(&config)
+                .iter()
+                .all(|decoded| decoded.affinity() == config.affinity() && decoded.nice() == config.nice())
+        }
+        QuickCheck::new()
+            .tests(TESTS)
+            .quickcheck(worker_config_round_trip as fn(u8, Vec<usize>, Option<i32>) -> bool);
+        let config: WorkerConfig = serde_json::from_str(r#"{"nice":10}"#).unwrap();
+        assert_eq!(config.affinity(), &CoreAffinity::Unpinned);
+        assert_eq!(config.nice(), Some(10));
+    }
+
+    #[cfg(feature = "monero")]
+    #[test]
+    fn serde_monero_blocks() {
+        use crate::monero::{Block, BlockHeader};
+
+        #[allow(clippy::needless_pass_by_value)] // This is required by the `QuickCheck` fuzzing framework
+        fn block_round_trip(
+            versions: (u8, u8),
+            timestamp: u64,
+            prev_id: Vec<u8>,
+            nonce: u32,
+            txs: Vec<Vec<u8>>,
+        ) -> bool {
+            let block = Block {
+                header: BlockHeader {
+                    major_version: versions.0,
+                    minor_version: versions.1,
+                    timestamp,
+                    prev_id: hash(&prev_id),
+                    nonce,
+                },
+                miner_tx_hash: hash(&timestamp.to_le_bytes()),
+                tx_hashes: txs.iter().map(|tx| hash(tx)).collect(),
+            };
+            round_trips(&block).iter().all(|decoded| *decoded == block)
+        }
+        QuickCheck::new()
+            .tests(TESTS)
+            .quickcheck(block_round_trip as fn((u8, u8), u64, Vec<u8>, u32, Vec<Vec<u8>>) -> bool);
+    }
+
+    #[cfg(feature = "merge-mining")]
+    #[test]
+    fn serde_merge_mining_proofs() {
+        use crate::{
+            merge_mining::{MergeMiningProof, MergeMiningTag},
+            monero::BlockHeader,
+        };
+
+        #[allow(clippy::needless_pass_by_value)] // This is required by the `QuickCheck` fuzzing framework
+        fn proof_round_trip(depth: u64, seed_hash: Vec<u8>, tx_count: u64, branch: Vec<Vec<u8>>, tx: Vec<u8>) -> bool {
+            let tag = MergeMiningTag {
+                depth,
+                merkle_root: hash(&tx),
+            };
+            let proof = MergeMiningProof {
+                header: BlockHeader {
+                    major_version: 16,
+                    minor_version: 16,
+                    timestamp: depth,
+                    prev_id: hash(&seed_hash),
+                    nonce: 0,
+                },
+                seed_hash: hash(&seed_hash),
+                tx_count,
+                merkle_root: tag.merkle_root,
+                coinbase_branch: branch.iter().map(|bytes| hash(bytes)).collect(),
+                coinbase_tx: tx,
+            };
+            round_trips(&tag).iter().all(|decoded| *decoded == tag) &&
+                round_trips(&proof).iter().all(|decoded| *decoded == proof)
+        }
+        QuickCheck::new()
+            .tests(TESTS)
+            .quickcheck(proof_round_trip as fn(u64, Vec<u8>, u64, Vec<Vec<u8>>, Vec<u8>) -> bool);
+    }
+}
@@ -0,0 +1,544 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Parsing and serialising Monero-style stratum messages.
+//!
+//! Pools and miners exchange JSON-RPC messages, one per line: the miner sends `login`, `submit` and `keepalived`
+//! requests, and the pool answers them and pushes new `job`s. This module only converts between [`Message`]s and
+//! JSON text; reading and writing the lines is up to the caller. A [`Job`] turns into the [`NonceSearch`] a miner
+//! runs with a [`RandomXVM`](crate::RandomXVM) keyed with its seed hash.
+
+use std::convert::TryInto;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+    difficulty::{meets_difficulty, target_to_difficulty},
+    RandomXError,
+};
+
+/// Offset of the 4-byte nonce in a Monero hashing blob.
+pub const NONCE_OFFSET: usize = 39;
+
+/// Errors from parsing stratum messages or the jobs they carry.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum StratumError {
+    #[error("Malformed stratum message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Malformed stratum message: {0}")]
+    Malformed(&'static str),
+    #[error("Unknown stratum method: {0}")]
+    UnknownMethod(String),
+    #[error(transparent)]
+    RandomX(#[from] RandomXError),
+}
+
+/// The parameters of a `login` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Login {
+    /// Usually the wallet address to pay.
+    pub login: String,
+    pub pass: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<String>,
+    /// The algorithms the miner supports, such as `rx/0`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub algo: Vec<String>,
+}
+
+/// A job pushed by the pool, or returned for a login.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Job {
+    pub job_id: String,
+    /// The hashing blob, with the nonce at [`NONCE_OFFSET`].
+    #[serde(with = "hex")]
+    pub blob: Vec<u8>,
+    /// The share target, 4 or 8 bytes little-endian, see [`target_to_difficulty`].
+    #[serde(with = "hex")]
+    pub target: Vec<u8>,
+    /// The RandomX key for the blob.
+    #[serde(with = "hex")]
+    pub seed_hash: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algo: Option<String>,
+    /// The worker id the job was issued to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+/// The parameters of a `submit` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Submit {
+    /// The worker id returned by the login.
+    pub id: String,
+    pub job_id: String,
+    /// The nonce bytes as written into the blob.
+    #[serde(with = "hex")]
+    pub nonce: [u8; 4],
+    /// The RandomX hash of the blob with the nonce.
+    #[serde(with = "hex")]
+    pub result: [u8; 32],
+}
+
+impl Submit {
+    /// Returns the submitted nonce.
+    pub fn nonce(&self) -> u32 {
+        u32::from_le_bytes(self.nonce)
+    }
+}
+
+/// The result of a successful `login`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginResult {
+    /// The worker id to send with `submit` and `keepalived`.
+    pub id: String,
+    pub job: Job,
+    pub status: String,
+}
+
+/// The result of a successful request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Response {
+    Login(LoginResult),
+    /// The result of `submit` (`OK`) or `keepalived` (`KEEPALIVED`).
+    Status {
+        status: String,
+    },
+}
+
+/// An error returned by the pool for a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+/// A stratum message, in either direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Login {
+        id: u64,
+        params: Login,
+    },
+    Submit {
+        id: u64,
+        params: Submit,
+    },
+    /// Keeps the connection of worker `worker_id` open while it has no share to submit.
+    Keepalived {
+        id: u64,
+        worker_id: String,
+    },
+    /// A new job from the pool, replacing the previous one.
+    Job(Job),
+    /// The pool's answer to request `id`.
+    Response {
+        id: u64,
+        result: Result<Response, RpcError>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct WorkerId {
+    id: String,
+}
+
+// The JSON-RPC fields of every message; which of them are set decides the kind of message.
+#[derive(Default, Serialize, Deserialize)]
+struct Envelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(default)]
+    jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    params: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    // Pools send `"error": null` with results, which deserializes to `None` as well.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+impl Message {
+    /// Parses one line of stratum JSON.
+    pub fn from_json(json: &str) -> Result<Message, StratumError> {
+        let envelope: Envelope = serde_json::from_str(json)?;
+        match (envelope.method.as_deref(), envelope.id) {
+            (Some("job"), _) => Ok(Message::Job(params(envelope.params)?)),
+            (Some("login"), Some(id)) => Ok(Message::Login {
+                id,
+                params: params(envelope.params)?,
+            }),
+            (Some("submit"), Some(id)) => Ok(Message::Submit {
+                id,
+                params: params(envelope.params)?,
+            }),
+            (Some("keepalived"), Some(id)) => Ok(Message::Keepalived {
+                id,
+                worker_id: params::<WorkerId>(envelope.params)?.id,
+            }),
+            (Some("login" | "submit" | "keepalived"), None) => Err(StratumError::Malformed("request without an id")),
+            (Some(method), _) => Err(StratumError::UnknownMethod(method.to_string())),
+            (None, Some(id)) => {
+                let result = match (envelope.error, envelope.result) {
+                    (Some(error), _) => Err(serde_json::from_value(error)?),
+                    (None, Some(result)) => Ok(serde_json::from_value(result)?),
+                    (None, None) => return Err(StratumError::Malformed("response without a result or an error")),
+                };
+                Ok(Message::Response { id, result })
+            },
+            (None, None) => Err(StratumError::Malformed("neither a request nor a response")),
+        }
+    }
+
+    /// Serialises the message to one line of stratum JSON, without the line terminator.
+    pub fn to_json(&self) -> Result<String, StratumError> {
+        let envelope = match self {
+            Message::Login { id, params } => request(*id, "login", params)?,
+            Message::Submit { id, params } => request(*id, "submit", params)?,
+            Message::Keepalived { id, worker_id } => request(*id, "keepalived", &WorkerId { id: worker_id.clone() })?,
+            Message::Job(job) => Envelope {
+                method: Some("job".to_string()),
+                params: Some(serde_json::to_value(job)?),
+                ..Envelope::default()
+            },
+            Message::Response { id, result: Ok(result) } => Envelope {
+                id: Some(*id),
+                result: Some(serde_json::to_value(result)?),
+                ..Envelope::default()
+            },
+            Message::Response { id, result: Err(error) } => Envelope {
+                id: Some(*id),
+                error: Some(serde_json::to_value(error)?),
+                ..Envelope::default()
+            },
+        };
+        Ok(serde_json::to_string(&Envelope {
+            jsonrpc: "2.0".to_string(),
+            ..envelope
+        })?)
+    }
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Option<Value>) -> Result<T, StratumError> {
+    let params = params.ok_or(StratumError::Malformed("request without params"))?;
+    Ok(serde_json::from_value(params)?)
+}
+
+fn request<T: Serialize>(id: u64, method: &str, params: &T) -> Result<Envelope, StratumError> {
+    Ok(Envelope {
+        id: Some(id),
+        method: Some(method.to_string()),
+        params: Some(serde_json::to_value(params)?),
+        ..Envelope::default()
+    })
+}
+
+/// What a miner needs to search nonces for a [`Job`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NonceSearch {
+    pub job_id: String,
+    /// The hashing blob; [`NonceSearch::input`] writes nonces into it at [`NONCE_OFFSET`].
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes"))]
+    pub blob: Vec<u8>,
+    /// The key of the cache or dataset to hash with.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serialization::hex_bytes"))]
+    pub seed_hash: [u8; 32],
+    pub height: Option<u64>,
+    /// The share difficulty the pool asks for.
+    pub difficulty: u64,
+}
+
+impl Job {
+    /// Returns the parameters for searching nonces for this job, after checking its blob, target and seed hash.
+    pub fn nonce_search(&self) -> Result<NonceSearch, StratumError> {
+        if self.blob.len() < NONCE_OFFSET + 4 {
+            return Err(RandomXError::InvalidParameter {
+                name: "blob",
+                reason: "is too short to hold a nonce",
+            }
+            .into());
+        }
+        let seed_hash = self
+            .seed_hash
+            .as_slice()
+            .try_into()
+            .map_err(|_| RandomXError::InvalidParameter {
+                name: "seed_hash",
+                reason: "must be 32 bytes",
+            })?;
+        Ok(NonceSearch {
+            job_id: self.job_id.clone(),
+            blob: self.blob.clone(),
+            seed_hash,
+            height: self.height,
+            difficulty: target_to_difficulty(&self.target)?,
+        })
+    }
+}
+
+impl NonceSearch {
+    /// Returns the blob with `nonce` written into it, the input to hash.
+    pub fn input(&self, nonce: u32) -> Vec<u8> {
+        let mut input = self.blob.clone();
+        input[NONCE_OFFSET..NONCE_OFFSET + 4].copy_from_slice(&nonce.to_le_bytes());
+        input
+    }
+
+    /// Returns whether `hash` meets the share difficulty. A hash that is not 32 bytes long never does.
+    pub fn is_share(&self, hash: &[u8]) -> bool {
+        let hash: Result<&[u8; 32], _> = hash.try_into();
+        matches!(hash, Ok(hash) if meets_difficulty(hash, u128::from(self.difficulty)))
+    }
+
+    /// Returns the `submit` parameters for worker `worker_id` finding `hash` with `nonce`.
+    pub fn submit(&self, worker_id: &str, nonce: u32, hash: &[u8]) -> Result<Submit, StratumError> {
+        Ok(Submit {
+            id: worker_id.to_string(),
+            job_id: self.job_id.clone(),
+            nonce: nonce.to_le_bytes(),
+            result: hash.try_into().map_err(|_| RandomXError::InvalidParameter {
+                name: "hash",
+                reason: "must be 32 bytes",
+            })?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Lines, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use crate::{
+        stratum::{Job, Login, LoginResult, Message, Response, RpcError, StratumError},
+        RandomXCache,
+        RandomXFlag,
+        RandomXVM,
+    };
+
+    const WORKER_ID: &str = "479e2a4a";
+
+    fn job(job_id: &str) -> Job {
+        Job {
+            job_id: job_id.to_string(),
+            blob: vec![0x0e; 76],
+            // Difficulty 2, so that about every other nonce finds a share.
+            target: vec![0xff, 0xff, 0xff, 0x7f],
+            seed_hash: vec![0x5e; 32],
+            height: Some(3_000_000),
+            algo: Some("rx/0".to_string()),
+            id: Some(WORKER_ID.to_string()),
+        }
+    }
+
+    fn send(stream: &mut TcpStream, message: &Message) {
+        writeln!(stream, "{}", message.to_json().unwrap()).unwrap();
+    }
+
+    fn receive(lines: &mut Lines<BufReader<TcpStream>>) -> Message {
+        Message::from_json(&lines.next().unwrap().unwrap()).unwrap()
+    }
+
+    // Serves one miner: hands out a job at login, checks submitted shares by hashing them again and pushes a new job
+    // after each keepalive.
+    fn pool(listener: &TcpListener) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        let search = job("1").nonce_search().unwrap();
+        let flags = RandomXFlag::get_recommended_flags();
+        let cache = RandomXCache::new(flags, &search.seed_hash).unwrap();
+        let vm = RandomXVM::new(flags, Some(cache), None).unwrap();
+        for line in lines {
+            let replies = match Message::from_json(&line.unwrap()).unwrap() {
+                Message::Login { id, .. } => vec![Message::Response {
+                    id,
+                    result: Ok(Response::Login(LoginResult {
+                        id: WORKER_ID.to_string(),
+                        job: job("1"),
+                        status: "OK".to_string(),
+                    })),
+                }],
+                Message::Submit { id, params } => {
+                    let hash = vm.calculate_hash(&search.input(params.nonce())).unwrap();
+                    let result = if params.job_id == search.job_id && hash == params.result && search.is_share(&hash) {
+                        Ok(Response::Status {
+                            status: "OK".to_string(),
+                        })
+                    } else {
+                        Err(RpcError {
+                            code: -1,
+                            message: "Invalid share".to_string(),
+                        })
+                    };
+                    vec![Message::Response { id, result }]
+                },
+                Message::Keepalived { id, .. } => vec![
+                    Message::Response {
+                        id,
+                        result: Ok(Response::Status {
+                            status: "KEEPALIVED".to_string(),
+                        }),
+                    },
+                    Message::Job(job("2")),
+                ],
+                message => panic!("unexpected message {:?}", message),
+            };
+            for reply in &replies {
+                send(&mut stream, reply);
+            }
+        }
+    }
+
+    #[test]
+    fn stratum_miner_against_local_pool() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let pool = thread::spawn(move || pool(&listener));
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+
+        send(&mut stream, &Message::Login {
+            id: 1,
+            params: Login {
+                login: "wallet".to_string(),
+                pass: "x".to_string(),
+                agent: Some("randomx-rs".to_string()),
+                algo: vec!["rx/0".to_string()],
+            },
+        });
+        let login = match receive(&mut lines) {
+            Message::Response {
+                id: 1,
+                result: Ok(Response::Login(login)),
+            } => login,
+            message => panic!("unexpected message {:?}", message),
+        };
+        let search = login.job.nonce_search().unwrap();
+        assert_eq!(search.difficulty, 2);
+
+        let flags = RandomXFlag::get_recommended_flags();
+        let cache = RandomXCache::new(flags, &search.seed_hash).unwrap();
+        let vm = RandomXVM::new(flags, Some(cache), None).unwrap();
+        let (nonce, hash) = (0..)
+            .find_map(|nonce| {
+                let hash = vm.calculate_hash(&search.input(nonce)).unwrap();
+                search.is_share(&hash).then_some((nonce, hash))
+            })
+            .unwrap();
+        let submit = search.submit(&login.id, nonce, &hash).unwrap();
+        send(&mut stream, &Message::Submit { id: 2, params: submit });
+        assert_eq!(receive(&mut lines), Message::Response {
+            id: 2,
+            result: Ok(Response::Status {
+                status: "OK".to_string()
+            }),
+        });
+
+        // The same hash does not match another nonce.
+        let submit = search.submit(&login.id, nonce + 1, &hash).unwrap();
+        send(&mut stream, &Message::Submit { id: 3, params: submit });
+        assert!(matches!(receive(&mut lines), Message::Response {
+            id: 3,
+            result: Err(_)
+        }));
+
+        send(&mut stream, &Message::Keepalived {
+            id: 4,
+            worker_id: login.id,
+        });
+        assert_eq!(receive(&mut lines), Message::Response {
+            id: 4,
+            result: Ok(Response::Status {
+                status: "KEEPALIVED".to_string()
+            }),
+        });
+        assert_eq!(receive(&mut lines), Message::Job(job("2")));
+
+        drop(stream);
+        drop(lines);
+        pool.join().unwrap();
+    }
+
+    #[test]
+    fn stratum_parses_pool_messages() {
+        let login = format!(
+            r#"{{"id":1,"jsonrpc":"2.0","error":null,"result":{{"id":"{}","job":{{"blob":"{}","job_id":"285920","target":"b88d0600","algo":"rx/0","height":3000000,"seed_hash":"{}"}},"extensions":["algo","keepalive"],"status":"OK"}}}}"#,
+            WORKER_ID,
+            "0e".repeat(76),
+            "5e".repeat(32)
+        );
+        let message = Message::from_json(&login).unwrap();
+        let job = match &message {
+            Message::Response {
+                id: 1,
+                result: Ok(Response::Login(login)),
+            } => login.job.clone(),
+            message => panic!("unexpected message {:?}", message),
+        };
+        assert_eq!(job.nonce_search().unwrap().difficulty, 10000);
+        assert_eq!(Message::from_json(&message.to_json().unwrap()).unwrap(), message);
+
+        let error = r#"{"id":2,"jsonrpc":"2.0","error":{"code":-1,"message":"Low difficulty share"}}"#;
+        assert_eq!(Message::from_json(error).unwrap(), Message::Response {
+            id: 2,
+            result: Err(RpcError {
+                code: -1,
+                message: "Low difficulty share".to_string(),
+            }),
+        });
+
+        let submit = format!(
+            r#"{{"id":3,"jsonrpc":"2.0","method":"submit","params":{{"id":"{}","job_id":"285920","nonce":"deadbeef","result":"{}"}}}}"#,
+            WORKER_ID,
+            "00".repeat(32)
+        );
+        match Message::from_json(&submit).unwrap() {
+            Message::Submit { id: 3, params } => assert_eq!(params.nonce(), 0xefbe_adde),
+            message => panic!("unexpected message {:?}", message),
+        }
+
+        assert!(matches!(
+            Message::from_json(r#"{"id":4,"jsonrpc":"2.0","method":"getjob","params":{}}"#),
+            Err(StratumError::UnknownMethod(method)) if method == "getjob"
+        ));
+        assert!(matches!(
+            Message::from_json(r#"{"jsonrpc":"2.0","method":"submit","params":{}}"#),
+            Err(StratumError::Malformed(_))
+        ));
+        let mut short = job;
+        short.blob.truncate(42);
+        assert!(short.nonce_search().is_err());
+    }
+}
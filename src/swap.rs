@@ -0,0 +1,310 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Switching running VMs to new key material at a seed change.
+//!
+//! Re-initializing a cache in place while VMs hash with it (or with a dataset built from it) leaves them with stale
+//! state. Instead, a [`Swappable`] handle publishes a new cache or dataset, built on a background thread while the VMs
+//! keep hashing with the old one. Each [`SwapVm`] checks the handle before it hashes and switches to the published
+//! object with `reinit_cache`/`reinit_dataset`; the old object is freed when the last VM has switched away from it.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+        Mutex,
+        MutexGuard,
+        PoisonError,
+    },
+    thread,
+};
+
+use crate::{worker::WorkerConfig, RandomXCache, RandomXDataset, RandomXError, RandomXFlag, RandomXVM};
+
+/// A cache or dataset that a [`SwapVm`] can hash with.
+pub trait KeyMaterial: Clone + Send + 'static {
+    /// Creates a VM that hashes with `self`.
+    fn create_vm(self, flags: RandomXFlag) -> Result<RandomXVM, RandomXError>;
+
+    /// Switches `vm` to `self`.
+    fn set_on(self, vm: &mut RandomXVM) -> Result<(), RandomXError>;
+}
+
+impl KeyMaterial for RandomXCache {
+    fn create_vm(self, flags: RandomXFlag) -> Result<RandomXVM, RandomXError> {
+        RandomXVM::new(flags & !RandomXFlag::FLAG_FULL_MEM, Some(self), None)
+    }
+
+    fn set_on(self, vm: &mut RandomXVM) -> Result<(), RandomXError> {
+        vm.reinit_cache(self)
+    }
+}
+
+impl KeyMaterial for RandomXDataset {
+    fn create_vm(self, flags: RandomXFlag) -> Result<RandomXVM, RandomXError> {
+        RandomXVM::new(flags | RandomXFlag::FLAG_FULL_MEM, None, Some(self))
+    }
+
+    fn set_on(self, vm: &mut RandomXVM) -> Result<(), RandomXError> {
+        vm.reinit_dataset(self)
+    }
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    // Bumped under the `current` lock, so that a reader that sees a new version also gets the new object.
+    version: AtomicUsize,
+    current: Mutex<T>,
+}
+
+/// A shared handle to the current cache or dataset, which can be replaced while VMs hash with the previous one.
+///
+/// Clones refer to the same handle.
+#[derive(Debug)]
+pub struct Swappable<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// A [`Swappable`] cache, for light-mode VMs.
+pub type SwappableCache = Swappable<RandomXCache>;
+/// A [`Swappable`] dataset, for fast-mode VMs.
+pub type SwappableDataset = Swappable<RandomXDataset>;
+
+impl<T> Clone for Swappable<T> {
+    fn clone(&self) -> Self {
+        Swappable {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T: KeyMaterial> Swappable<T> {
+    /// Creates a handle that publishes `value`.
+    pub fn new(value: T) -> Swappable<T> {
+        Swappable {
+            shared: Arc::new(Shared {
+                version: AtomicUsize::new(0),
+                current: Mutex::new(value),
+            }),
+        }
+    }
+
+    /// Returns the currently published object.
+    pub fn load(&self) -> T {
+        self.lock().clone()
+    }
+
+    /// Returns the number of times an object was published after the first one.
+    pub fn version(&self) -> usize {
+        self.shared.version.load(Ordering::Acquire)
+    }
+
+    /// Publishes `value`. VMs switch to it at their next hash; the previous object is dropped by the handle.
+    pub fn publish(&self, value: T) {
+        let mut current = self.lock();
+        *current = value;
+        self.shared.version.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Builds a new object with `build` on a background thread configured by `workers` and publishes it, unless
+    /// `build` fails. The thread waits while the idle hook asks workers to pause before it starts building. VMs keep
+    /// hashing with the current object in the meantime.
+    pub fn swap<F>(
+        &self,
+        workers: &WorkerConfig,
+        build: F,
+    ) -> Result<thread::JoinHandle<Result<(), RandomXError>>, RandomXError>
+    where F: FnOnce() -> Result<T, RandomXError> + Send + 'static {
+        let swappable = self.clone();
+        let config = workers.clone();
+        workers.spawn_named("randomx-swap".to_string(), 0, move || {
+            config.wait_while_idle();
+            swappable.publish(build()?);
+            Ok(())
+        })
+    }
+
+    fn load_versioned(&self) -> (T, usize) {
+        let current = self.lock();
+        (current.clone(), self.version())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, T> {
+        // The object is replaced in a single assignment, so a panic elsewhere cannot leave it inconsistent.
+        self.shared.current.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A VM that follows a [`Swappable`] cache or dataset, switching to a newly published object before its next hash.
+#[derive(Debug)]
+pub struct SwapVm<T> {
+    vm: RandomXVM,
+    source: Swappable<T>,
+    version: usize,
+}
+
+impl<T: KeyMaterial> SwapVm<T> {
+    /// Creates a VM with `flags` for the object currently published by `source`. FLAG_FULL_MEM is set for datasets
+    /// and cleared for caches.
+    pub fn new(flags: RandomXFlag, source: &Swappable<T>) -> Result<SwapVm<T>, RandomXError> {
+        let (value, version) = source.load_versioned();
+        Ok(SwapVm {
+            vm: value.create_vm(flags)?,
+            source: source.clone(),
+            version,
+        })
+    }
+
+    /// Switches the VM to the published object if it changed since the last check, and returns whether it did.
+    /// The hashing functions call this first; workers can also call it at other safe points.
+    pub fn refresh(&mut self) -> Result<bool, RandomXError> {
+        if self.source.version() == self.version {
+            return Ok(false);
+        }
+        let (value, version) = self.source.load_versioned();
+        value.set_on(&mut self.vm)?;
+        self.version = version;
+        Ok(true)
+    }
+
+    /// Calculates the hash of `input` with the published object, see [`RandomXVM::calculate_hash`].
+    pub fn calculate_hash(&mut self, input: &[u8]) -> Result<Vec<u8>, RandomXError> {
+        self.refresh()?;
+        self.vm.calculate_hash(input)
+    }
+
+    /// Calculates the hashes of `input` with the published object, see [`RandomXVM::calculate_hash_set`].
+    pub fn calculate_hash_set(&mut self, input: &[&[u8]]) -> Result<Vec<Vec<u8>>, RandomXError> {
+        self.refresh()?;
+        self.vm.calculate_hash_set(input)
+    }
+
+    /// Returns the version of the handle the VM last switched to, see [`Swappable::version`].
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Returns the underlying VM, which is not refreshed.
+    pub fn vm(&self) -> &RandomXVM {
+        &self.vm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{
+        swap::{SwapVm, SwappableCache, SwappableDataset},
+        tests::datasets_disabled,
+        worker::WorkerConfig,
+        RandomXCache,
+        RandomXDataset,
+        RandomXError,
+        RandomXFlag,
+    };
+
+    #[test]
+    fn swap_vm_follows_published_cache() {
+        let flags = RandomXFlag::get_recommended_flags();
+        let input = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+        let old = RandomXCache::new(flags, b"test key 000").unwrap();
+        let old_inner = Arc::downgrade(&old.inner);
+        let swappable = SwappableCache::new(old);
+        let mut vm = SwapVm::new(flags, &swappable).unwrap();
+        assert_eq!(
+            hex::encode(vm.calculate_hash(input).unwrap()),
+            "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8"
+        );
+
+        // The build thread polls the idle hook of the worker configuration before it starts.
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let workers = WorkerConfig::default().with_idle_hook(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            false
+        });
+        let handle = swappable
+            .swap(&workers, move || RandomXCache::new(flags, b"test key 001"))
+            .unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(polls.load(Ordering::SeqCst), 1);
+        assert_eq!(swappable.version(), 1);
+        // The VM still holds the old cache until its next hash.
+        assert!(old_inner.upgrade().is_some());
+        assert_eq!(
+            hex::encode(vm.calculate_hash(input).unwrap()),
+            "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc"
+        );
+        assert_eq!(vm.version(), 1);
+        assert!(old_inner.upgrade().is_none());
+        assert!(!vm.refresh().unwrap());
+
+        let failed = swappable.swap(&workers, move || RandomXCache::new(flags, b"")).unwrap();
+        assert!(matches!(failed.join().unwrap(), Err(RandomXError::EmptyKey)));
+        assert_eq!(swappable.version(), 1);
+    }
+
+    #[test]
+    fn swap_vm_follows_published_dataset() {
+        if datasets_disabled() {
+            return;
+        }
+        let flags = RandomXFlag::get_recommended_flags();
+        let input = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+        let cache = RandomXCache::new(flags, b"test key 000").unwrap();
+        let old = RandomXDataset::new(flags, cache, 0).unwrap();
+        let old_inner = Arc::downgrade(&old.inner);
+        let swappable = SwappableDataset::new(old);
+        let mut first = SwapVm::new(flags, &swappable).unwrap();
+        let mut second = SwapVm::new(flags, &swappable).unwrap();
+        assert_eq!(
+            hex::encode(first.calculate_hash(input).unwrap()),
+            "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8"
+        );
+
+        let handle = swappable
+            .swap(&WorkerConfig::default(), move || {
+                RandomXDataset::new(flags, RandomXCache::new(flags, b"test key 001")?, 0)
+            })
+            .unwrap();
+        handle.join().unwrap().unwrap();
+        assert_eq!(swappable.version(), 1);
+        // `reinit_dataset` switches the first VM at its next hash.
+        assert_eq!(
+            hex::encode(first.calculate_hash(input).unwrap()),
+            "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc"
+        );
+        assert_eq!(first.version(), 1);
+        // The second VM has not hashed since the swap, so it still holds the old dataset.
+        assert_eq!(second.version(), 0);
+        assert!(old_inner.upgrade().is_some());
+        assert_eq!(
+            hex::encode(second.calculate_hash(input).unwrap()),
+            "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc"
+        );
+        assert!(old_inner.upgrade().is_none());
+    }
+}
@@ -20,6 +20,12 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use alloc::vec::Vec;
+#[cfg(feature = "differential")]
+use std::sync::Mutex;
+
+#[cfg(feature = "differential")]
+use crate::pure;
 use crate::{RandomXCache, RandomXDataset, RandomXFlag, RandomXVM};
 
 /// Fuzzing:
@@ -183,7 +189,41 @@ pub fn fuzz_randomx_vm_calculate_hash_with_cache_and_dataset(data: Vec<u8>) -> b
     true
 }
 
-#[cfg(test)]
+/// Keys of the caches [`fuzz_pure_rust_matches_ffi`] hashes with; initializing a cache per input would make fuzzing
+/// too slow.
+#[cfg(feature = "differential")]
+const DIFFERENTIAL_KEYS: [&[u8]; 2] = [b"test key 000", b"test key 001"];
+
+/// Differential fuzzing, the first byte of `data` selects the key and the rest is hashed by both backends:
+/// - `pub fn randomx_calculate_hash`
+/// - `pure::Vm::calculate_hash`
+///   Secondary:
+/// - `pub fn randomx_create_vm`
+/// - `pub fn randomx_destroy_vm`
+#[cfg(feature = "differential")]
+#[allow(clippy::needless_pass_by_value)] // This is required by the `QuickCheck` fuzzing framework
+pub fn fuzz_pure_rust_matches_ffi(data: Vec<u8>) -> bool {
+    static CACHES: [Mutex<Option<(RandomXCache, pure::Cache)>>; 2] = [Mutex::new(None), Mutex::new(None)];
+
+    let (key_index, input) = match data.split_first() {
+        Some((&first, input)) if !input.is_empty() => (usize::from(first) % DIFFERENTIAL_KEYS.len(), input),
+        _ => return true,
+    };
+    let mut caches = CACHES[key_index].lock().unwrap();
+    let (cache, pure_cache) = caches.get_or_insert_with(|| {
+        let key = DIFFERENTIAL_KEYS[key_index];
+        (
+            RandomXCache::new(RandomXFlag::get_recommended_flags(), key).unwrap(),
+            pure::Cache::new(key).unwrap(),
+        )
+    });
+    let vm = RandomXVM::new(RandomXFlag::get_recommended_flags(), Some(cache.clone()), None).unwrap();
+    let mut pure_vm = pure::Vm::new(pure_cache).unwrap();
+    vm.calculate_hash(input).unwrap() == pure_vm.calculate_hash(input)
+}
+
+// QuickCheck does not build for wasm32-unknown-unknown
+#[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use quickcheck::QuickCheck;
 
@@ -247,4 +287,18 @@ mod tests {
             .max_tests(TESTS)
             .quickcheck(fuzz_randomx_vm_calculate_hash_with_cache_and_dataset as fn(Vec<u8>) -> bool);
     }
+
+    #[cfg(feature = "differential")]
+    #[test]
+    fn test_fuzz_pure_rust_matches_ffi() {
+        use crate::test_utils::fuzz_pure_rust_matches_ffi;
+
+        assert!(fuzz_pure_rust_matches_ffi(b"\x01sed do eiusmod tempor".to_vec()));
+        const TESTS: u64 = 3;
+        QuickCheck::new()
+            .min_tests_passed(TESTS)
+            .tests(TESTS)
+            .max_tests(TESTS)
+            .quickcheck(fuzz_pure_rust_matches_ffi as fn(Vec<u8>) -> bool);
+    }
 }
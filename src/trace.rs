@@ -0,0 +1,199 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Debug-level spans and events for the lifecycles of caches, datasets and VMs, with the `tracing` feature.
+//!
+//! Each traced operation runs in a span named after it (e.g. `RandomXDataset::init`, with the item range as fields)
+//! and ends with an event holding its duration and, if it failed, the error. Without the feature nothing is emitted
+//! and [`traced`] leaves only the operation.
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+#[cfg(feature = "tracing")]
+use crate::RandomXError;
+
+/// Evaluates `$body` in a debug span named `$name` with `$fields` (in `tracing` syntax), then emits a debug event with
+/// its duration and outcome. The body is run in a closure, so it must be the tail of the function.
+macro_rules! traced {
+    ($name:literal, { $($fields:tt)* }, $body:expr) => {{
+        #[cfg(feature = "tracing")]
+        let outcome = $crate::trace::timed(::tracing::debug_span!($name, $($fields)*), || $body);
+        #[cfg(not(feature = "tracing"))]
+        let outcome = $body;
+        outcome
+    }};
+}
+
+pub(crate) use traced;
+
+/// The result of a traced operation.
+#[cfg(feature = "tracing")]
+pub(crate) trait Outcome {
+    /// Returns the error if the operation failed.
+    fn failure(&self) -> Option<&RandomXError>;
+}
+
+#[cfg(feature = "tracing")]
+impl<T> Outcome for Result<T, RandomXError> {
+    fn failure(&self) -> Option<&RandomXError> {
+        self.as_ref().err()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Outcome for () {
+    fn failure(&self) -> Option<&RandomXError> {
+        None
+    }
+}
+
+/// Runs `operation` in `span` and reports how long it took. Does not read the clock if no subscriber wants the span.
+#[cfg(feature = "tracing")]
+pub(crate) fn timed<T: Outcome>(span: tracing::Span, operation: impl FnOnce() -> T) -> T {
+    if span.is_disabled() {
+        return operation();
+    }
+    let _entered = span.entered();
+    let start = Instant::now();
+    let outcome = operation();
+    let elapsed = start.elapsed();
+    match outcome.failure() {
+        None => tracing::debug!(?elapsed, "done"),
+        Some(error) => tracing::debug!(?elapsed, %error, "failed"),
+    }
+    outcome
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::{
+        convert::TryFrom,
+        fmt,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        subscriber::with_default,
+        Event,
+        Metadata,
+        Subscriber,
+    };
+
+    use crate::{RandomXCache, RandomXFlag, RandomXVM};
+
+    /// Records the spans and, for each event, the name of the span it happened in and its fields.
+    #[derive(Default)]
+    struct Recorder {
+        spans: Mutex<Vec<String>>,
+        current: Mutex<Vec<u64>>,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields(span.metadata().name().to_string());
+            span.record(&mut fields);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(fields.0);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let span = match self.current.lock().unwrap().last() {
+                Some(id) => self.spans.lock().unwrap()[usize::try_from(*id).unwrap() - 1].clone(),
+                None => String::new(),
+            };
+            let mut fields = Fields(span + ":");
+            event.record(&mut fields);
+            self.events.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, span: &Id) {
+            self.current.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _span: &Id) {
+            self.current.lock().unwrap().pop();
+        }
+    }
+
+    #[test]
+    fn trace_cache_and_vm_lifecycles() {
+        let recorder = Recorder::default();
+        let events = recorder.events.clone();
+        let flags = RandomXFlag::get_recommended_flags();
+        with_default(recorder, || {
+            let cache = RandomXCache::new(flags, b"test key 000").unwrap();
+            assert!(RandomXCache::new(flags, b"").is_err());
+            let vm = RandomXVM::new(flags, Some(cache), None).unwrap();
+            vm.calculate_hash_set(&[b"one", b"two"]).unwrap();
+            assert!(vm.calculate_hash_set(&[]).is_err());
+        });
+
+        let events = events.lock().unwrap();
+        let find = |prefix: &str| {
+            events
+                .iter()
+                .filter(|event| event.starts_with(prefix))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let cache_new = find("RandomXCache::new flags=");
+        assert_eq!(cache_new.len(), 2, "{:?}", events);
+        assert!(cache_new[0].contains("message=done elapsed="), "{}", cache_new[0]);
+        assert!(cache_new[1].contains("message=failed"), "{}", cache_new[1]);
+        assert!(
+            cache_new[1].contains("error=Problem with parameters supplied: key is empty"),
+            "{}",
+            cache_new[1]
+        );
+        assert_eq!(find("RandomXCache::init key_len=12:").len(), 1, "{:?}", events);
+        assert_eq!(find("RandomXVM::new").len(), 1, "{:?}", events);
+        let hashes = find("RandomXVM::calculate_hash_set inputs=");
+        assert_eq!(hashes.len(), 2, "{:?}", events);
+        assert!(
+            hashes[0].starts_with("RandomXVM::calculate_hash_set inputs=2:"),
+            "{}",
+            hashes[0]
+        );
+        assert!(hashes[1].contains("message=failed"), "{}", hashes[1]);
+    }
+}
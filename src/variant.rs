@@ -0,0 +1,147 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Selection of the RandomX build objects are created with.
+//!
+//! By default the crate links one RandomX library, built with [`RandomXConfig::active`]. With the `multi-variant`
+//! feature, `build.rs` also builds each other preset with prefixed symbols, so that a single process can hash for
+//! several RandomX-family chains. Caches, datasets and VMs remember the build they belong to, and combining objects of
+//! different builds is rejected with [`RandomXError::VariantMismatch`].
+
+use core::fmt;
+
+use crate::{
+    bindings::{Backend, DEFAULT_BACKEND},
+    RandomXConfig, RandomXError,
+};
+
+/// A RandomX parameter set that objects can be created with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+#[non_exhaustive]
+pub enum Variant {
+    /// The parameters the crate was built with, see [`RandomXConfig::active`].
+    #[default]
+    Default,
+    /// Upstream RandomX, see [`RandomXConfig::MONERO`].
+    Monero,
+    /// RandomWOW, see [`RandomXConfig::WOWNERO`].
+    Wow,
+    /// RandomARQ, see [`RandomXConfig::ARQMA`].
+    Arq,
+}
+
+impl Variant {
+    /// Returns the parameters of this variant.
+    pub fn config(self) -> &'static RandomXConfig {
+        match self {
+            Variant::Default => RandomXConfig::active(),
+            Variant::Monero => &RandomXConfig::MONERO,
+            Variant::Wow => &RandomXConfig::WOWNERO,
+            Variant::Arq => &RandomXConfig::ARQMA,
+        }
+    }
+
+    /// Returns `true` if a RandomX library for this variant is linked into the binary.
+    pub fn is_available(self) -> bool {
+        self.backend().is_ok()
+    }
+
+    pub(crate) fn backend(self) -> Result<&'static Backend, RandomXError> {
+        // A preset that matches the active parameters is served by the default build.
+        if self.config() == RandomXConfig::active() {
+            return Ok(&DEFAULT_BACKEND);
+        }
+        match self {
+            #[cfg(randomx_variant_monero)]
+            Variant::Monero => Ok(&crate::bindings::monero::BACKEND),
+            #[cfg(randomx_variant_wownero)]
+            Variant::Wow => Ok(&crate::bindings::wownero::BACKEND),
+            #[cfg(randomx_variant_arqma)]
+            Variant::Arq => Ok(&crate::bindings::arqma::BACKEND),
+            _ => Err(RandomXError::UnavailableVariant { variant: self }),
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.config().name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RandomXConfig, Variant};
+
+    #[test]
+    fn default_build_serves_matching_preset() {
+        assert!(Variant::Default.is_available());
+        assert_eq!(Variant::Default.config(), RandomXConfig::active());
+    }
+
+    #[cfg(not(any(feature = "randomx-wow", feature = "randomx-arq", feature = "multi-variant")))]
+    #[test]
+    fn only_the_monero_preset_is_built() {
+        use crate::{RandomXCache, RandomXError, RandomXFlag, RandomXVM};
+
+        assert!(Variant::Monero.is_available());
+        assert!(!Variant::Wow.is_available());
+        let flags = RandomXFlag::get_recommended_flags();
+        assert!(matches!(
+            RandomXCache::new_variant(Variant::Arq, flags, b"Key"),
+            Err(RandomXError::UnavailableVariant { variant: Variant::Arq })
+        ));
+        // Monero objects are created by the default build, so they can be combined with default ones.
+        let cache = RandomXCache::new_variant(Variant::Monero, flags, b"Key").unwrap();
+        assert!(RandomXVM::new(flags, Some(cache), None).is_ok());
+    }
+
+    #[cfg(feature = "multi-variant")]
+    #[test]
+    fn variants_are_not_mixed() {
+        use crate::{RandomXCache, RandomXError, RandomXFlag, RandomXVM};
+
+        let flags = RandomXFlag::get_recommended_flags();
+        for variant in [Variant::Monero, Variant::Wow, Variant::Arq] {
+            assert!(variant.is_available(), "{}", variant);
+        }
+        let wow = RandomXCache::new_variant(Variant::Wow, flags, b"test key 000").unwrap();
+        let monero = RandomXCache::new_variant(Variant::Monero, flags, b"test key 000").unwrap();
+
+        let mut vm = RandomXVM::new(flags, Some(monero.clone()), None).unwrap();
+        assert_eq!(
+            hex::encode(vm.calculate_hash(b"This is a test").unwrap()),
+            "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f"
+        );
+        assert!(matches!(
+            vm.reinit_cache(wow.clone()),
+            Err(RandomXError::VariantMismatch)
+        ));
+
+        let wow_vm = RandomXVM::new(flags, Some(wow), None).unwrap();
+        assert_ne!(
+            wow_vm.calculate_hash(b"This is a test").unwrap(),
+            vm.calculate_hash(b"This is a test").unwrap()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Light-mode hash verification across several keys.
+//!
+//! Verifying proofs of work from a chain means hashing with whichever key (seed) each block commits to. Building a
+//! cache takes about half a second, so the [`Verifier`] keeps the most recently used caches in a small LRU.
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{RandomXCache, RandomXError, RandomXFlag, RandomXVM};
+
+/// Verifies light-mode hashes, keeping caches for the `capacity` most recently used keys.
+#[derive(Debug)]
+pub struct Verifier {
+    flags: RandomXFlag,
+    capacity: usize,
+    // Most recently used first.
+    caches: Mutex<VecDeque<(Vec<u8>, RandomXCache)>>,
+}
+
+impl Verifier {
+    /// Creates a verifier whose caches and VMs are created with `flags`. `FLAG_FULL_MEM` is ignored.
+    pub fn new(flags: RandomXFlag, capacity: usize) -> Result<Verifier, RandomXError> {
+        if capacity == 0 {
+            return Err(RandomXError::InvalidParameter {
+                name: "capacity",
+                reason: "must be greater than zero",
+            });
+        }
+        Ok(Verifier {
+            flags: flags & !RandomXFlag::FLAG_FULL_MEM,
+            capacity,
+            caches: Mutex::new(VecDeque::with_capacity(capacity)),
+        })
+    }
+
+    /// Returns the cache for `key`, creating it (and evicting the least recently used one) if needed.
+    pub fn cache_for(&self, key: &[u8]) -> Result<RandomXCache, RandomXError> {
+        if let Some(cache) = self.lookup(key) {
+            #[cfg(feature = "metrics")]
+            crate::metrics::VERIFIER_HITS.add(1);
+            return Ok(cache);
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::VERIFIER_MISSES.add(1);
+        // Build outside the lock so that verification with other keys is not held up.
+        let cache = RandomXCache::new(self.flags, key)?;
+        let mut caches = self.lock();
+        if let Some(position) = caches.iter().position(|(k, _)| k == key) {
+            // Another thread built the same cache in the meantime.
+            return Ok(caches[position].1.clone());
+        }
+        if caches.len() == self.capacity {
+            caches.pop_back();
+        }
+        caches.push_front((key.to_vec(), cache.clone()));
+        Ok(cache)
+    }
+
+    /// Calculates the hash of `input` under `key`.
+    pub fn calculate_hash(&self, key: &[u8], input: &[u8]) -> Result<Vec<u8>, RandomXError> {
+        let cache = self.cache_for(key)?;
+        let vm = RandomXVM::new(self.flags, Some(cache), None)?;
+        vm.calculate_hash(input)
+    }
+
+    /// Returns `true` if `hash` is the hash of `input` under `key`.
+    pub fn verify(&self, key: &[u8], input: &[u8], hash: &[u8]) -> Result<bool, RandomXError> {
+        Ok(self.calculate_hash(key, input)? == hash)
+    }
+
+    /// Returns the maximum number of caches kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of caches currently kept.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if no caches are kept.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Returns `true` if a cache for `key` is kept.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.lock().iter().any(|(k, _)| k == key)
+    }
+
+    /// Drops all kept caches.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lookup(&self, key: &[u8]) -> Option<RandomXCache> {
+        let mut caches = self.lock();
+        let position = caches.iter().position(|(k, _)| k == key)?;
+        let entry = caches.remove(position)?;
+        let cache = entry.1.clone();
+        caches.push_front(entry);
+        Some(cache)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<(Vec<u8>, RandomXCache)>> {
+        // The list is always left consistent, so a panic elsewhere does not invalidate it.
+        self.caches.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{verifier::Verifier, RandomXFlag};
+
+    #[test]
+    fn verifier_light_mode_vectors_and_eviction() {
+        let flags = RandomXFlag::get_recommended_flags();
+        let verifier = Verifier::new(flags, 1).unwrap();
+        let input = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+        let hash_000 = hex::decode("c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8").unwrap();
+        let hash_001 = hex::decode("e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc").unwrap();
+
+        assert!(verifier.verify(b"test key 000", input, &hash_000).unwrap());
+        assert!(verifier.contains(b"test key 000"));
+        assert!(!verifier.verify(b"test key 000", input, &hash_001).unwrap());
+
+        assert!(verifier.verify(b"test key 001", input, &hash_001).unwrap());
+        assert!(!verifier.contains(b"test key 000"));
+        assert_eq!(verifier.len(), 1);
+
+        verifier.clear();
+        assert!(verifier.is_empty());
+        assert!(Verifier::new(flags, 0).is_err());
+    }
+}
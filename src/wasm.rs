@@ -0,0 +1,70 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Hash verification for JavaScript, e.g. a block explorer checking proofs of work in the browser.
+//!
+//! Build for `wasm32-unknown-unknown` with the `wasm` feature, which hashes with the [`pure`](crate::pure) backend in
+//! light mode, and generate the JavaScript bindings from the `.wasm` module of the `cdylib` with `wasm-bindgen`. Each
+//! cache takes 256 MiB of the module's memory.
+
+use wasm_bindgen::prelude::{wasm_bindgen, JsError};
+
+use crate::{verifier::Verifier, RandomXError, RandomXFlag};
+
+/// Number of keys whose caches are kept, enough to verify across one key change.
+const CACHED_KEYS: usize = 2;
+
+thread_local! {
+    static VERIFIER: Result<Verifier, RandomXError> =
+        Verifier::new(RandomXFlag::get_recommended_flags(), CACHED_KEYS);
+}
+
+/// Returns `true` if `hash` is the RandomX hash of `input` under `key`.
+///
+/// The caches of the two most recently used keys are kept between calls. Throws if the hash cannot be calculated, e.g.
+/// for an empty key.
+#[wasm_bindgen]
+pub fn verify(key: &[u8], input: &[u8], hash: &[u8]) -> Result<bool, JsError> {
+    VERIFIER.with(|verifier| match verifier {
+        Ok(verifier) => Ok(verifier.verify(key, input, hash)?),
+        Err(error) => Err(JsError::new(&error.to_string())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify;
+
+    // Runs natively, and under Node with `cargo test --target wasm32-unknown-unknown --features wasm`
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test::wasm_bindgen_test)]
+    fn verify_light_mode_vectors() {
+        let input = b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua";
+        let hash_000 = hex::decode("c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8").unwrap();
+        let hash_001 = hex::decode("e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc").unwrap();
+
+        assert!(matches!(verify(b"test key 000", input, &hash_000), Ok(true)));
+        assert!(matches!(verify(b"test key 000", input, &hash_001), Ok(false)));
+        assert!(matches!(verify(b"test key 001", input, &hash_001), Ok(true)));
+        assert!(matches!(verify(b"test key 000", input, &hash_000[..31]), Ok(false)));
+    }
+}
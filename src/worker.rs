@@ -0,0 +1,295 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Thread placement for the worker threads spawned by this crate.
+//!
+//! A [`WorkerConfig`] describes which cores hashing threads are pinned to, the scheduling priority they run at and
+//! an optional idle hook that is polled between units of work. Pinning is implemented with `sched_setaffinity` and
+//! priority with `setpriority` on Linux and Android; on other platforms both are accepted but have no effect.
+
+use std::{fmt, sync::Arc, thread, time::Duration};
+
+use crate::RandomXError;
+
+/// How long an idle worker sleeps before polling the idle hook again.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Which cores worker threads are pinned to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum CoreAffinity {
+    /// Leave thread placement to the OS scheduler.
+    #[default]
+    Unpinned,
+    /// Pin worker `n` to the `n`-th core the process is allowed to run on, wrapping around.
+    Auto,
+    /// Pin worker `n` to `cores[n % cores.len()]`.
+    Cores(Vec<usize>),
+}
+
+/// Callback polled by workers between units of work. While it returns `true` the worker pauses, e.g. when the host is
+/// running on battery.
+pub type IdleHook = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Configuration applied to every worker thread spawned by the crate.
+///
+/// With the `serde` feature, the idle hook is not serialised; missing fields deserialise to their defaults.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct WorkerConfig {
+    affinity: CoreAffinity,
+    nice: Option<i32>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    idle_hook: Option<IdleHook>,
+}
+
+impl fmt::Debug for WorkerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkerConfig")
+            .field("affinity", &self.affinity)
+            .field("nice", &self.nice)
+            .field("idle_hook", &self.idle_hook.is_some())
+            .finish()
+    }
+}
+
+impl WorkerConfig {
+    /// Sets the cores worker threads are pinned to.
+    pub fn with_affinity(mut self, affinity: CoreAffinity) -> Self {
+        self.affinity = affinity;
+        self
+    }
+
+    /// Sets the nice level worker threads run at. Unprivileged processes can only raise it (lower the priority).
+    pub fn with_nice(mut self, nice: i32) -> Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    /// Sets the hook polled by workers between units of work, see [`IdleHook`].
+    pub fn with_idle_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.idle_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Returns the configured core affinity.
+    pub fn affinity(&self) -> &CoreAffinity {
+        &self.affinity
+    }
+
+    /// Returns the configured nice level, if any.
+    pub fn nice(&self) -> Option<i32> {
+        self.nice
+    }
+
+    /// Returns the core worker `index` should be pinned to, or `None` if it is left unpinned.
+    pub fn core_for(&self, index: usize) -> Option<usize> {
+        match &self.affinity {
+            CoreAffinity::Unpinned => None,
+            CoreAffinity::Auto => {
+                let cores = allowed_cores();
+                if cores.is_empty() {
+                    None
+                } else {
+                    Some(cores[index % cores.len()])
+                }
+            },
+            CoreAffinity::Cores(cores) if cores.is_empty() => None,
+            CoreAffinity::Cores(cores) => Some(cores[index % cores.len()]),
+        }
+    }
+
+    /// Applies the affinity and priority settings for worker `index` to the calling thread.
+    pub fn apply(&self, index: usize) -> Result<(), RandomXError> {
+        if let Some(core) = self.core_for(index) {
+            set_current_thread_affinity(core)?;
+        }
+        if let Some(nice) = self.nice {
+            set_current_thread_nice(nice)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the idle hook asks workers to pause.
+    pub fn is_idle(&self) -> bool {
+        match &self.idle_hook {
+            Some(hook) => hook(),
+            None => false,
+        }
+    }
+
+    /// Blocks the calling thread for as long as the idle hook asks workers to pause.
+    pub fn wait_while_idle(&self) {
+        while self.is_idle() {
+            thread::sleep(IDLE_POLL_INTERVAL);
+        }
+    }
+
+    /// Spawns worker thread `index`, applying this configuration before running `f`.
+    pub fn spawn<F, T>(&self, index: usize, f: F) -> Result<thread::JoinHandle<Result<T, RandomXError>>, RandomXError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_named(format!("randomx-worker-{index}"), index, move || Ok(f()))
+    }
+
+    /// Spawns a thread called `name` as worker `index`, applying this configuration before running `f`, whose error
+    /// is returned by the join handle like one from applying the configuration.
+    pub(crate) fn spawn_named<F, T>(
+        &self,
+        name: String,
+        index: usize,
+        f: F,
+    ) -> Result<thread::JoinHandle<Result<T, RandomXError>>, RandomXError>
+    where
+        F: FnOnce() -> Result<T, RandomXError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let config = self.clone();
+        thread::Builder::new()
+            .name(name)
+            .spawn(move || {
+                config.apply(index)?;
+                f()
+            })
+            .map_err(RandomXError::from)
+    }
+}
+
+/// Returns the cores the calling process is allowed to run on.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn allowed_cores() -> Vec<usize> {
+    // SAFETY: `cpu_set_t` is a plain bit set for which all zeroes is a valid (empty) value.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    if unsafe { libc::sched_getaffinity(0, size, &mut set) } != 0 {
+        return Vec::new();
+    }
+    (0..size * 8)
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn allowed_cores() -> Vec<usize> {
+    (0..thread::available_parallelism().map_or(1, |n| n.get())).collect()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_current_thread_affinity(core: usize) -> Result<(), RandomXError> {
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    if core >= size * 8 {
+        return Err(RandomXError::InvalidParameter {
+            name: "core",
+            reason: "is out of range",
+        });
+    }
+    // SAFETY: see `allowed_cores`; `core` was bounds checked against the set size above.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::CPU_SET(core, &mut set) };
+    // A pid of 0 applies the mask to the calling thread only.
+    if unsafe { libc::sched_setaffinity(0, size, &set) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().into())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_current_thread_affinity(_core: usize) -> Result<(), RandomXError> {
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_current_thread_nice(nice: i32) -> Result<(), RandomXError> {
+    // On Linux the priority of a thread id only affects that thread, not the whole process.
+    #[allow(clippy::cast_sign_loss)]
+    let tid = unsafe { libc::gettid() } as libc::id_t;
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS as _, tid, nice) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().into())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_current_thread_nice(_nice: i32) -> Result<(), RandomXError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::worker::{allowed_cores, CoreAffinity, WorkerConfig};
+
+    #[test]
+    fn core_assignment_wraps_around() {
+        let config = WorkerConfig::default().with_affinity(CoreAffinity::Cores(vec![2, 5]));
+        assert_eq!(config.core_for(0), Some(2));
+        assert_eq!(config.core_for(1), Some(5));
+        assert_eq!(config.core_for(2), Some(2));
+        assert_eq!(WorkerConfig::default().core_for(0), None);
+        assert_eq!(
+            WorkerConfig::default()
+                .with_affinity(CoreAffinity::Cores(vec![]))
+                .core_for(0),
+            None
+        );
+    }
+
+    #[test]
+    fn auto_affinity_uses_allowed_cores() {
+        let cores = allowed_cores();
+        assert!(!cores.is_empty());
+        let config = WorkerConfig::default().with_affinity(CoreAffinity::Auto);
+        assert_eq!(config.core_for(cores.len()), Some(cores[0]));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn spawned_worker_is_pinned() {
+        let core = allowed_cores()[0];
+        let config = WorkerConfig::default()
+            .with_affinity(CoreAffinity::Cores(vec![core]))
+            .with_nice(1);
+        let handle = config.spawn(0, allowed_cores).unwrap();
+        assert_eq!(handle.join().unwrap().unwrap(), vec![core]);
+    }
+
+    #[test]
+    fn idle_hook_pauses_until_cleared() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let config = WorkerConfig::default().with_idle_hook(move || counter.fetch_add(1, Ordering::SeqCst) < 1);
+        config.wait_while_idle();
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+        assert!(!WorkerConfig::default().is_idle());
+    }
+}
@@ -0,0 +1,146 @@
+# Tests for the Python bindings. Build them into the current environment with `maturin develop --release` and run
+# `pytest tests/python`.
+
+import sys
+import threading
+
+import pytest
+
+from randomx_rs import RandomXCache, RandomXDataset, RandomXError, RandomXFlag, RandomXVM
+
+# Test vectors from https://github.com/tevador/RandomX/blob/040f4500a6e79d54d84a668013a94507045e786f/src/tests/tests.cpp#L963-L985
+VECTORS = [
+    (b"test key 000", b"This is a test", "639183aae1bf4c9a35884cb46b09cad9175f04efd7684e7262a0ac1c2f0b4e3f"),
+    (b"test key 000", b"Lorem ipsum dolor sit amet", "300a0adb47603dedb42228ccb2b211104f4da45af709cd7547cd049e9489c969"),
+    (
+        b"test key 000",
+        b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua",
+        "c36d4ed4191e617309867ed66a443be4075014e2b061bcdaf9ce7b721d2b77a8",
+    ),
+    (
+        b"test key 001",
+        b"sed do eiusmod tempor incididunt ut labore et dolore magna aliqua",
+        "e9ff4503201c0c2cca26d285c93ae883f9b1d30c9eb240b820756f2d5a7905fc",
+    ),
+]
+
+FLAGS = RandomXFlag.get_recommended_flags()
+
+
+@pytest.mark.parametrize("key,data,expected", VECTORS)
+def test_light_mode_vectors(key, data, expected):
+    vm = RandomXVM(FLAGS, RandomXCache(FLAGS, key))
+    assert vm.calculate_hash(data).hex() == expected
+
+
+@pytest.mark.skipif(RandomXFlag.FLAG_FULL_MEM in RandomXFlag.disabled(), reason="fast mode is disabled")
+def test_fast_mode_vectors():
+    flags = FLAGS | RandomXFlag.FLAG_FULL_MEM
+    dataset = RandomXDataset(flags, RandomXCache(flags, b"test key 000"))
+    vm = RandomXVM(flags, dataset=dataset)
+    for key, data, expected in VECTORS[:3]:
+        assert vm.calculate_hash(data).hex() == expected
+
+
+def test_hash_set_and_reinit():
+    vm = RandomXVM(FLAGS, RandomXCache(FLAGS, b"test key 000"))
+    hashes = vm.calculate_hash_set([data for _, data, _ in VECTORS[:3]])
+    assert [hash.hex() for hash in hashes] == [expected for _, _, expected in VECTORS[:3]]
+
+    vm.reinit_cache(RandomXCache(FLAGS, b"test key 001"))
+    key, data, expected = VECTORS[3]
+    assert vm.calculate_hash(data).hex() == expected
+
+
+def test_hashing_in_threads():
+    cache = RandomXCache(FLAGS, b"test key 000")
+    results = {}
+
+    def hash_vector(index):
+        # Each thread needs its own VM; the cache is shared.
+        vm = RandomXVM(FLAGS, cache)
+        results[index] = vm.calculate_hash(VECTORS[index][1]).hex()
+
+    threads = [threading.Thread(target=hash_vector, args=(index,)) for index in range(3)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    assert results == {index: VECTORS[index][2] for index in range(3)}
+
+
+def test_hashing_releases_the_gil():
+    cache = RandomXCache(FLAGS, b"test key 000")
+    started = threading.Event()
+    done = threading.Event()
+
+    def hash_vectors():
+        vm = RandomXVM(FLAGS, cache)
+        started.set()
+        # Light-mode hashes take milliseconds each, so the batch runs for far longer than the ticks counted below.
+        vm.calculate_hash_set([data for _, data, _ in VECTORS[:3]] * 8)
+        done.set()
+
+    # With a long switch interval, the GIL only changes hands when a thread releases it: this thread gets to count
+    # while the other one hashes only if hashing releases the GIL. If it does not, the first wait times out but only
+    # returns once the batch is done, so ticks are only counted while `done` is still unset.
+    interval = sys.getswitchinterval()
+    sys.setswitchinterval(10)
+    try:
+        thread = threading.Thread(target=hash_vectors)
+        thread.start()
+        started.wait()
+        ticks = 0
+        while not done.wait(0.001):
+            if not done.is_set():
+                ticks += 1
+        thread.join()
+    finally:
+        sys.setswitchinterval(interval)
+    assert ticks >= 10
+
+
+def test_rekeying_while_hashing():
+    cache = RandomXCache(FLAGS, b"test key 000")
+    _, data, expected = VECTORS[2]
+    hashed = threading.Event()
+    results = []
+
+    def hash_until_stale():
+        vm = RandomXVM(FLAGS, cache)
+        while True:
+            try:
+                results.append(vm.calculate_hash(data).hex())
+            except RandomXError as error:
+                assert "different keys" in str(error)
+                return
+            hashed.set()
+
+    thread = threading.Thread(target=hash_until_stale)
+    thread.start()
+    hashed.wait()
+    # Waits for the hash in progress, which finishes with the old key; the next one fails.
+    cache.init(b"test key 001")
+    thread.join()
+    assert results and set(results) == {expected}
+
+
+def test_flags():
+    flags = RandomXFlag("jit,hard_aes") | RandomXFlag.FLAG_SECURE
+    assert int(flags) == 2 | 8 | 16
+    assert str(flags) == "hard_aes,jit,secure"
+    assert RandomXFlag.FLAG_JIT in flags
+    assert RandomXFlag(int(flags)) == flags
+    assert str(RandomXFlag.FLAG_ARGON2) == "argon2"
+    with pytest.raises(ValueError):
+        RandomXFlag("turbo")
+
+
+def test_errors():
+    with pytest.raises(RandomXError, match="key is empty"):
+        RandomXCache(FLAGS, b"")
+    vm = RandomXVM(FLAGS, RandomXCache(FLAGS, b"test key 000"))
+    with pytest.raises(RandomXError, match="input was empty"):
+        vm.calculate_hash(b"")
+    with pytest.raises(TypeError):
+        vm.calculate_hash("not bytes")